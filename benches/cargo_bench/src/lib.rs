@@ -25,6 +25,20 @@ fn bench_blake2b_avx2_one_mb(b: &mut Bencher) {
     b.iter(|| blake2b(MB));
 }
 
+#[bench]
+fn bench_blake2b_exact_one_block(b: &mut Bencher) {
+    b.bytes = BLOCK.len() as u64;
+    let params = Params::new();
+    b.iter(|| blake2b_exact(&params, BLOCK));
+}
+
+#[bench]
+fn bench_blake2b_hash4_exact_one_block(b: &mut Bencher) {
+    b.bytes = 4 * BLOCK.len() as u64;
+    let params = Params::new();
+    b.iter(|| hash4_exact(&params, BLOCK, BLOCK, BLOCK, BLOCK));
+}
+
 #[bench]
 fn bench_blake2b_portable_one_block(b: &mut Bencher) {
     b.bytes = BLOCK.len() as u64;
@@ -53,6 +67,28 @@ fn bench_blake2bp_one_mb(b: &mut Bencher) {
     b.iter(|| blake2bp::blake2bp(MB));
 }
 
+#[bench]
+fn bench_blake2b_update2_one_block(b: &mut Bencher) {
+    b.bytes = 2 * BLOCK.len() as u64;
+    b.iter(|| {
+        let mut state0 = State::new();
+        let mut state1 = State::new();
+        update2(&mut state0, &mut state1, BLOCK, BLOCK);
+        finalize2(&mut state0, &mut state1)
+    });
+}
+
+#[bench]
+fn bench_blake2b_update2_one_mb(b: &mut Bencher) {
+    b.bytes = 2 * MB.len() as u64;
+    b.iter(|| {
+        let mut state0 = State::new();
+        let mut state1 = State::new();
+        update2(&mut state0, &mut state1, MB, MB);
+        finalize2(&mut state0, &mut state1)
+    });
+}
+
 #[bench]
 fn bench_blake2b_update4_one_block(b: &mut Bencher) {
     b.bytes = 4 * BLOCK.len() as u64;
@@ -97,6 +133,48 @@ fn bench_blake2b_update4_one_mb(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn bench_blake2b_update8_one_block(b: &mut Bencher) {
+    b.bytes = 8 * BLOCK.len() as u64;
+    b.iter(|| {
+        let mut states = [
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+        ];
+        let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+        update8([s0, s1, s2, s3, s4, s5, s6, s7], [&BLOCK[..]; 8]);
+        let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+        finalize8([s0, s1, s2, s3, s4, s5, s6, s7])
+    });
+}
+
+#[bench]
+fn bench_blake2b_update8_one_mb(b: &mut Bencher) {
+    b.bytes = 8 * MB.len() as u64;
+    b.iter(|| {
+        let mut states = [
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+            State::new(),
+        ];
+        let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+        update8([s0, s1, s2, s3, s4, s5, s6, s7], [&MB[..]; 8]);
+        let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+        finalize8([s0, s1, s2, s3, s4, s5, s6, s7])
+    });
+}
+
 #[cfg(feature = "libsodium-ffi")]
 #[bench]
 fn bench_libsodium_one_mb(b: &mut Bencher) {
@@ -0,0 +1,833 @@
+//! An implementation of the Argon2 password hash, version 0x13, as specified in
+//! [RFC 9106](https://www.rfc-editor.org/rfc/rfc9106.html).
+//!
+//! Argon2 uses BLAKE2b in two places. The inputs are hashed with BLAKE2b into a 64-byte seed, and
+//! the variable-length hash `H'`, which is [`blake2b_long`], stretches that seed into the first
+//! blocks of memory and compresses the last blocks into the output. The memory-hard part fills the
+//! rest of the memory with a block function built on the BlaMka permutation, which is the BLAKE2b
+//! round with a multiplication added to each addition. That permutation uses AVX2 when it's
+//! available, like the rest of this crate. All three variants are supported: Argon2d, with
+//! data-dependent memory access, Argon2i, with data-independent access, and the recommended hybrid
+//! Argon2id.
+//!
+//! This module is only available with the `argon2` Cargo feature, which also enables `std`. Note
+//! that lanes are computed one after another, so the parallelism parameter changes the output but
+//! not the running time.
+//!
+//! Hashes can be stored as [PHC strings], like
+//! `$argon2id$v=19$m=65536,t=3,p=4$c29tZXNhbHRzb21lc2FsdA$...`, which record the variant, the
+//! costs, and the salt along with the hash. The secret and the associated data aren't recorded,
+//! so the same ones need to be provided again to verify.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::argon2;
+//!
+//! let salt = b"a random salt, at least 8 bytes";
+//! let encoded = argon2::Params::new()
+//!     .memory_cost(64)
+//!     .time_cost(1)
+//!     .parallelism(1)
+//!     .hash_encoded(b"hunter2", salt);
+//! assert!(encoded.starts_with("$argon2id$v=19$m=64,t=1,p=1$"));
+//!
+//! assert!(argon2::verify_encoded(&encoded, b"hunter2").is_ok());
+//! assert_eq!(
+//!     Err(argon2::Error::PasswordMismatch),
+//!     argon2::verify_encoded(&encoded, b"hunter3"),
+//! );
+//! ```
+//!
+//! [`blake2b_long`]: ../fn.blake2b_long.html
+//! [PHC strings]: https://github.com/P-H-C/phc-string-format/blob/master/phc-sf-spec.md
+
+use crate::blake2b_long;
+use crate::guts;
+use crate::State as Blake2bState;
+use byteorder::{ByteOrder, LittleEndian};
+use core::fmt;
+
+use std;
+use std::string::String;
+use std::vec::Vec;
+
+/// The Argon2 version implemented here, 0x13.
+pub const VERSION: u32 = 0x13;
+
+/// The shortest salt that Argon2 allows.
+pub const MIN_SALT_LENGTH: usize = 8;
+
+/// The shortest hash that Argon2 allows.
+pub const MIN_HASH_LENGTH: usize = 4;
+
+/// The largest parallelism that Argon2 allows, `2^24 - 1`.
+pub const MAX_PARALLELISM: u32 = (1 << 24) - 1;
+
+const BLOCK_WORDS: usize = 128;
+const BLOCK_BYTES: usize = 8 * BLOCK_WORDS;
+const SYNC_POINTS: u32 = 4;
+const ADDRESSES_PER_BLOCK: u32 = BLOCK_WORDS as u32;
+
+type Block = [u64; BLOCK_WORDS];
+
+/// The three Argon2 variants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// Data-dependent memory access. The fastest and the most resistant to GPU cracking, but
+    /// vulnerable to side channels.
+    Argon2d,
+    /// Data-independent memory access, resistant to side channels.
+    Argon2i,
+    /// Argon2i for the first half of the first pass, and Argon2d after that. This is the
+    /// recommended variant.
+    Argon2id,
+}
+
+impl Variant {
+    fn type_id(self) -> u32 {
+        match self {
+            Variant::Argon2d => 0,
+            Variant::Argon2i => 1,
+            Variant::Argon2id => 2,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Variant::Argon2d => "argon2d",
+            Variant::Argon2i => "argon2i",
+            Variant::Argon2id => "argon2id",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "argon2d" => Some(Variant::Argon2d),
+            "argon2i" => Some(Variant::Argon2i),
+            "argon2id" => Some(Variant::Argon2id),
+            _ => None,
+        }
+    }
+}
+
+/// A parameter builder for Argon2.
+///
+/// The defaults are the second recommended option from RFC 9106: Argon2id with 64 MiB of memory,
+/// 3 passes, 4 lanes, and a 32-byte hash.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::argon2::{Params, Variant};
+///
+/// let hash = Params::new()
+///     .variant(Variant::Argon2i)
+///     .memory_cost(256)
+///     .time_cost(2)
+///     .parallelism(2)
+///     .hash_length(16)
+///     .secret(b"pepper")
+///     .associated_data(b"user 42")
+///     .hash(b"password", b"saltsaltsalt");
+/// assert_eq!(16, hash.len());
+/// ```
+#[derive(Clone)]
+pub struct Params {
+    variant: Variant,
+    hash_length: u32,
+    memory_cost: u32,
+    time_cost: u32,
+    parallelism: u32,
+    secret: Vec<u8>,
+    associated_data: Vec<u8>,
+}
+
+impl Params {
+    /// Equivalent to `Params::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash a password with a salt, and return a hash of `hash_length` bytes. The salt must be at
+    /// least `MIN_SALT_LENGTH` (8) bytes, and should be random.
+    pub fn hash(&self, password: &[u8], salt: &[u8]) -> Vec<u8> {
+        let mut output = vec![0; self.hash_length as usize];
+        self.hash_with_implementation(guts::Implementation::detect(), password, salt, &mut output);
+        output
+    }
+
+    /// Hash a password with a salt, and return the result as a PHC string.
+    pub fn hash_encoded(&self, password: &[u8], salt: &[u8]) -> String {
+        let hash = self.hash(password, salt);
+        let mut encoded = format!(
+            "${}$v={}$m={},t={},p={}$",
+            self.variant.name(),
+            VERSION,
+            self.memory_cost,
+            self.time_cost,
+            self.parallelism,
+        );
+        base64_encode(salt, &mut encoded);
+        encoded.push('$');
+        base64_encode(&hash, &mut encoded);
+        encoded
+    }
+
+    /// Verify a password against a PHC string. The variant, the costs, and the hash length come
+    /// from the string, and the secret and the associated data come from these parameters.
+    /// Comparing the hash is constant time.
+    pub fn verify_encoded(&self, encoded: &str, password: &[u8]) -> Result<(), Error> {
+        let decoded = decode(encoded).ok_or(Error::InvalidEncoding)?;
+        let mut params = self.clone();
+        params
+            .variant(decoded.variant)
+            .memory_cost(decoded.memory_cost)
+            .time_cost(decoded.time_cost)
+            .parallelism(decoded.parallelism)
+            .hash_length(decoded.hash.len());
+        let hash = params.hash(password, &decoded.salt);
+        if constant_time_eq::constant_time_eq(&hash, &decoded.hash) {
+            Ok(())
+        } else {
+            Err(Error::PasswordMismatch)
+        }
+    }
+
+    /// Argon2d, Argon2i, or Argon2id. The default is Argon2id.
+    pub fn variant(&mut self, variant: Variant) -> &mut Self {
+        self.variant = variant;
+        self
+    }
+
+    /// At least `MIN_HASH_LENGTH` (4) bytes. The default is 32. Note that the length is
+    /// associated data, so a 16-byte hash is *not* a prefix of a 32-byte hash.
+    pub fn hash_length(&mut self, length: usize) -> &mut Self {
+        assert!(
+            MIN_HASH_LENGTH <= length && length <= u32::MAX as usize,
+            "Bad hash length: {}",
+            length
+        );
+        self.hash_length = length as u32;
+        self
+    }
+
+    /// The memory cost in KiB. This must be at least 8 times the parallelism, and it's rounded
+    /// down to a multiple of 4 times the parallelism. The default is 65536 (64 MiB).
+    pub fn memory_cost(&mut self, kib: u32) -> &mut Self {
+        assert!(kib >= 2 * SYNC_POINTS, "Bad memory cost: {}", kib);
+        self.memory_cost = kib;
+        self
+    }
+
+    /// The number of passes over the memory, at least 1. The default is 3.
+    pub fn time_cost(&mut self, passes: u32) -> &mut Self {
+        assert!(passes >= 1, "Bad time cost: {}", passes);
+        self.time_cost = passes;
+        self
+    }
+
+    /// The number of lanes, between 1 and `MAX_PARALLELISM`. The default is 4.
+    pub fn parallelism(&mut self, lanes: u32) -> &mut Self {
+        assert!(
+            (1..=MAX_PARALLELISM).contains(&lanes),
+            "Bad parallelism: {}",
+            lanes
+        );
+        self.parallelism = lanes;
+        self
+    }
+
+    /// An optional secret, sometimes called a pepper, which is stored apart from the hashes. An
+    /// empty secret is equivalent to having no secret at all.
+    pub fn secret(&mut self, secret: &[u8]) -> &mut Self {
+        self.secret = secret.to_vec();
+        self
+    }
+
+    /// Optional associated data. An empty slice is equivalent to having no associated data at all.
+    pub fn associated_data(&mut self, associated_data: &[u8]) -> &mut Self {
+        self.associated_data = associated_data.to_vec();
+        self
+    }
+
+    fn hash_with_implementation(
+        &self,
+        implementation: guts::Implementation,
+        password: &[u8],
+        salt: &[u8],
+        output: &mut [u8],
+    ) {
+        assert!(
+            salt.len() >= MIN_SALT_LENGTH,
+            "Bad salt length: {}",
+            salt.len()
+        );
+        let lanes = self.parallelism;
+        assert!(
+            self.memory_cost >= 2 * SYNC_POINTS * lanes,
+            "Bad memory cost: {}",
+            self.memory_cost
+        );
+        debug_assert_eq!(self.hash_length as usize, output.len());
+
+        // Hash the parameters and all the inputs into the 64-byte seed, H0.
+        let mut state = Blake2bState::new();
+        for &word in &[
+            lanes,
+            self.hash_length,
+            self.memory_cost,
+            self.time_cost,
+            VERSION,
+            self.variant.type_id(),
+        ] {
+            state.update(&le32(word));
+        }
+        for input in &[password, salt, &self.secret[..], &self.associated_data[..]] {
+            state.update(&le32(input.len() as u32));
+            state.update(input);
+        }
+        let seed = state.finalize();
+
+        let segment_length = self.memory_cost / (SYNC_POINTS * lanes);
+        let mut memory = Memory {
+            implementation,
+            blocks: vec![[0; BLOCK_WORDS]; (segment_length * SYNC_POINTS * lanes) as usize],
+            variant: self.variant,
+            time_cost: self.time_cost,
+            lanes,
+            segment_length,
+            lane_length: segment_length * SYNC_POINTS,
+        };
+
+        // The first two blocks of each lane come straight from the seed.
+        let mut block_bytes = [0; BLOCK_BYTES];
+        for lane in 0..lanes {
+            for column in 0..2 {
+                blake2b_long(
+                    &mut block_bytes,
+                    &[seed.as_bytes(), &le32(column), &le32(lane)],
+                );
+                let index = memory.index(lane, column);
+                LittleEndian::read_u64_into(&block_bytes, &mut memory.blocks[index]);
+            }
+        }
+
+        for pass in 0..self.time_cost {
+            for slice in 0..SYNC_POINTS {
+                for lane in 0..lanes {
+                    memory.fill_segment(pass, slice, lane);
+                }
+            }
+        }
+
+        // XOR together the last block of each lane, and hash that into the output.
+        let mut last = [0; BLOCK_WORDS];
+        for lane in 0..lanes {
+            let index = memory.index(lane, memory.lane_length - 1);
+            xor_into(&mut last, &memory.blocks[index]);
+        }
+        LittleEndian::write_u64_into(&last, &mut block_bytes);
+        blake2b_long(output, &[&block_bytes]);
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            variant: Variant::Argon2id,
+            hash_length: 32,
+            memory_cost: 1 << 16,
+            time_cost: 3,
+            parallelism: 4,
+            secret: Vec::new(),
+            associated_data: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Params {{ variant: {:?}, hash_length: {}, memory_cost: {}, time_cost: {}, \
+             parallelism: {}, secret_length: {}, associated_data: {:?} }}",
+            self.variant,
+            self.hash_length,
+            self.memory_cost,
+            self.time_cost,
+            self.parallelism,
+            // NB: Don't print the secret itself. Debug shouldn't leak secrets.
+            self.secret.len(),
+            &self.associated_data,
+        )
+    }
+}
+
+/// Verify a password against a PHC string, with no secret and no associated data. See
+/// [`Params::verify_encoded`].
+///
+/// [`Params::verify_encoded`]: struct.Params.html#method.verify_encoded
+pub fn verify_encoded(encoded: &str, password: &[u8]) -> Result<(), Error> {
+    Params::new().verify_encoded(encoded, password)
+}
+
+/// The error returned when verifying a PHC string fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The string isn't a valid Argon2 version 0x13 PHC string.
+    InvalidEncoding,
+    /// The password doesn't match the hash.
+    PasswordMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidEncoding => write!(f, "invalid Argon2 PHC string"),
+            Error::PasswordMismatch => write!(f, "password mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn le32(x: u32) -> [u8; 4] {
+    let mut bytes = [0; 4];
+    LittleEndian::write_u32(&mut bytes, x);
+    bytes
+}
+
+fn xor_into(out: &mut Block, other: &Block) {
+    for (word, &other_word) in out.iter_mut().zip(other.iter()) {
+        *word ^= other_word;
+    }
+}
+
+struct Memory {
+    implementation: guts::Implementation,
+    blocks: Vec<Block>,
+    variant: Variant,
+    time_cost: u32,
+    lanes: u32,
+    segment_length: u32,
+    lane_length: u32,
+}
+
+impl Memory {
+    fn index(&self, lane: u32, column: u32) -> usize {
+        (lane * self.lane_length + column) as usize
+    }
+
+    // The block function G. The rows of the 8x8 matrix of 16-byte registers are 16 consecutive
+    // words, and the columns are pairs of words 16 apart.
+    fn compress(&self, x: &Block, y: &Block) -> Block {
+        let mut r = *x;
+        xor_into(&mut r, y);
+        let mut q = r;
+        for row in q.chunks_mut(16) {
+            self.implementation.blamka_round(array_mut_ref!(row, 0, 16));
+        }
+        for column in 0..8 {
+            let mut v = [0; 16];
+            for (i, pair) in v.chunks_mut(2).enumerate() {
+                pair.copy_from_slice(&q[16 * i + 2 * column..][..2]);
+            }
+            self.implementation.blamka_round(&mut v);
+            for (i, pair) in v.chunks(2).enumerate() {
+                q[16 * i + 2 * column..][..2].copy_from_slice(pair);
+            }
+        }
+        xor_into(&mut q, &r);
+        q
+    }
+
+    // Generate the next block of pseudo-random addresses for data-independent addressing.
+    fn next_addresses(&self, input: &mut Block) -> Block {
+        input[6] += 1;
+        let zero = [0; BLOCK_WORDS];
+        let tmp = self.compress(&zero, input);
+        self.compress(&zero, &tmp)
+    }
+
+    // The index of the reference block within its lane, from section 3.4.2 of the RFC.
+    fn reference_column(
+        &self,
+        pass: u32,
+        slice: u32,
+        index: u32,
+        same_lane: bool,
+        pseudo_rand: u32,
+    ) -> u32 {
+        // Blocks in the current slice of other lanes might not be done yet, so they're excluded.
+        // So is the previous block, which is always an input already.
+        let finished = if pass == 0 {
+            slice * self.segment_length
+        } else {
+            self.lane_length - self.segment_length
+        };
+        let area_size = if same_lane {
+            finished + index - 1
+        } else if index == 0 {
+            finished - 1
+        } else {
+            finished
+        };
+        let x = (pseudo_rand as u64 * pseudo_rand as u64) >> 32;
+        let y = (area_size as u64 * x) >> 32;
+        let relative_position = area_size - 1 - y as u32;
+        let start = if pass == 0 || slice == SYNC_POINTS - 1 {
+            0
+        } else {
+            (slice + 1) * self.segment_length
+        };
+        (start + relative_position) % self.lane_length
+    }
+
+    fn fill_segment(&mut self, pass: u32, slice: u32, lane: u32) {
+        let data_independent = match self.variant {
+            Variant::Argon2d => false,
+            Variant::Argon2i => true,
+            Variant::Argon2id => pass == 0 && slice < SYNC_POINTS / 2,
+        };
+        let mut address_input = [0; BLOCK_WORDS];
+        let mut addresses = [0; BLOCK_WORDS];
+        if data_independent {
+            address_input[0] = pass as u64;
+            address_input[1] = lane as u64;
+            address_input[2] = slice as u64;
+            address_input[3] = self.blocks.len() as u64;
+            address_input[4] = self.time_cost as u64;
+            address_input[5] = self.variant.type_id() as u64;
+        }
+        let first_index = if pass == 0 && slice == 0 {
+            // The first two blocks are already filled.
+            if data_independent {
+                addresses = self.next_addresses(&mut address_input);
+            }
+            2
+        } else {
+            0
+        };
+        for index in first_index..self.segment_length {
+            let column = slice * self.segment_length + index;
+            let current = self.index(lane, column);
+            let previous = if column == 0 {
+                self.index(lane, self.lane_length - 1)
+            } else {
+                current - 1
+            };
+            let pseudo_rand = if data_independent {
+                if index % ADDRESSES_PER_BLOCK == 0 {
+                    addresses = self.next_addresses(&mut address_input);
+                }
+                addresses[(index % ADDRESSES_PER_BLOCK) as usize]
+            } else {
+                self.blocks[previous][0]
+            };
+            let reference_lane = if pass == 0 && slice == 0 {
+                lane
+            } else {
+                ((pseudo_rand >> 32) % self.lanes as u64) as u32
+            };
+            let reference_column = self.reference_column(
+                pass,
+                slice,
+                index,
+                reference_lane == lane,
+                pseudo_rand as u32,
+            );
+            let reference = self.index(reference_lane, reference_column);
+            let new_block = self.compress(&self.blocks[previous], &self.blocks[reference]);
+            if pass == 0 {
+                self.blocks[current] = new_block;
+            } else {
+                // Version 0x13 XORs the new block into the old one on later passes.
+                xor_into(&mut self.blocks[current], &new_block);
+            }
+        }
+    }
+}
+
+struct Decoded {
+    variant: Variant,
+    memory_cost: u32,
+    time_cost: u32,
+    parallelism: u32,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+// Parse `$argon2id$v=19$m=65536,t=3,p=4$<salt>$<hash>`. Anything out of range is an error, rather
+// than a panic in the Params builder.
+fn decode(encoded: &str) -> Option<Decoded> {
+    let mut fields = encoded.split('$');
+    if !fields.next()?.is_empty() {
+        return None;
+    }
+    let variant = Variant::from_name(fields.next()?)?;
+    if parse_field(fields.next()?, "v=")? != VERSION {
+        return None;
+    }
+    let mut costs = fields.next()?.split(',');
+    let memory_cost = parse_field(costs.next()?, "m=")?;
+    let time_cost = parse_field(costs.next()?, "t=")?;
+    let parallelism = parse_field(costs.next()?, "p=")?;
+    let salt = base64_decode(fields.next()?)?;
+    let hash = base64_decode(fields.next()?)?;
+    if costs.next().is_some() || fields.next().is_some() {
+        return None;
+    }
+    let valid = time_cost >= 1
+        && (1..=MAX_PARALLELISM).contains(&parallelism)
+        && memory_cost as u64 >= 2 * SYNC_POINTS as u64 * parallelism as u64
+        && salt.len() >= MIN_SALT_LENGTH
+        && hash.len() >= MIN_HASH_LENGTH
+        && hash.len() <= u32::MAX as usize;
+    if !valid {
+        return None;
+    }
+    Some(Decoded {
+        variant,
+        memory_cost,
+        time_cost,
+        parallelism,
+        salt,
+        hash,
+    })
+}
+
+// Parse a field like "m=65536". Only plain decimal digits are allowed, with no sign.
+fn parse_field(field: &str, prefix: &str) -> Option<u32> {
+    if !field.starts_with(prefix) {
+        return None;
+    }
+    let digits = &field[prefix.len()..];
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+const BASE64_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard base64 with no padding, as PHC strings use.
+fn base64_encode(bytes: &[u8], out: &mut String) {
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bits = (group[0] as u32) << 16 | (group[1] as u32) << 8 | group[2] as u32;
+        for i in 0..chunk.len() + 1 {
+            out.push(BASE64_TABLE[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+}
+
+fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    if encoded.len() % 4 == 1 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    for chunk in encoded.as_bytes().chunks(4) {
+        let mut bits = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = BASE64_TABLE.iter().position(|&t| t == c)? as u32;
+            bits |= value << (18 - 6 * i);
+        }
+        let group = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+        let len = chunk.len() - 1;
+        // Reject leftover bits, so that every hash has only one encoding.
+        if group[len..].iter().any(|&b| b != 0) {
+            return None;
+        }
+        bytes.extend_from_slice(&group[..len]);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The test vectors from section 5 of RFC 9106.
+    fn rfc_params(variant: Variant) -> Params {
+        let mut params = Params::new();
+        params
+            .variant(variant)
+            .memory_cost(32)
+            .time_cost(3)
+            .parallelism(4)
+            .hash_length(32)
+            .secret(&[3; 8])
+            .associated_data(&[4; 12]);
+        params
+    }
+
+    fn check_rfc_vector(variant: Variant, expected: &str) {
+        let params = rfc_params(variant);
+        let password = [1; 32];
+        let salt = [2; 16];
+        assert_eq!(expected, hex::encode(params.hash(&password, &salt)));
+        // Also check the portable implementation of the permutation, in case the default is AVX2.
+        let mut output = [0; 32];
+        params.hash_with_implementation(
+            guts::Implementation::portable(),
+            &password,
+            &salt,
+            &mut output,
+        );
+        assert_eq!(expected, hex::encode(output));
+    }
+
+    #[test]
+    fn test_rfc_argon2d() {
+        check_rfc_vector(
+            Variant::Argon2d,
+            "512b391b6f1162975371d30919734294f868e3be3984f3c1a13a4db9fabe4acb",
+        );
+    }
+
+    #[test]
+    fn test_rfc_argon2i() {
+        check_rfc_vector(
+            Variant::Argon2i,
+            "c814d9d1dc7f37aa13f0d77f2494bda1c8de6b016dd388d29952a4c4672b6ce8",
+        );
+    }
+
+    #[test]
+    fn test_rfc_argon2id() {
+        check_rfc_vector(
+            Variant::Argon2id,
+            "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659",
+        );
+    }
+
+    // These were generated by libsodium's crypto_pwhash. With 1 MiB of memory, each segment is
+    // longer than one block of addresses.
+    #[test]
+    fn test_libsodium_hashes() {
+        for &(variant, time_cost, expected) in &[
+            (
+                Variant::Argon2i,
+                3,
+                "1e88f68edcfe8cec557f89c06b1c51f0f35725f012afcf2cc39efaa98b7e9daf",
+            ),
+            (
+                Variant::Argon2id,
+                2,
+                "f8e093093a6b41c49804170f32e87602ab619e9a686a14e91f4d8bf1e60cea0b",
+            ),
+        ] {
+            let hash = Params::new()
+                .variant(variant)
+                .memory_cost(1024)
+                .time_cost(time_cost)
+                .parallelism(1)
+                .hash(b"password", b"saltsaltsaltsalt");
+            assert_eq!(expected, hex::encode(hash));
+        }
+    }
+
+    // These were generated by libsodium's crypto_pwhash_str functions.
+    #[test]
+    fn test_libsodium_strings() {
+        let argon2id = "$argon2id$v=19$m=64,t=2,p=1$8KcB+5yJITWreBdrAiDBog$+BNQEk1Si1miG/KFVsVnaFQkY9KDB4vEIAuqTO19d6U";
+        assert_eq!(Ok(()), verify_encoded(argon2id, b"correct horse"));
+        assert_eq!(
+            Err(Error::PasswordMismatch),
+            verify_encoded(argon2id, b"battery staple")
+        );
+        let argon2i = "$argon2i$v=19$m=64,t=3,p=1$ZTBQkeO2IGPwYuUSUjobOQ$dDqH13H035klj1DSbfGkAYyrcRrd25noZHz4Orllq3s";
+        assert_eq!(Ok(()), verify_encoded(argon2i, b"correct horse"));
+        assert_eq!(
+            Err(Error::PasswordMismatch),
+            verify_encoded(argon2i, b"battery staple")
+        );
+    }
+
+    #[test]
+    fn test_encoded_round_trip() {
+        for &variant in &[Variant::Argon2d, Variant::Argon2i, Variant::Argon2id] {
+            let mut params = Params::new();
+            params
+                .variant(variant)
+                .memory_cost(40)
+                .time_cost(2)
+                .parallelism(3)
+                .hash_length(17)
+                .secret(b"secret");
+            let encoded = params.hash_encoded(b"password", b"saltysalt");
+            assert!(encoded.starts_with(&format!("${}$v=19$m=40,t=2,p=3$", variant.name())));
+            assert_eq!(Ok(()), params.verify_encoded(&encoded, b"password"));
+            assert_eq!(
+                Err(Error::PasswordMismatch),
+                params.verify_encoded(&encoded, b"passwore")
+            );
+            // Without the secret, verification fails.
+            assert_eq!(
+                Err(Error::PasswordMismatch),
+                verify_encoded(&encoded, b"password")
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_encodings() {
+        let good = "$argon2id$v=19$m=64,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA";
+        assert_eq!(Err(Error::PasswordMismatch), verify_encoded(good, b""));
+        for bad in &[
+            "",
+            "argon2id$v=19$m=64,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2x$v=19$m=64,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=16$m=64,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$m=64,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=64,t=0,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=7,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=64,t=2,p=9$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=64,t=+2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$t=2,m=64,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=64,t=2,p=1,x=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=64,t=2,p=1$c2FsdA$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=64,t=2,p=1$c29tZXNhbHQ$wWK",
+            "$argon2id$v=19$m=64,t=2,p=1$c29tZXNhbHQ=$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=64,t=2,p=1$c29tZXNhbHR$wWKIMhR9lyDFvRz9YTZweA",
+            "$argon2id$v=19$m=64,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweA$",
+        ] {
+            assert_eq!(
+                Err(Error::InvalidEncoding),
+                verify_encoded(bad, b""),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_base64() {
+        for len in 0..10 {
+            let bytes: Vec<u8> = (0..len as u8).map(|b| b.wrapping_mul(97)).collect();
+            let mut encoded = String::new();
+            base64_encode(&bytes, &mut encoded);
+            assert_eq!(Some(bytes), base64_decode(&encoded));
+        }
+        let mut encoded = String::new();
+        base64_encode(b"somesalt", &mut encoded);
+        assert_eq!("c29tZXNhbHQ", encoded);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_short_salt_panics() {
+        Params::new().memory_cost(32).hash(b"password", b"short");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_low_memory_for_parallelism_panics() {
+        Params::new()
+            .memory_cost(31)
+            .parallelism(4)
+            .hash(b"password", b"saltsalt");
+    }
+}
@@ -0,0 +1,343 @@
+//! ASN.1 object identifiers and DER `DigestInfo` encoding for BLAKE2b, for RSA PKCS#1 v1.5
+//! signatures and X.509 tooling.
+//!
+//! [RFC 7693] assigns OIDs to BLAKE2b with 160, 256, 384, and 512-bit outputs. They're
+//! `1.3.6.1.4.1.1722.12.2.1` followed by the output length in 32-bit words, so the OID for a
+//! 32-byte hash ends in 8. Other hash lengths don't have an OID. [`encoded_oid`] and
+//! [`hash_length_from_oid`] convert between these OIDs and the value of
+//! [`Params::hash_length`].
+//!
+//! RSA PKCS#1 v1.5 signatures ([RFC 8017]) sign a DER-encoded `DigestInfo`:
+//!
+//! ```text
+//! DigestInfo ::= SEQUENCE {
+//!     digestAlgorithm AlgorithmIdentifier,
+//!     digest OCTET STRING
+//! }
+//! ```
+//!
+//! [`Hash::to_digest_info`] produces one, with NULL algorithm parameters, as PKCS#1 uses for the
+//! SHA-2 family. [`Hash::from_digest_info`] parses one, and it also accepts absent parameters.
+//! This is a small hand-written DER encoder and parser for just this structure, rather than a
+//! general ASN.1 library.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::{asn1, Hash, Params};
+//!
+//! let hash = Params::new().hash_length(32).to_state().update(b"foo").finalize();
+//! let digest_info = hash.to_digest_info();
+//! assert_eq!(hash, Hash::from_digest_info(&digest_info).unwrap());
+//! assert_eq!(Some(32), asn1::hash_length_from_oid(&digest_info[4..17]));
+//! ```
+//!
+//! [RFC 7693]: https://tools.ietf.org/html/rfc7693#section-4
+//! [RFC 8017]: https://tools.ietf.org/html/rfc8017#section-9.2
+//! [`encoded_oid`]: fn.encoded_oid.html
+//! [`hash_length_from_oid`]: fn.hash_length_from_oid.html
+//! [`Params::hash_length`]: ../struct.Params.html#method.hash_length
+//! [`Hash::to_digest_info`]: ../struct.Hash.html#method.to_digest_info
+//! [`Hash::from_digest_info`]: ../struct.Hash.html#method.from_digest_info
+
+use crate::Hash;
+use arrayvec::ArrayVec;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std;
+
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+
+// The encoded arcs of 1.3.6.1.4.1.1722.12.2.1. The first two arcs share a byte, and 1722 takes
+// two bytes in base 128.
+const OID_PREFIX: [u8; 10] = [0x2b, 0x06, 0x01, 0x04, 0x01, 0x8d, 0x3a, 0x0c, 0x02, 0x01];
+
+/// The length of a DER-encoded BLAKE2b OID, including the tag and length bytes.
+pub const OID_BYTES: usize = 2 + 10 + 1;
+
+/// The hash lengths that have OIDs: 20, 32, 48, and 64 bytes.
+pub const HASH_LENGTHS: [usize; 4] = [20, 32, 48, 64];
+
+// The longest DigestInfo is 85 bytes, which rounds up to a size that arrayvec supports.
+type DigestInfoBytes = ArrayVec<[u8; 96]>;
+
+/// Return the DER encoding of the OID for BLAKE2b with the given hash length, including the tag
+/// and length bytes, or `None` if the length isn't one of [`HASH_LENGTHS`].
+///
+/// [`HASH_LENGTHS`]: constant.HASH_LENGTHS.html
+pub fn encoded_oid(hash_length: usize) -> Option<[u8; OID_BYTES]> {
+    if !HASH_LENGTHS.contains(&hash_length) {
+        return None;
+    }
+    let mut oid = [0; OID_BYTES];
+    oid[0] = TAG_OID;
+    oid[1] = (OID_BYTES - 2) as u8;
+    oid[2..OID_BYTES - 1].copy_from_slice(&OID_PREFIX);
+    oid[OID_BYTES - 1] = (hash_length / 4) as u8;
+    Some(oid)
+}
+
+/// Return the hash length for a DER-encoded BLAKE2b OID, including the tag and length bytes, or
+/// `None` if it isn't one of the BLAKE2b OIDs. The result can be passed to
+/// [`Params::hash_length`].
+///
+/// [`Params::hash_length`]: ../struct.Params.html#method.hash_length
+pub fn hash_length_from_oid(oid: &[u8]) -> Option<usize> {
+    if oid.len() != OID_BYTES
+        || oid[..2] != [TAG_OID, (OID_BYTES - 2) as u8]
+        || oid[2..OID_BYTES - 1] != OID_PREFIX
+    {
+        return None;
+    }
+    let hash_length = oid[OID_BYTES - 1] as usize * 4;
+    if HASH_LENGTHS.contains(&hash_length) {
+        Some(hash_length)
+    } else {
+        None
+    }
+}
+
+// Read one element with the given tag, and return its contents. Every length in a BLAKE2b
+// DigestInfo is less than 128, and DER requires those to use the one-byte form.
+fn read_element<'a>(input: &mut &'a [u8], tag: u8) -> Result<&'a [u8], Error> {
+    if input.len() < 2 || input[0] != tag || input[1] >= 0x80 {
+        return Err(Error::InvalidDer);
+    }
+    let len = input[1] as usize;
+    if input.len() - 2 < len {
+        return Err(Error::InvalidDer);
+    }
+    let (contents, rest) = input[2..].split_at(len);
+    *input = rest;
+    Ok(contents)
+}
+
+impl Hash {
+    /// Encode the hash as a DER `DigestInfo`, with the BLAKE2b OID for its length and NULL
+    /// parameters. See the [`asn1`] module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the hash length isn't 20, 32, 48, or 64 bytes, since other lengths don't have an
+    /// OID.
+    ///
+    /// [`asn1`]: asn1/index.html
+    pub fn to_digest_info(&self) -> DigestInfoBytes {
+        let digest = self.as_bytes();
+        let oid = match encoded_oid(digest.len()) {
+            Some(oid) => oid,
+            None => panic!("Bad hash length: {}", digest.len()),
+        };
+        let algorithm_len = OID_BYTES + 2;
+        let mut der = ArrayVec::new();
+        der.push(TAG_SEQUENCE);
+        der.push((2 + algorithm_len + 2 + digest.len()) as u8);
+        der.push(TAG_SEQUENCE);
+        der.push(algorithm_len as u8);
+        der.extend(oid.iter().cloned());
+        der.push(TAG_NULL);
+        der.push(0);
+        der.push(TAG_OCTET_STRING);
+        der.push(digest.len() as u8);
+        der.extend(digest.iter().cloned());
+        der
+    }
+
+    /// Parse a DER `DigestInfo` with a BLAKE2b OID, as produced by [`to_digest_info`]. The
+    /// algorithm parameters can be NULL or absent. The length of the returned hash is the one
+    /// that the OID gives.
+    ///
+    /// [`to_digest_info`]: #method.to_digest_info
+    pub fn from_digest_info(der: &[u8]) -> Result<Self, Error> {
+        let mut input = der;
+        let mut digest_info = read_element(&mut input, TAG_SEQUENCE)?;
+        if !input.is_empty() {
+            return Err(Error::InvalidDer);
+        }
+        let mut algorithm = read_element(&mut digest_info, TAG_SEQUENCE)?;
+        let oid_start = algorithm;
+        read_element(&mut algorithm, TAG_OID)?;
+        let oid = &oid_start[..oid_start.len() - algorithm.len()];
+        let hash_length = hash_length_from_oid(oid).ok_or(Error::UnsupportedAlgorithm)?;
+        if !algorithm.is_empty() && algorithm != [TAG_NULL, 0] {
+            return Err(Error::InvalidDer);
+        }
+        let digest = read_element(&mut digest_info, TAG_OCTET_STRING)?;
+        if !digest_info.is_empty() {
+            return Err(Error::InvalidDer);
+        }
+        if digest.len() != hash_length {
+            return Err(Error::InvalidLength);
+        }
+        Ok(Self::from_bytes(digest).expect("the length has an OID"))
+    }
+}
+
+/// The error returned when parsing a `DigestInfo` fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The input isn't a DER `DigestInfo`, or it has extra bytes at the end.
+    InvalidDer,
+    /// The algorithm isn't BLAKE2b with one of the lengths that have an OID.
+    UnsupportedAlgorithm,
+    /// The digest length doesn't match the OID.
+    InvalidLength,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidDer => write!(f, "invalid DER DigestInfo"),
+            Error::UnsupportedAlgorithm => write!(f, "DigestInfo algorithm isn't BLAKE2b"),
+            Error::InvalidLength => write!(f, "DigestInfo digest length doesn't match the OID"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Params;
+
+    // These fixtures are the hash of "abc" at each length, in a DigestInfo generated by
+    // `openssl asn1parse -genconf`, with a config like this:
+    //
+    // asn1 = SEQUENCE:digest_info
+    // [digest_info]
+    // alg = SEQUENCE:alg
+    // digest = FORMAT:HEX,OCTETSTRING:384264f676f39536840523f284921cdc68b6846b
+    // [alg]
+    // oid = OID:1.3.6.1.4.1.1722.12.2.1.5
+    // params = NULL
+    const FIXTURES: &[(usize, &str)] = &[
+        (
+            20,
+            "3027300f060b2b060104018d3a0c02010505000414384264f676f39536840523f284921cdc68b6846b",
+        ),
+        (
+            32,
+            "3033300f060b2b060104018d3a0c02010805000420bddd813c634239723171ef3fee98579b94964e3b\
+             b1cb3e427262c8c068d52319",
+        ),
+        (
+            48,
+            "3043300f060b2b060104018d3a0c02010c050004306f56a82c8e7ef526dfe182eb5212f7db9df1317e\
+             57815dbda46083fc30f54ee6c66ba83be64b302d7cba6ce15bb556f4",
+        ),
+        (
+            64,
+            "3053300f060b2b060104018d3a0c02011005000440ba80a53f981c4d0d6a2797b69f12f6e94c212f14\
+             685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4\
+             009923",
+        ),
+    ];
+
+    // The 32-byte fixture without the NULL parameters.
+    const ABSENT_PARAMETERS: &str =
+        "3031300d060b2b060104018d3a0c0201080420bddd813c634239723171ef3f\
+         ee98579b94964e3bb1cb3e427262c8c068d52319";
+
+    fn hash(hash_length: usize) -> Hash {
+        Params::new()
+            .hash_length(hash_length)
+            .to_state()
+            .update(b"abc")
+            .finalize()
+    }
+
+    #[test]
+    fn test_fixtures() {
+        for &(hash_length, fixture) in FIXTURES {
+            let hash = hash(hash_length);
+            assert_eq!(fixture, hex::encode(hash.to_digest_info()));
+            let der = hex::decode(fixture).unwrap();
+            assert_eq!(hash, Hash::from_digest_info(&der).unwrap());
+            assert_eq!(
+                Some(hash_length),
+                hash_length_from_oid(&der[4..4 + OID_BYTES])
+            );
+            assert_eq!(
+                &der[4..4 + OID_BYTES],
+                &encoded_oid(hash_length).unwrap()[..]
+            );
+        }
+        let der = hex::decode(ABSENT_PARAMETERS).unwrap();
+        assert_eq!(hash(32), Hash::from_digest_info(&der).unwrap());
+    }
+
+    #[test]
+    fn test_oids() {
+        for hash_length in 0..=65 {
+            let oid = encoded_oid(hash_length);
+            assert_eq!(HASH_LENGTHS.contains(&hash_length), oid.is_some());
+            if let Some(oid) = oid {
+                assert_eq!(Some(hash_length), hash_length_from_oid(&oid));
+            }
+        }
+        // The arc for 24 bytes follows the pattern, but RFC 7693 doesn't assign it.
+        let mut oid = encoded_oid(32).unwrap();
+        oid[OID_BYTES - 1] = 6;
+        assert_eq!(None, hash_length_from_oid(&oid));
+        // BLAKE2s-256 is 1.3.6.1.4.1.1722.12.2.2.8.
+        let mut oid = encoded_oid(32).unwrap();
+        oid[OID_BYTES - 2] = 2;
+        assert_eq!(None, hash_length_from_oid(&oid));
+        assert_eq!(None, hash_length_from_oid(&oid[..OID_BYTES - 1]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let good = hex::decode(FIXTURES[1].1).unwrap();
+        let mut trailing = good.clone();
+        trailing.push(0);
+        assert_eq!(Err(Error::InvalidDer), Hash::from_digest_info(&trailing));
+        assert_eq!(
+            Err(Error::InvalidDer),
+            Hash::from_digest_info(&good[..good.len() - 1])
+        );
+        assert_eq!(Err(Error::InvalidDer), Hash::from_digest_info(&[]));
+
+        // The same DigestInfo with SHA-256, from RFC 8017.
+        let sha256 = hex::decode(
+            "3031300d060960864801650304020105000420bddd813c634239723171ef3fee98579b94964e3bb1cb3e\
+             427262c8c068d52319",
+        )
+        .unwrap();
+        assert_eq!(
+            Err(Error::UnsupportedAlgorithm),
+            Hash::from_digest_info(&sha256)
+        );
+
+        // The BLAKE2b-160 OID with a 32-byte digest.
+        let mut mismatched = good.clone();
+        mismatched[16] = 5;
+        assert_eq!(
+            Err(Error::InvalidLength),
+            Hash::from_digest_info(&mismatched)
+        );
+
+        // Parameters other than NULL.
+        let mut parameters = good.clone();
+        parameters[18] = 1;
+        assert_eq!(Err(Error::InvalidDer), Hash::from_digest_info(&parameters));
+
+        // The outer length in the long form, which DER doesn't allow for short lengths.
+        let mut long_form = [0; 54];
+        long_form[..2].copy_from_slice(&[TAG_SEQUENCE, 0x81]);
+        long_form[2..].copy_from_slice(&good[1..]);
+        assert_eq!(Err(Error::InvalidDer), Hash::from_digest_info(&long_form));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_no_oid_panics() {
+        hash(33).to_digest_info();
+    }
+}
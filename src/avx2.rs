@@ -4,7 +4,7 @@ use core::arch::x86::*;
 use core::arch::x86_64::*;
 
 use super::*;
-use crate::guts::u64x4;
+use crate::guts::{u64x4, u64x8};
 use core::mem;
 
 #[inline(always)]
@@ -115,6 +115,52 @@ unsafe fn blake2b_undiag_v1(_a: &mut __m256i, b: &mut __m256i, c: &mut __m256i,
     *b = _mm256_permute4x64_epi64(*b, _MM_SHUFFLE!(2, 1, 0, 3));
 }
 
+// The BlaMka addition from Argon2, a + b + 2 * lo(a) * lo(b).
+#[inline(always)]
+unsafe fn blamka_add(a: __m256i, b: __m256i) -> __m256i {
+    let product = _mm256_mul_epu32(a, b);
+    add(add(a, b), add(product, product))
+}
+
+#[inline(always)]
+unsafe fn blamka_g1(a: &mut __m256i, b: &mut __m256i, c: &mut __m256i, d: &mut __m256i) {
+    *a = blamka_add(*a, *b);
+    *d = xor(*d, *a);
+    *d = rot32(*d);
+    *c = blamka_add(*c, *d);
+    *b = xor(*b, *c);
+    *b = rot24(*b);
+}
+
+#[inline(always)]
+unsafe fn blamka_g2(a: &mut __m256i, b: &mut __m256i, c: &mut __m256i, d: &mut __m256i) {
+    *a = blamka_add(*a, *b);
+    *d = xor(*d, *a);
+    *d = rot16(*d);
+    *c = blamka_add(*c, *d);
+    *b = xor(*b, *c);
+    *b = rot63(*b);
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn blamka_round(v: &mut [u64; 16]) {
+    let (v0, v1, v2, v3) = mut_array_refs!(v, 4, 4, 4, 4);
+    let mut a = load_256_unaligned(v0);
+    let mut b = load_256_unaligned(v1);
+    let mut c = load_256_unaligned(v2);
+    let mut d = load_256_unaligned(v3);
+    blamka_g1(&mut a, &mut b, &mut c, &mut d);
+    blamka_g2(&mut a, &mut b, &mut c, &mut d);
+    blake2b_diag_v1(&mut a, &mut b, &mut c, &mut d);
+    blamka_g1(&mut a, &mut b, &mut c, &mut d);
+    blamka_g2(&mut a, &mut b, &mut c, &mut d);
+    blake2b_undiag_v1(&mut a, &mut b, &mut c, &mut d);
+    store_256_unaligned(v0, a);
+    store_256_unaligned(v1, b);
+    store_256_unaligned(v2, c);
+    store_256_unaligned(v3, d);
+}
+
 #[target_feature(enable = "avx2")]
 pub unsafe fn compress(
     h: &mut StateWords,
@@ -399,6 +445,70 @@ pub unsafe fn compress(
     store_256_unaligned(h_high, b);
 }
 
+#[inline(always)]
+unsafe fn load_msg_words(m: &[u64; 16], s: &[u8; 16], i: usize) -> __m256i {
+    _mm256_set_epi64x(
+        m[s[i + 6] as usize] as i64,
+        m[s[i + 4] as usize] as i64,
+        m[s[i + 2] as usize] as i64,
+        m[s[i] as usize] as i64,
+    )
+}
+
+// The same as compress, but with any number of rounds, like the F function in
+// EIP-152. Since the round count isn't fixed, this loads the message words for
+// each round according to SIGMA, rather than unrolling the rounds and
+// shuffling the message vectors.
+#[target_feature(enable = "avx2")]
+pub unsafe fn compress_rounds(
+    h: &mut StateWords,
+    msg: &Block,
+    count: u128,
+    lastblock: u64,
+    lastnode: u64,
+    rounds: u32,
+) {
+    let (h_low, h_high) = mut_array_refs!(h, 4, 4);
+    let (iv_low, iv_high) = array_refs!(&IV, 4, 4);
+    let count_low = count as i64;
+    let count_high = (count >> 64) as i64;
+
+    let mut a = load_256_unaligned(h_low);
+    let mut b = load_256_unaligned(h_high);
+    let mut c = load_256_unaligned(iv_low);
+    let flags = _mm256_set_epi64x(lastnode as i64, lastblock as i64, count_high, count_low);
+    let mut d = xor(load_256_unaligned(iv_high), flags);
+
+    let mut m = [0; 16];
+    LittleEndian::read_u64_into(msg, &mut m);
+
+    let iv0 = a;
+    let iv1 = b;
+    let mut b0;
+
+    for r in 0..rounds {
+        let s = &SIGMA[(r % 10) as usize];
+        b0 = load_msg_words(&m, s, 0);
+        blake2b_g1_v1(&mut a, &mut b, &mut c, &mut d, &mut b0);
+        b0 = load_msg_words(&m, s, 1);
+        blake2b_g2_v1(&mut a, &mut b, &mut c, &mut d, &mut b0);
+        blake2b_diag_v1(&mut a, &mut b, &mut c, &mut d);
+        b0 = load_msg_words(&m, s, 8);
+        blake2b_g1_v1(&mut a, &mut b, &mut c, &mut d, &mut b0);
+        b0 = load_msg_words(&m, s, 9);
+        blake2b_g2_v1(&mut a, &mut b, &mut c, &mut d, &mut b0);
+        blake2b_undiag_v1(&mut a, &mut b, &mut c, &mut d);
+    }
+
+    a = xor(a, c);
+    b = xor(b, d);
+    a = xor(a, iv0);
+    b = xor(b, iv1);
+
+    store_256_unaligned(h_low, a);
+    store_256_unaligned(h_high, b);
+}
+
 #[inline(always)]
 unsafe fn load_256_from_u64(x: u64) -> __m256i {
     _mm256_set1_epi64x(x as i64)
@@ -662,15 +772,14 @@ pub unsafe fn transpose_message_blocks(
 }
 
 #[inline(always)]
-unsafe fn compress4_transposed_inline(
-    h_vecs: &mut [__m256i; 8],
-    msg_vecs: &[__m256i; 16],
+unsafe fn init_v_4x(
+    h_vecs: &[__m256i; 8],
     count_low: __m256i,
     count_high: __m256i,
     lastblock: __m256i,
     lastnode: __m256i,
-) {
-    let mut v = [
+) -> [__m256i; 16] {
+    [
         h_vecs[0],
         h_vecs[1],
         h_vecs[2],
@@ -687,7 +796,31 @@ unsafe fn compress4_transposed_inline(
         xor(load_256_from_u64(IV[5]), count_high),
         xor(load_256_from_u64(IV[6]), lastblock),
         xor(load_256_from_u64(IV[7]), lastnode),
-    ];
+    ]
+}
+
+#[inline(always)]
+unsafe fn finish_v_4x(h_vecs: &mut [__m256i; 8], v: &[__m256i; 16]) {
+    h_vecs[0] = xor(xor(h_vecs[0], v[0]), v[8]);
+    h_vecs[1] = xor(xor(h_vecs[1], v[1]), v[9]);
+    h_vecs[2] = xor(xor(h_vecs[2], v[2]), v[10]);
+    h_vecs[3] = xor(xor(h_vecs[3], v[3]), v[11]);
+    h_vecs[4] = xor(xor(h_vecs[4], v[4]), v[12]);
+    h_vecs[5] = xor(xor(h_vecs[5], v[5]), v[13]);
+    h_vecs[6] = xor(xor(h_vecs[6], v[6]), v[14]);
+    h_vecs[7] = xor(xor(h_vecs[7], v[7]), v[15]);
+}
+
+#[inline(always)]
+unsafe fn compress4_transposed_inline(
+    h_vecs: &mut [__m256i; 8],
+    msg_vecs: &[__m256i; 16],
+    count_low: __m256i,
+    count_high: __m256i,
+    lastblock: __m256i,
+    lastnode: __m256i,
+) {
+    let mut v = init_v_4x(h_vecs, count_low, count_high, lastblock, lastnode);
 
     blake2b_round_4x(&mut v, &msg_vecs, 0);
     blake2b_round_4x(&mut v, &msg_vecs, 1);
@@ -702,14 +835,7 @@ unsafe fn compress4_transposed_inline(
     blake2b_round_4x(&mut v, &msg_vecs, 10);
     blake2b_round_4x(&mut v, &msg_vecs, 11);
 
-    h_vecs[0] = xor(xor(h_vecs[0], v[0]), v[8]);
-    h_vecs[1] = xor(xor(h_vecs[1], v[1]), v[9]);
-    h_vecs[2] = xor(xor(h_vecs[2], v[2]), v[10]);
-    h_vecs[3] = xor(xor(h_vecs[3], v[3]), v[11]);
-    h_vecs[4] = xor(xor(h_vecs[4], v[4]), v[12]);
-    h_vecs[5] = xor(xor(h_vecs[5], v[5]), v[13]);
-    h_vecs[6] = xor(xor(h_vecs[6], v[6]), v[14]);
-    h_vecs[7] = xor(xor(h_vecs[7], v[7]), v[15]);
+    finish_v_4x(h_vecs, &v);
 }
 
 // Currently just for benchmarking.
@@ -735,3 +861,62 @@ pub unsafe fn compress4_transposed(
         mem::transmute(*lastnode),
     );
 }
+
+#[inline(always)]
+unsafe fn load_u64x4(x: &u64x4) -> __m256i {
+    // u64x4 is 32-byte aligned.
+    _mm256_load_si256(x.as_ptr() as *const __m256i)
+}
+
+#[inline(always)]
+unsafe fn store_u64x4(x: &mut u64x4, a: __m256i) {
+    _mm256_store_si256(x.as_mut_ptr() as *mut __m256i, a)
+}
+
+// AVX2 only has room for four 64-bit lanes, so this is two compress4 passes. Interleaving their
+// rounds gives the CPU two independent dependency chains to work on, which hides some of the
+// latency of each one, at the cost of spilling some registers.
+#[target_feature(enable = "avx2")]
+pub unsafe fn compress8_transposed(
+    h_vecs: &mut [u64x8; 8],
+    msgs: [&Block; 8],
+    count_low: &u64x8,
+    count_high: &u64x8,
+    lastblock: &u64x8,
+    lastnode: &u64x8,
+) {
+    let mut h_low = [_mm256_setzero_si256(); 8];
+    let mut h_high = [_mm256_setzero_si256(); 8];
+    for ((vec, low), high) in h_vecs.iter().zip(&mut h_low).zip(&mut h_high) {
+        *low = load_u64x4(&vec.split()[0]);
+        *high = load_u64x4(&vec.split()[1]);
+    }
+    let m_low = transpose_message_blocks(msgs[0], msgs[1], msgs[2], msgs[3]);
+    let m_high = transpose_message_blocks(msgs[4], msgs[5], msgs[6], msgs[7]);
+    let mut v_low = init_v_4x(
+        &h_low,
+        load_u64x4(&count_low.split()[0]),
+        load_u64x4(&count_high.split()[0]),
+        load_u64x4(&lastblock.split()[0]),
+        load_u64x4(&lastnode.split()[0]),
+    );
+    let mut v_high = init_v_4x(
+        &h_high,
+        load_u64x4(&count_low.split()[1]),
+        load_u64x4(&count_high.split()[1]),
+        load_u64x4(&lastblock.split()[1]),
+        load_u64x4(&lastnode.split()[1]),
+    );
+
+    for r in 0..12 {
+        blake2b_round_4x(&mut v_low, &m_low, r);
+        blake2b_round_4x(&mut v_high, &m_high, r);
+    }
+
+    finish_v_4x(&mut h_low, &v_low);
+    finish_v_4x(&mut h_high, &v_high);
+    for ((vec, low), high) in h_vecs.iter_mut().zip(&h_low).zip(&h_high) {
+        store_u64x4(&mut vec.split_mut()[0], *low);
+        store_u64x4(&mut vec.split_mut()[1], *high);
+    }
+}
@@ -23,7 +23,9 @@
 
 use crate::guts;
 use crate::Hash;
+use crate::ParamBlockError;
 use crate::Params as Blake2bParams;
+use crate::RestoreError;
 use crate::BLOCKBYTES;
 use crate::KEYBYTES;
 use crate::OUTBYTES;
@@ -36,6 +38,9 @@ use std;
 
 const DEGREE: usize = 4;
 
+/// The length of a state saved with [`State::save`](struct.State.html#method.save).
+pub const SAVED_STATE_BYTES: usize = 2 + DEGREE * 64 + 64 + 16 + 2 + 1 + 2 * DEGREE * BLOCKBYTES;
+
 /// Compute the BLAKE2bp hash of a slice of bytes, using default parameters.
 ///
 /// # Example
@@ -82,6 +87,81 @@ impl Params {
         State::with_params(self)
     }
 
+    // The BLAKE2b parameters shared by the leaves and the root.
+    fn to_base_params(&self) -> Blake2bParams {
+        let mut base_params = Blake2bParams::new();
+        base_params
+            .hash_length(self.hash_length as usize)
+            .key(&self.key[..self.key_length as usize])
+            .fanout(DEGREE as u8)
+            .max_depth(2)
+            .max_leaf_length(0)
+            // Note that inner_hash_length is always OUTBYTES, regardless of the hash_length
+            // parameter. This isn't documented in the spec, but it matches the behavior of the
+            // reference implementation: https://github.com/BLAKE2/BLAKE2/blob/320c325437539ae91091ce62efec1913cd8093c2/ref/blake2bp-ref.c#L55
+            .inner_hash_length(OUTBYTES);
+        base_params
+    }
+
+    fn to_leaf_params(&self, worker_index: u64) -> Blake2bParams {
+        let mut params = self.to_base_params();
+        // Note that setting the last_node flag here would have no effect, because it isn't
+        // included in the state words.
+        params.node_offset(worker_index).node_depth(0);
+        params
+    }
+
+    fn to_root_params(&self) -> Blake2bParams {
+        let mut params = self.to_base_params();
+        params.node_offset(0).node_depth(1);
+        params
+    }
+
+    /// Return the BLAKE2b parameter block of one of the four leaves, in the layout of
+    /// [`Params::to_param_block`](../struct.Params.html#method.to_param_block). The worker index
+    /// is the leaf's node offset, from 0 to 3.
+    pub fn to_leaf_param_block(&self, worker_index: u64) -> [u8; 64] {
+        assert!(
+            worker_index < DEGREE as u64,
+            "Bad worker index: {}",
+            worker_index
+        );
+        self.to_leaf_params(worker_index).to_param_block()
+    }
+
+    /// Return the BLAKE2b parameter block of the root node, in the layout of
+    /// [`Params::to_param_block`](../struct.Params.html#method.to_param_block).
+    pub fn to_root_param_block(&self) -> [u8; 64] {
+        self.to_root_params().to_param_block()
+    }
+
+    /// Parse the parameter block of any of the leaves or the root. The hash length and the key
+    /// length are the only things that can vary, and everything else must match the blocks that
+    /// BLAKE2bp uses. As with
+    /// [`Params::from_param_block`](../struct.Params.html#method.from_param_block), a block with
+    /// a nonzero key length returns `ParamBlockError::KeyLengthMismatch`. Use
+    /// [`from_param_block_with_key`](#method.from_param_block_with_key) for keyed blocks.
+    pub fn from_param_block(block: &[u8; 64]) -> Result<Self, ParamBlockError> {
+        Self::from_param_block_with_key(block, &[])
+    }
+
+    /// Like [`from_param_block`](#method.from_param_block), but with the key passed separately.
+    /// Its length must match the key length in the block.
+    pub fn from_param_block_with_key(
+        block: &[u8; 64],
+        key: &[u8],
+    ) -> Result<Self, ParamBlockError> {
+        let hash_length = Blake2bParams::from_param_block_with_key(block, key)?.hash_length;
+        let mut params = Self::new();
+        params.hash_length(hash_length as usize).key(key);
+        let is_leaf = (0..DEGREE as u64).any(|i| params.to_leaf_param_block(i)[..] == block[..]);
+        if is_leaf || params.to_root_param_block()[..] == block[..] {
+            Ok(params)
+        } else {
+            Err(ParamBlockError::InvalidParameter)
+        }
+    }
+
     /// Set the length of the final hash, from 1 to `OUTBYTES` (64). Apart from controlling the
     /// length of the final `Hash`, this is also associated data, and changing it will result in a
     /// totally different hash.
@@ -154,7 +234,7 @@ pub struct State {
     buf: [u8; 8 * BLOCKBYTES],
     buflen: u16,
     count: u128,
-    hash_length: u8,
+    pub(crate) hash_length: u8,
     implementation: guts::Implementation,
 }
 
@@ -166,39 +246,13 @@ impl State {
 
     fn with_params(params: &Params) -> Self {
         let implementation = guts::Implementation::detect();
-        let mut base_params = Blake2bParams::new();
-        base_params
-            .hash_length(params.hash_length as usize)
-            .key(&params.key[..params.key_length as usize])
-            .fanout(DEGREE as u8)
-            .max_depth(2)
-            .max_leaf_length(0)
-            // Note that inner_hash_length is always OUTBYTES, regardless of the hash_length
-            // parameter. This isn't documented in the spec, but it matches the behavior of the
-            // reference implementation: https://github.com/BLAKE2/BLAKE2/blob/320c325437539ae91091ce62efec1913cd8093c2/ref/blake2bp-ref.c#L55
-            .inner_hash_length(OUTBYTES);
-        let leaf_words = |worker_index| {
-            base_params
-                .clone()
-                .node_offset(worker_index)
-                .node_depth(0)
-                // Note that setting the last_node flag here has no effect,
-                // because it isn't included in the state words.
-                .to_state_words()
-        };
         let transposed_leaf_words = implementation.transpose4(
-            &leaf_words(0),
-            &leaf_words(1),
-            &leaf_words(2),
-            &leaf_words(3),
+            &params.to_leaf_params(0).to_state_words(),
+            &params.to_leaf_params(1).to_state_words(),
+            &params.to_leaf_params(2).to_state_words(),
+            &params.to_leaf_params(3).to_state_words(),
         );
-        let root_words = base_params
-            .clone()
-            .node_offset(0)
-            .node_depth(1)
-            // Note that setting the last_node flag here has no effect, because
-            // it isn't included in the state words.
-            .to_state_words();
+        let root_words = params.to_root_params().to_state_words();
 
         // If a key is set, initalize the buffer to contain the key bytes. Note
         // that only the leaves hash key bytes. The root doesn't, even though
@@ -263,14 +317,13 @@ impl State {
         );
     }
 
-    /// Add input to the hash. You can call `update` any number of times.
-    pub fn update(&mut self, mut input: &[u8]) -> &mut Self {
+    fn update_buffer(&mut self, input: &mut &[u8]) {
         // If we have a partial buffer, try to complete it. If we complete it and there's more
         // input waiting, we need to compress to make more room. However, because we need to be
         // sure that *none* of the leaves would need to be finalized as part of this round of
         // compression, we need to buffer more than we would for BLAKE2b.
         if self.buflen > 0 {
-            self.fill_buf(&mut input);
+            self.fill_buf(input);
             if !input.is_empty() {
                 // The buffer is large enough for two compressions. If it's full and there's more
                 // input coming, always do at least the first compression, on the left half of the
@@ -300,6 +353,11 @@ impl State {
                 }
             }
         }
+    }
+
+    /// Add input to the hash. You can call `update` any number of times.
+    pub fn update(&mut self, mut input: &[u8]) -> &mut Self {
+        self.update_buffer(&mut input);
 
         // While there are more than 7 input blocks coming, then we know that we can perform a
         // compression and still have more input coming for each leaf. (We also know that the
@@ -322,6 +380,82 @@ impl State {
         self
     }
 
+    /// Add input to the hash, like `update`, but hash the four leaves on separate threads using
+    /// [Rayon](https://github.com/rayon-rs/rayon). The result is the same as if the input was
+    /// passed to `update`. This is only available with the `rayon` Cargo feature.
+    ///
+    /// Each leaf is an ordinary sequential BLAKE2b hash, so the work can only be split four ways,
+    /// and each thread uses the single-block compression function rather than the four-way SIMD
+    /// one. This is worth it for large inputs, like big files read into memory or memory-mapped,
+    /// on machines with at least four cores. For small inputs, the threading overhead makes it
+    /// slower than `update`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "rayon")]
+    /// # {
+    /// use blake2b_simd::blake2bp;
+    ///
+    /// let input = vec![0xff; 1_000_000];
+    /// let hash = blake2bp::State::new().update_rayon(&input).finalize();
+    /// assert_eq!(blake2bp::blake2bp(&input), hash);
+    /// # }
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn update_rayon(&mut self, mut input: &[u8]) -> &mut Self {
+        use rayon::prelude::*;
+
+        self.update_buffer(&mut input);
+
+        // Compress the same blocks that the loop in `update` would, that is, everything except
+        // the last 4 to 7 blocks, which need to be buffered.
+        let group_len = DEGREE * BLOCKBYTES;
+        let num_groups = if input.len() > (2 * DEGREE - 1) * BLOCKBYTES {
+            (input.len() - (DEGREE - 1) * BLOCKBYTES - 1) / group_len
+        } else {
+            0
+        };
+        if num_groups > 0 {
+            debug_assert_eq!(0, self.buflen);
+            let bulk = &input[..num_groups * group_len];
+            let mut leaves = [[0u64; 8]; DEGREE];
+            {
+                let &mut [ref mut leaf0, ref mut leaf1, ref mut leaf2, ref mut leaf3] = &mut leaves;
+                self.implementation.untranspose4(
+                    &self.transposed_leaf_words,
+                    leaf0,
+                    leaf1,
+                    leaf2,
+                    leaf3,
+                );
+            }
+            let count = self.count;
+            let implementation = self.implementation;
+            leaves
+                .par_iter_mut()
+                .enumerate()
+                .for_each(|(leaf_index, leaf_words)| {
+                    // Leaf i gets the i-th block of each group. As in `compress`, the count for
+                    // each leaf goes up by one block per group.
+                    for (group_index, group) in bulk.chunks_exact(group_len).enumerate() {
+                        let block = array_ref!(group, leaf_index * BLOCKBYTES, BLOCKBYTES);
+                        let leaf_count = count + ((group_index + 1) * BLOCKBYTES) as u128;
+                        implementation.compress(leaf_words, block, leaf_count, 0, 0);
+                    }
+                });
+            self.transposed_leaf_words =
+                implementation.transpose4(&leaves[0], &leaves[1], &leaves[2], &leaves[3]);
+            self.count += (num_groups * BLOCKBYTES) as u128;
+            input = &input[bulk.len()..];
+        }
+
+        // Buffer any remaining input, exactly as `update` does.
+        self.fill_buf(&mut input);
+        debug_assert_eq!(0, input.len());
+        self
+    }
+
     /// Finalize the state and return a `Hash`. This method is idempotent, and calling it multiple
     /// times will give the same result. It's also possible to `update` with more input in between.
     pub fn finalize(&mut self) -> Hash {
@@ -463,6 +597,103 @@ impl State {
     pub fn count(&self) -> u128 {
         4 * self.count + self.buflen as u128
     }
+
+    /// Save the state, including any buffered input, as `SAVED_STATE_BYTES` (1365) bytes, like
+    /// [`State::save`](../struct.State.html#method.save) for BLAKE2b. **The saved bytes are as
+    /// sensitive as the key.** They contain the leaf and root state words, and for a keyed state
+    /// they can contain the key itself.
+    pub fn save(&self) -> [u8; SAVED_STATE_BYTES] {
+        let mut saved = [0; SAVED_STATE_BYTES];
+        {
+            let (header, leaf_words, root_words, count, buflen, hash_length, buf) = mut_array_refs!(
+                &mut saved,
+                2,
+                DEGREE * 64,
+                64,
+                16,
+                2,
+                1,
+                2 * DEGREE * BLOCKBYTES
+            );
+            *header = [crate::SAVED_STATE_VERSION, crate::SAVED_STATE_BLAKE2BP];
+            // Save the leaves untransposed, so that the format doesn't depend on the
+            // implementation.
+            let mut leaves = [[0u64; 8]; DEGREE];
+            {
+                let &mut [ref mut leaf0, ref mut leaf1, ref mut leaf2, ref mut leaf3] = &mut leaves;
+                self.implementation.untranspose4(
+                    &self.transposed_leaf_words,
+                    leaf0,
+                    leaf1,
+                    leaf2,
+                    leaf3,
+                );
+            }
+            for (leaf, bytes) in leaves.iter().zip(leaf_words.chunks_mut(64)) {
+                LittleEndian::write_u64_into(leaf, bytes);
+            }
+            LittleEndian::write_u64_into(&self.root_words, root_words);
+            crate::write_u128(count, self.count);
+            LittleEndian::write_u16(buflen, self.buflen);
+            hash_length[0] = self.hash_length;
+            buf[..self.buflen as usize].copy_from_slice(&self.buf[..self.buflen as usize]);
+        }
+        saved
+    }
+
+    /// Restore a state saved with [`save`](#method.save), like
+    /// [`State::restore`](../struct.State.html#method.restore) for BLAKE2b.
+    pub fn restore(saved: &[u8]) -> Result<Self, RestoreError> {
+        if saved.len() != SAVED_STATE_BYTES {
+            return Err(RestoreError::WrongLength);
+        }
+        let (header, leaf_words, root_words, count, buflen, hash_length, buf) = array_refs!(
+            array_ref!(saved, 0, SAVED_STATE_BYTES),
+            2,
+            DEGREE * 64,
+            64,
+            16,
+            2,
+            1,
+            2 * DEGREE * BLOCKBYTES
+        );
+        if *header != [crate::SAVED_STATE_VERSION, crate::SAVED_STATE_BLAKE2BP] {
+            return Err(RestoreError::UnknownFormat);
+        }
+        let count = crate::read_u128(count);
+        let buflen = LittleEndian::read_u16(buflen);
+        let hash_length = hash_length[0];
+        // The leaves are only ever compressed a whole block at a time, and only when more than
+        // DEGREE - 1 blocks of input are left over for the buffer, so that no leaf needs to be
+        // finalized. Before the first compression, the buffer can hold anything up to its
+        // length, including the key blocks.
+        if count / BLOCKBYTES as u128 * BLOCKBYTES as u128 != count
+            || buflen as usize > buf.len()
+            || (count > 0 && buflen as usize <= (DEGREE - 1) * BLOCKBYTES)
+            || hash_length == 0
+            || hash_length as usize > OUTBYTES
+        {
+            return Err(RestoreError::InvalidState);
+        }
+        let mut leaves = [[0u64; 8]; DEGREE];
+        for (leaf, bytes) in leaves.iter_mut().zip(leaf_words.chunks(64)) {
+            LittleEndian::read_u64_into(bytes, leaf);
+        }
+        let implementation = guts::Implementation::detect();
+        let mut state = Self {
+            transposed_leaf_words: implementation
+                .transpose4(&leaves[0], &leaves[1], &leaves[2], &leaves[3]),
+            root_words: [0; 8],
+            buf: [0; 2 * DEGREE * BLOCKBYTES],
+            buflen,
+            count,
+            hash_length,
+            implementation,
+        };
+        LittleEndian::read_u64_into(root_words, &mut state.root_words);
+        state.buf[..buflen as usize].copy_from_slice(&buf[..buflen as usize]);
+        Ok(state)
+    }
 }
 
 #[cfg(feature = "std")]
@@ -601,4 +832,107 @@ pub(crate) mod test {
             }
         }
     }
+
+    #[test]
+    fn test_save_restore() {
+        let mut buf = [0; 20 * BLOCKBYTES];
+        paint_input(&mut buf);
+        let mut params = Params::new();
+        params.hash_length(32).key(b"foo");
+        for params in &[Params::new(), params] {
+            let expected = params.to_state().update(&buf).finalize();
+            // Split the input at points that leave the double buffer empty, partly full, and
+            // full, and that need the second half of the buffer shifted.
+            for &split in &[0, 1, 511, 512, 1024, 1025, 1536, 2047, 2560] {
+                let mut state = params.to_state();
+                state.update(&buf[..split]);
+                let saved = state.save();
+                let mut restored = State::restore(&saved).unwrap();
+                assert_eq!(state.count(), restored.count());
+                // The format doesn't depend on the implementation.
+                restored.implementation = guts::Implementation::portable();
+                restored.update(&buf[split..]);
+                assert_eq!(expected, restored.finalize());
+            }
+        }
+
+        let saved = State::new().update(b"foo").save();
+        assert_eq!(
+            Err(RestoreError::WrongLength),
+            State::restore(&saved[1..]).map(|_| ())
+        );
+        let blake2b_saved = crate::State::new().save();
+        assert_eq!(
+            Err(RestoreError::WrongLength),
+            State::restore(&blake2b_saved).map(|_| ())
+        );
+        let mut bad = saved;
+        bad[1] = b'b';
+        assert_eq!(
+            Err(RestoreError::UnknownFormat),
+            State::restore(&bad).map(|_| ())
+        );
+        // A count that isn't a whole number of blocks, a buflen past the end of the buffer, and
+        // a hash_length that's too long.
+        let count_start = 2 + DEGREE * 64 + 64;
+        for &(index, value) in &[
+            (count_start, 1),
+            (count_start + 17, 5),
+            (count_start + 18, 65),
+        ] {
+            bad = saved;
+            bad[index] = value;
+            assert_eq!(
+                Err(RestoreError::InvalidState),
+                State::restore(&bad).map(|_| ())
+            );
+        }
+
+        // After the leaves have been compressed, update always leaves more than DEGREE - 1
+        // blocks in the buffer, so a smaller buflen can't come from a real state.
+        let mut state = State::new();
+        state.update(&buf[..2000]);
+        let saved = state.save();
+        for &buflen in &[0, 1, (DEGREE - 1) * BLOCKBYTES] {
+            bad = saved;
+            LittleEndian::write_u16(&mut bad[count_start + 16..][..2], buflen as u16);
+            assert_eq!(
+                Err(RestoreError::InvalidState),
+                State::restore(&bad).map(|_| ())
+            );
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_update_rayon() {
+        let mut buf = [0; 40 * BLOCKBYTES];
+        paint_input(&mut buf);
+        for num_blocks in 0..=40 {
+            for &extra in &[0, 1, BLOCKBYTES - 1] {
+                let input = &buf[..cmp::min(buf.len(), num_blocks * BLOCKBYTES + extra)];
+                let expected = blake2bp(input);
+
+                // All at once.
+                let found = State::new().update_rayon(input).finalize();
+                assert_eq!(expected, found);
+
+                // Keyed, with a partial buffer going in, and with a regular update and a
+                // throwaway finalize in between.
+                let mut params = Params::new();
+                params.hash_length(32).key(b"foo");
+                let mut expected_state = params.to_state();
+                let expected = expected_state.update(input).finalize();
+                let mut state = params.to_state();
+                let (first, rest) = input.split_at(cmp::min(input.len(), BLOCKBYTES + 1));
+                state.update_rayon(first);
+                state.finalize();
+                let (middle, last) = rest.split_at(rest.len() / 2);
+                state.update(middle);
+                state.update_rayon(last);
+                assert_eq!(expected_state.count(), state.count());
+                assert_eq!(expected, state.finalize());
+            }
+        }
+    }
 }
@@ -0,0 +1,516 @@
+//! An implementation of BLAKE2s, the 32-bit sibling of BLAKE2b.
+//!
+//! BLAKE2s operates on 32-bit words and 64-byte blocks, and its max output length is 32 bytes.
+//! It's optimized for 8- to 32-bit platforms, and it's the variant used by protocols like
+//! WireGuard. On 64-bit platforms BLAKE2b is usually faster, but the [`blake2sp`] variant of
+//! BLAKE2s can make up the difference by hashing eight leaves in parallel with AVX2.
+//!
+//! The API mirrors the crate's BLAKE2b API exactly, with its own `Params`, `State`, and `Hash`
+//! types.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::blake2s::{blake2s, Params};
+//!
+//! let expected = "08d6cad88075de8f192db097573d0e829411cd91eb6ec65e8fc16c017edfdb74";
+//! let hash = blake2s(b"foo");
+//! assert_eq!(expected, &hash.to_hex());
+//!
+//! let hash = Params::new()
+//!     .hash_length(16)
+//!     .key(b"Squeamish Ossifrage")
+//!     .personal(b"Shaftoe")
+//!     .to_state()
+//!     .update(b"foo")
+//!     .update(b"bar")
+//!     .update(b"baz")
+//!     .finalize();
+//! assert_eq!("28325512782cbf5019424fa65da9a6c7", &hash.to_hex());
+//! ```
+//!
+//! [`blake2sp`]: ../blake2sp/index.html
+
+use crate::guts;
+use byteorder::{ByteOrder, LittleEndian};
+use core::cmp;
+use core::fmt;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) mod avx2;
+pub(crate) mod portable;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub(crate) mod sse41;
+
+#[cfg(feature = "std")]
+use std;
+
+/// The max hash length.
+pub const OUTBYTES: usize = 32;
+/// The max key length.
+pub const KEYBYTES: usize = 32;
+/// The max salt length.
+pub const SALTBYTES: usize = 8;
+/// The max personalization length.
+pub const PERSONALBYTES: usize = 8;
+/// The number input bytes passed to each call to the compression function. Small benchmarks need
+/// to use an even multiple of `BLOCKBYTES`, or else their apparent throughput will be low.
+pub const BLOCKBYTES: usize = 64;
+
+// The max node offset is 48 bits in BLAKE2s.
+const MAX_NODE_OFFSET: u64 = (1 << 48) - 1;
+
+pub(crate) const IV: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+pub(crate) const SIGMA: [[u8; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+pub(crate) type StateWords = [u32; 8];
+pub(crate) type Block = [u8; BLOCKBYTES];
+type HexString = arrayvec::ArrayString<[u8; 2 * OUTBYTES]>;
+
+/// Compute the BLAKE2s hash of a slice of bytes, using default parameters.
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::blake2s::{blake2s, Params};
+/// let expected = "08d6cad88075de8f192db097573d0e829411cd91eb6ec65e8fc16c017edfdb74";
+/// let hash = blake2s(b"foo");
+/// assert_eq!(&hash.to_hex(), expected);
+/// ```
+pub fn blake2s(input: &[u8]) -> Hash {
+    State::new().update(input).finalize()
+}
+
+/// A parameter builder for BLAKE2s, just like the [`Params`](../struct.Params.html) type for
+/// BLAKE2b.
+///
+/// Apart from `hash_length`, which controls the length of the final `Hash`, all of these
+/// parameters are just associated data that gets mixed with the input. For all the details, see
+/// [the BLAKE2 spec](https://blake2.net/blake2.pdf).
+///
+/// Several of the parameters have a valid range defined in the spec and documented below. Trying
+/// to set an invalid parameter will panic.
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::blake2s::Params;
+/// let mut state = Params::new().hash_length(16).to_state();
+/// ```
+#[derive(Clone)]
+pub struct Params {
+    hash_length: u8,
+    key_length: u8,
+    key: [u8; KEYBYTES],
+    salt: [u8; SALTBYTES],
+    personal: [u8; PERSONALBYTES],
+    fanout: u8,
+    max_depth: u8,
+    max_leaf_length: u32,
+    node_offset: u64,
+    node_depth: u8,
+    inner_hash_length: u8,
+    last_node: bool,
+}
+
+impl Params {
+    /// Equivalent to `Params::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn to_state_words(&self) -> StateWords {
+        [
+            IV[0]
+                ^ self.hash_length as u32
+                ^ (self.key_length as u32) << 8
+                ^ (self.fanout as u32) << 16
+                ^ (self.max_depth as u32) << 24,
+            IV[1] ^ self.max_leaf_length,
+            IV[2] ^ self.node_offset as u32,
+            IV[3]
+                ^ (self.node_offset >> 32) as u32
+                ^ (self.node_depth as u32) << 16
+                ^ (self.inner_hash_length as u32) << 24,
+            IV[4] ^ LittleEndian::read_u32(&self.salt[0..4]),
+            IV[5] ^ LittleEndian::read_u32(&self.salt[4..8]),
+            IV[6] ^ LittleEndian::read_u32(&self.personal[0..4]),
+            IV[7] ^ LittleEndian::read_u32(&self.personal[4..8]),
+        ]
+    }
+
+    /// Construct a `State` object based on these parameters.
+    pub fn to_state(&self) -> State {
+        State::with_params(self)
+    }
+
+    /// Set the length of the final hash in bytes, from 1 to `OUTBYTES` (32). Apart from
+    /// controlling the length of the final `Hash`, this is also associated data, and changing it
+    /// will result in a totally different hash.
+    pub fn hash_length(&mut self, length: usize) -> &mut Self {
+        assert!(
+            (1..=OUTBYTES).contains(&length),
+            "Bad hash length: {}",
+            length
+        );
+        self.hash_length = length as u8;
+        self
+    }
+
+    /// Use a secret key, so that BLAKE2s acts as a MAC. The maximum key length is `KEYBYTES` (32).
+    /// An empty key is equivalent to having no key at all.
+    pub fn key(&mut self, key: &[u8]) -> &mut Self {
+        assert!(key.len() <= KEYBYTES, "Bad key length: {}", key.len());
+        self.key_length = key.len() as u8;
+        self.key = [0; KEYBYTES];
+        self.key[..key.len()].copy_from_slice(key);
+        self
+    }
+
+    /// At most `SALTBYTES` (8). Shorter salts are padded with null bytes. An empty salt is
+    /// equivalent to having no salt at all.
+    pub fn salt(&mut self, salt: &[u8]) -> &mut Self {
+        assert!(salt.len() <= SALTBYTES, "Bad salt length: {}", salt.len());
+        self.salt = [0; SALTBYTES];
+        self.salt[..salt.len()].copy_from_slice(salt);
+        self
+    }
+
+    /// At most `PERSONALBYTES` (8). Shorter personalizations are padded with null bytes. An empty
+    /// personalization is equivalent to having no personalization at all.
+    pub fn personal(&mut self, personalization: &[u8]) -> &mut Self {
+        assert!(
+            personalization.len() <= PERSONALBYTES,
+            "Bad personalization length: {}",
+            personalization.len()
+        );
+        self.personal = [0; PERSONALBYTES];
+        self.personal[..personalization.len()].copy_from_slice(personalization);
+        self
+    }
+
+    /// From 0 (meaning unlimited) to 255. The default is 1 (meaning sequential).
+    pub fn fanout(&mut self, fanout: u8) -> &mut Self {
+        self.fanout = fanout;
+        self
+    }
+
+    /// From 1 (the default, meaning sequential) to 255 (meaning unlimited).
+    pub fn max_depth(&mut self, depth: u8) -> &mut Self {
+        assert!(depth != 0, "Bad max depth: {}", depth);
+        self.max_depth = depth;
+        self
+    }
+
+    /// From 0 (the default, meaning unlimited or sequential) to `2^32 - 1`.
+    pub fn max_leaf_length(&mut self, length: u32) -> &mut Self {
+        self.max_leaf_length = length;
+        self
+    }
+
+    /// From 0 (the default, meaning first, leftmost, leaf, or sequential) to `2^48 - 1`.
+    pub fn node_offset(&mut self, offset: u64) -> &mut Self {
+        assert!(offset <= MAX_NODE_OFFSET, "Bad node offset: {}", offset);
+        self.node_offset = offset;
+        self
+    }
+
+    /// From 0 (the default, meaning leaf or sequential) to 255.
+    pub fn node_depth(&mut self, depth: u8) -> &mut Self {
+        self.node_depth = depth;
+        self
+    }
+
+    /// From 0 (the default, meaning sequential) to `OUTBYTES` (32).
+    pub fn inner_hash_length(&mut self, length: usize) -> &mut Self {
+        assert!(length <= OUTBYTES, "Bad inner hash length: {}", length);
+        self.inner_hash_length = length as u8;
+        self
+    }
+
+    /// Indicates the rightmost node in a row. This can also be changed on the `State` object
+    /// itself, potentially after hashing has begun. See [`State::set_last_node`].
+    ///
+    /// [`State::set_last_node`]: struct.State.html#method.set_last_node
+    pub fn last_node(&mut self, last_node: bool) -> &mut Self {
+        self.last_node = last_node;
+        self
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            hash_length: OUTBYTES as u8,
+            key_length: 0,
+            key: [0; KEYBYTES],
+            salt: [0; SALTBYTES],
+            personal: [0; PERSONALBYTES],
+            // NOTE: fanout and max_depth don't default to zero!
+            fanout: 1,
+            max_depth: 1,
+            max_leaf_length: 0,
+            node_offset: 0,
+            node_depth: 0,
+            inner_hash_length: 0,
+            last_node: false,
+        }
+    }
+}
+
+impl fmt::Debug for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Params {{ hash_length: {}, key_length: {}, salt: {:?}, personal: {:?}, fanout: {}, \
+             max_depth: {}, max_leaf_length: {}, node_offset: {}, node_depth: {}, \
+             inner_hash_length: {}, last_node: {} }}",
+            self.hash_length,
+            // NB: Don't print the key itself. Debug shouldn't leak secrets.
+            self.key_length,
+            &self.salt,
+            &self.personal,
+            self.fanout,
+            self.max_depth,
+            self.max_leaf_length,
+            self.node_offset,
+            self.node_depth,
+            self.inner_hash_length,
+            self.last_node,
+        )
+    }
+}
+
+/// An incremental hasher for BLAKE2s, just like the [`State`](../struct.State.html) type for
+/// BLAKE2b.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::blake2s::{blake2s, State};
+///
+/// let mut state = State::new();
+///
+/// state.update(b"foo");
+/// assert_eq!(blake2s(b"foo"), state.finalize());
+///
+/// state.update(b"bar");
+/// assert_eq!(blake2s(b"foobar"), state.finalize());
+/// ```
+#[derive(Clone)]
+pub struct State {
+    h: StateWords,
+    buf: Block,
+    buflen: u8,
+    count: u64,
+    last_node: bool,
+    hash_length: u8,
+    implementation: guts::Implementation,
+}
+
+impl State {
+    /// Equivalent to `State::default()` or `Params::default().to_state()`.
+    pub fn new() -> Self {
+        Self::with_params(&Params::default())
+    }
+
+    fn with_params(params: &Params) -> Self {
+        let mut state = Self {
+            h: params.to_state_words(),
+            buf: [0; BLOCKBYTES],
+            buflen: 0,
+            count: 0,
+            last_node: params.last_node,
+            hash_length: params.hash_length,
+            implementation: guts::Implementation::detect(),
+        };
+        if params.key_length > 0 {
+            let mut key_block = [0; BLOCKBYTES];
+            key_block[..KEYBYTES].copy_from_slice(&params.key);
+            state.update(&key_block);
+        }
+        state
+    }
+
+    fn fill_buf(&mut self, input: &mut &[u8]) {
+        let take = cmp::min(BLOCKBYTES - self.buflen as usize, input.len());
+        self.buf[self.buflen as usize..self.buflen as usize + take].copy_from_slice(&input[..take]);
+        self.buflen += take as u8;
+        self.count += take as u64;
+        *input = &input[take..];
+    }
+
+    // If the state already has some input in its buffer, try to fill the buffer and perform a
+    // compression. However, only do the compression if there's more input coming, otherwise it
+    // will give the wrong hash it the caller finalizes immediately after.
+    fn compress_buffer_if_possible(&mut self, input: &mut &[u8]) {
+        if self.buflen > 0 {
+            self.fill_buf(input);
+            if !input.is_empty() {
+                self.implementation
+                    .blake2s_compress(&mut self.h, &self.buf, self.count, 0, 0);
+                self.buflen = 0;
+            }
+        }
+    }
+
+    /// Add input to the hash. You can call `update` any number of times.
+    pub fn update(&mut self, mut input: &[u8]) -> &mut Self {
+        // If we have a partial buffer, try to complete it.
+        self.compress_buffer_if_possible(&mut input);
+        // While there's more than a block of input left (which also means we cleared the buffer
+        // above), compress blocks directly without copying.
+        while input.len() > BLOCKBYTES {
+            self.count += BLOCKBYTES as u64;
+            let block = array_ref!(input, 0, BLOCKBYTES);
+            self.implementation
+                .blake2s_compress(&mut self.h, block, self.count, 0, 0);
+            input = &input[BLOCKBYTES..];
+        }
+        // Buffer any remaining input, to be either compressed or finalized in a subsequent call.
+        self.fill_buf(&mut input);
+        self
+    }
+
+    /// Finalize the state and return a `Hash`. This method is idempotent, and calling it multiple
+    /// times will give the same result. It's also possible to `update` with more input in between.
+    pub fn finalize(&mut self) -> Hash {
+        for i in self.buflen as usize..BLOCKBYTES {
+            self.buf[i] = 0;
+        }
+        let last_node = if self.last_node { !0 } else { 0 };
+        let mut h_copy = self.h;
+        self.implementation
+            .blake2s_compress(&mut h_copy, &self.buf, self.count, !0, last_node);
+        Hash {
+            bytes: state_words_to_bytes(&h_copy),
+            len: self.hash_length,
+        }
+    }
+
+    /// Set a flag indicating that this is the last node of its level in a tree hash. This is
+    /// equivalent to [`Params::last_node`], except that it can be set at any time before calling
+    /// `finalize`. That allows callers to begin hashing a node without knowing ahead of time
+    /// whether it's the last in its level. For more details about the intended use of this flag
+    /// [the BLAKE2 spec].
+    ///
+    /// [`Params::last_node`]: struct.Params.html#method.last_node
+    /// [the BLAKE2 spec]: https://blake2.net/blake2.pdf
+    pub fn set_last_node(&mut self, last_node: bool) -> &mut Self {
+        self.last_node = last_node;
+        self
+    }
+
+    /// Return the total number of bytes input so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+pub(crate) fn state_words_to_bytes(state_words: &StateWords) -> [u8; OUTBYTES] {
+    let mut bytes = [0; OUTBYTES];
+    LittleEndian::write_u32_into(state_words, &mut bytes);
+    bytes
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for State {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: Don't print the words. Leaking them would allow length extension.
+        write!(
+            f,
+            "State {{ count: {}, hash_length: {}, last_node: {} }}",
+            self.count, self.hash_length, self.last_node,
+        )
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::with_params(&Params::default())
+    }
+}
+
+/// A finalized BLAKE2s hash, with constant-time equality.
+#[derive(Clone, Copy)]
+pub struct Hash {
+    pub(crate) bytes: [u8; OUTBYTES],
+    pub(crate) len: u8,
+}
+
+impl Hash {
+    /// Convert the hash to a byte slice. Note that if you're using BLAKE2s as a MAC, you need
+    /// constant time equality, which `&[u8]` doesn't provide.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    /// Convert the hash to a lowercase hexadecimal
+    /// [`ArrayString`](https://docs.rs/arrayvec/0.4/arrayvec/struct.ArrayString.html).
+    pub fn to_hex(&self) -> HexString {
+        let mut s = arrayvec::ArrayString::new();
+        let table = b"0123456789abcdef";
+        for &b in self.as_bytes() {
+            s.push(table[(b >> 4) as usize] as char);
+            s.push(table[(b & 0xf) as usize] as char);
+        }
+        s
+    }
+}
+
+/// This implementation is constant time, if the two hashes are the same length.
+impl PartialEq for Hash {
+    fn eq(&self, other: &Hash) -> bool {
+        constant_time_eq::constant_time_eq(self.as_bytes(), other.as_bytes())
+    }
+}
+
+/// This implementation is constant time, if the slice is the same length as the hash.
+impl PartialEq<[u8]> for Hash {
+    fn eq(&self, other: &[u8]) -> bool {
+        constant_time_eq::constant_time_eq(self.as_bytes(), other)
+    }
+}
+
+impl Eq for Hash {}
+
+impl AsRef<[u8]> for Hash {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hash(0x{})", self.to_hex())
+    }
+}
+
+pub(crate) fn force_portable(state: &mut State) {
+    state.implementation = guts::Implementation::portable();
+}
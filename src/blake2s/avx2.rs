@@ -0,0 +1,350 @@
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use super::*;
+use crate::guts::u32x8;
+use core::mem;
+
+#[inline(always)]
+unsafe fn loadu(src: *const u8) -> __m256i {
+    _mm256_loadu_si256(src as *const __m256i)
+}
+
+#[inline(always)]
+unsafe fn storeu(src: __m256i, dest: *mut u32) {
+    _mm256_storeu_si256(dest as *mut __m256i, src)
+}
+
+#[inline(always)]
+unsafe fn add(a: __m256i, b: __m256i) -> __m256i {
+    _mm256_add_epi32(a, b)
+}
+
+#[inline(always)]
+unsafe fn xor(a: __m256i, b: __m256i) -> __m256i {
+    _mm256_xor_si256(a, b)
+}
+
+#[inline(always)]
+unsafe fn set1(x: u32) -> __m256i {
+    _mm256_set1_epi32(x as i32)
+}
+
+#[inline(always)]
+unsafe fn rot16(x: __m256i) -> __m256i {
+    let rotate16 = _mm256_setr_epi8(
+        2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9, 14, 15, 12, 13, 2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9,
+        14, 15, 12, 13,
+    );
+    _mm256_shuffle_epi8(x, rotate16)
+}
+
+#[inline(always)]
+unsafe fn rot12(x: __m256i) -> __m256i {
+    _mm256_or_si256(_mm256_srli_epi32(x, 12), _mm256_slli_epi32(x, 32 - 12))
+}
+
+#[inline(always)]
+unsafe fn rot8(x: __m256i) -> __m256i {
+    let rotate8 = _mm256_setr_epi8(
+        1, 2, 3, 0, 5, 6, 7, 4, 9, 10, 11, 8, 13, 14, 15, 12, 1, 2, 3, 0, 5, 6, 7, 4, 9, 10, 11, 8,
+        13, 14, 15, 12,
+    );
+    _mm256_shuffle_epi8(x, rotate8)
+}
+
+#[inline(always)]
+unsafe fn rot7(x: __m256i) -> __m256i {
+    _mm256_or_si256(_mm256_srli_epi32(x, 7), _mm256_slli_epi32(x, 32 - 7))
+}
+
+#[inline(always)]
+unsafe fn blake2s_round_8x(v: &mut [__m256i; 16], m: &[__m256i; 16], r: usize) {
+    v[0] = add(v[0], m[SIGMA[r][0] as usize]);
+    v[1] = add(v[1], m[SIGMA[r][2] as usize]);
+    v[2] = add(v[2], m[SIGMA[r][4] as usize]);
+    v[3] = add(v[3], m[SIGMA[r][6] as usize]);
+    v[0] = add(v[0], v[4]);
+    v[1] = add(v[1], v[5]);
+    v[2] = add(v[2], v[6]);
+    v[3] = add(v[3], v[7]);
+    v[12] = xor(v[12], v[0]);
+    v[13] = xor(v[13], v[1]);
+    v[14] = xor(v[14], v[2]);
+    v[15] = xor(v[15], v[3]);
+    v[12] = rot16(v[12]);
+    v[13] = rot16(v[13]);
+    v[14] = rot16(v[14]);
+    v[15] = rot16(v[15]);
+    v[8] = add(v[8], v[12]);
+    v[9] = add(v[9], v[13]);
+    v[10] = add(v[10], v[14]);
+    v[11] = add(v[11], v[15]);
+    v[4] = xor(v[4], v[8]);
+    v[5] = xor(v[5], v[9]);
+    v[6] = xor(v[6], v[10]);
+    v[7] = xor(v[7], v[11]);
+    v[4] = rot12(v[4]);
+    v[5] = rot12(v[5]);
+    v[6] = rot12(v[6]);
+    v[7] = rot12(v[7]);
+    v[0] = add(v[0], m[SIGMA[r][1] as usize]);
+    v[1] = add(v[1], m[SIGMA[r][3] as usize]);
+    v[2] = add(v[2], m[SIGMA[r][5] as usize]);
+    v[3] = add(v[3], m[SIGMA[r][7] as usize]);
+    v[0] = add(v[0], v[4]);
+    v[1] = add(v[1], v[5]);
+    v[2] = add(v[2], v[6]);
+    v[3] = add(v[3], v[7]);
+    v[12] = xor(v[12], v[0]);
+    v[13] = xor(v[13], v[1]);
+    v[14] = xor(v[14], v[2]);
+    v[15] = xor(v[15], v[3]);
+    v[12] = rot8(v[12]);
+    v[13] = rot8(v[13]);
+    v[14] = rot8(v[14]);
+    v[15] = rot8(v[15]);
+    v[8] = add(v[8], v[12]);
+    v[9] = add(v[9], v[13]);
+    v[10] = add(v[10], v[14]);
+    v[11] = add(v[11], v[15]);
+    v[4] = xor(v[4], v[8]);
+    v[5] = xor(v[5], v[9]);
+    v[6] = xor(v[6], v[10]);
+    v[7] = xor(v[7], v[11]);
+    v[4] = rot7(v[4]);
+    v[5] = rot7(v[5]);
+    v[6] = rot7(v[6]);
+    v[7] = rot7(v[7]);
+
+    v[0] = add(v[0], m[SIGMA[r][8] as usize]);
+    v[1] = add(v[1], m[SIGMA[r][10] as usize]);
+    v[2] = add(v[2], m[SIGMA[r][12] as usize]);
+    v[3] = add(v[3], m[SIGMA[r][14] as usize]);
+    v[0] = add(v[0], v[5]);
+    v[1] = add(v[1], v[6]);
+    v[2] = add(v[2], v[7]);
+    v[3] = add(v[3], v[4]);
+    v[15] = xor(v[15], v[0]);
+    v[12] = xor(v[12], v[1]);
+    v[13] = xor(v[13], v[2]);
+    v[14] = xor(v[14], v[3]);
+    v[15] = rot16(v[15]);
+    v[12] = rot16(v[12]);
+    v[13] = rot16(v[13]);
+    v[14] = rot16(v[14]);
+    v[10] = add(v[10], v[15]);
+    v[11] = add(v[11], v[12]);
+    v[8] = add(v[8], v[13]);
+    v[9] = add(v[9], v[14]);
+    v[5] = xor(v[5], v[10]);
+    v[6] = xor(v[6], v[11]);
+    v[7] = xor(v[7], v[8]);
+    v[4] = xor(v[4], v[9]);
+    v[5] = rot12(v[5]);
+    v[6] = rot12(v[6]);
+    v[7] = rot12(v[7]);
+    v[4] = rot12(v[4]);
+    v[0] = add(v[0], m[SIGMA[r][9] as usize]);
+    v[1] = add(v[1], m[SIGMA[r][11] as usize]);
+    v[2] = add(v[2], m[SIGMA[r][13] as usize]);
+    v[3] = add(v[3], m[SIGMA[r][15] as usize]);
+    v[0] = add(v[0], v[5]);
+    v[1] = add(v[1], v[6]);
+    v[2] = add(v[2], v[7]);
+    v[3] = add(v[3], v[4]);
+    v[15] = xor(v[15], v[0]);
+    v[12] = xor(v[12], v[1]);
+    v[13] = xor(v[13], v[2]);
+    v[14] = xor(v[14], v[3]);
+    v[15] = rot8(v[15]);
+    v[12] = rot8(v[12]);
+    v[13] = rot8(v[13]);
+    v[14] = rot8(v[14]);
+    v[10] = add(v[10], v[15]);
+    v[11] = add(v[11], v[12]);
+    v[8] = add(v[8], v[13]);
+    v[9] = add(v[9], v[14]);
+    v[5] = xor(v[5], v[10]);
+    v[6] = xor(v[6], v[11]);
+    v[7] = xor(v[7], v[8]);
+    v[4] = xor(v[4], v[9]);
+    v[5] = rot7(v[5]);
+    v[6] = rot7(v[6]);
+    v[7] = rot7(v[7]);
+    v[4] = rot7(v[4]);
+}
+
+#[inline(always)]
+unsafe fn interleave128(a: __m256i, b: __m256i) -> (__m256i, __m256i) {
+    (
+        _mm256_permute2x128_si256(a, b, 0x20),
+        _mm256_permute2x128_si256(a, b, 0x31),
+    )
+}
+
+// Transpose an 8x8 matrix of 32-bit words, using the same unpack approach as
+// the BLAKE2b transpose_vecs in the parent module, with one more level of
+// interleaving.
+#[inline(always)]
+unsafe fn transpose_vecs(vecs: [__m256i; 8]) -> [__m256i; 8] {
+    // Interleave 32-bit lanes. The low unpack is lanes 00/11/44/55 and the
+    // high is 22/33/66/77.
+    let ab_0145 = _mm256_unpacklo_epi32(vecs[0], vecs[1]);
+    let ab_2367 = _mm256_unpackhi_epi32(vecs[0], vecs[1]);
+    let cd_0145 = _mm256_unpacklo_epi32(vecs[2], vecs[3]);
+    let cd_2367 = _mm256_unpackhi_epi32(vecs[2], vecs[3]);
+    let ef_0145 = _mm256_unpacklo_epi32(vecs[4], vecs[5]);
+    let ef_2367 = _mm256_unpackhi_epi32(vecs[4], vecs[5]);
+    let gh_0145 = _mm256_unpacklo_epi32(vecs[6], vecs[7]);
+    let gh_2367 = _mm256_unpackhi_epi32(vecs[6], vecs[7]);
+
+    // Interleave 64-bit lanes.
+    let abcd_04 = _mm256_unpacklo_epi64(ab_0145, cd_0145);
+    let abcd_15 = _mm256_unpackhi_epi64(ab_0145, cd_0145);
+    let abcd_26 = _mm256_unpacklo_epi64(ab_2367, cd_2367);
+    let abcd_37 = _mm256_unpackhi_epi64(ab_2367, cd_2367);
+    let efgh_04 = _mm256_unpacklo_epi64(ef_0145, gh_0145);
+    let efgh_15 = _mm256_unpackhi_epi64(ef_0145, gh_0145);
+    let efgh_26 = _mm256_unpacklo_epi64(ef_2367, gh_2367);
+    let efgh_37 = _mm256_unpackhi_epi64(ef_2367, gh_2367);
+
+    // Interleave 128-bit lanes.
+    let (abcdefgh_0, abcdefgh_4) = interleave128(abcd_04, efgh_04);
+    let (abcdefgh_1, abcdefgh_5) = interleave128(abcd_15, efgh_15);
+    let (abcdefgh_2, abcdefgh_6) = interleave128(abcd_26, efgh_26);
+    let (abcdefgh_3, abcdefgh_7) = interleave128(abcd_37, efgh_37);
+
+    [
+        abcdefgh_0, abcdefgh_1, abcdefgh_2, abcdefgh_3, abcdefgh_4, abcdefgh_5, abcdefgh_6,
+        abcdefgh_7,
+    ]
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn transpose8(words: [&StateWords; 8]) -> [u32x8; 8] {
+    let transposed = transpose_vecs([
+        loadu(words[0].as_ptr() as *const u8),
+        loadu(words[1].as_ptr() as *const u8),
+        loadu(words[2].as_ptr() as *const u8),
+        loadu(words[3].as_ptr() as *const u8),
+        loadu(words[4].as_ptr() as *const u8),
+        loadu(words[5].as_ptr() as *const u8),
+        loadu(words[6].as_ptr() as *const u8),
+        loadu(words[7].as_ptr() as *const u8),
+    ]);
+    mem::transmute::<[__m256i; 8], [u32x8; 8]>(transposed)
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn untranspose8(transposed: &[u32x8; 8], out: [&mut StateWords; 8]) {
+    let h_vecs = &*(transposed as *const [u32x8; 8] as *const [__m256i; 8]);
+    let untransposed = transpose_vecs(*h_vecs);
+    storeu(untransposed[0], out[0].as_mut_ptr());
+    storeu(untransposed[1], out[1].as_mut_ptr());
+    storeu(untransposed[2], out[2].as_mut_ptr());
+    storeu(untransposed[3], out[3].as_mut_ptr());
+    storeu(untransposed[4], out[4].as_mut_ptr());
+    storeu(untransposed[5], out[5].as_mut_ptr());
+    storeu(untransposed[6], out[6].as_mut_ptr());
+    storeu(untransposed[7], out[7].as_mut_ptr());
+}
+
+#[inline(always)]
+unsafe fn transpose_message_blocks(msgs: &[&Block; 8]) -> [__m256i; 16] {
+    let low = transpose_vecs([
+        loadu(msgs[0].as_ptr()),
+        loadu(msgs[1].as_ptr()),
+        loadu(msgs[2].as_ptr()),
+        loadu(msgs[3].as_ptr()),
+        loadu(msgs[4].as_ptr()),
+        loadu(msgs[5].as_ptr()),
+        loadu(msgs[6].as_ptr()),
+        loadu(msgs[7].as_ptr()),
+    ]);
+    let high = transpose_vecs([
+        loadu(msgs[0].as_ptr().add(32)),
+        loadu(msgs[1].as_ptr().add(32)),
+        loadu(msgs[2].as_ptr().add(32)),
+        loadu(msgs[3].as_ptr().add(32)),
+        loadu(msgs[4].as_ptr().add(32)),
+        loadu(msgs[5].as_ptr().add(32)),
+        loadu(msgs[6].as_ptr().add(32)),
+        loadu(msgs[7].as_ptr().add(32)),
+    ]);
+    [
+        low[0], low[1], low[2], low[3], low[4], low[5], low[6], low[7], high[0], high[1], high[2],
+        high[3], high[4], high[5], high[6], high[7],
+    ]
+}
+
+#[inline(always)]
+unsafe fn compress8_transposed_inline(
+    h_vecs: &mut [__m256i; 8],
+    msg_vecs: &[__m256i; 16],
+    count_low: __m256i,
+    count_high: __m256i,
+    lastblock: __m256i,
+    lastnode: __m256i,
+) {
+    let mut v = [
+        h_vecs[0],
+        h_vecs[1],
+        h_vecs[2],
+        h_vecs[3],
+        h_vecs[4],
+        h_vecs[5],
+        h_vecs[6],
+        h_vecs[7],
+        set1(IV[0]),
+        set1(IV[1]),
+        set1(IV[2]),
+        set1(IV[3]),
+        xor(set1(IV[4]), count_low),
+        xor(set1(IV[5]), count_high),
+        xor(set1(IV[6]), lastblock),
+        xor(set1(IV[7]), lastnode),
+    ];
+
+    blake2s_round_8x(&mut v, msg_vecs, 0);
+    blake2s_round_8x(&mut v, msg_vecs, 1);
+    blake2s_round_8x(&mut v, msg_vecs, 2);
+    blake2s_round_8x(&mut v, msg_vecs, 3);
+    blake2s_round_8x(&mut v, msg_vecs, 4);
+    blake2s_round_8x(&mut v, msg_vecs, 5);
+    blake2s_round_8x(&mut v, msg_vecs, 6);
+    blake2s_round_8x(&mut v, msg_vecs, 7);
+    blake2s_round_8x(&mut v, msg_vecs, 8);
+    blake2s_round_8x(&mut v, msg_vecs, 9);
+
+    h_vecs[0] = xor(xor(h_vecs[0], v[0]), v[8]);
+    h_vecs[1] = xor(xor(h_vecs[1], v[1]), v[9]);
+    h_vecs[2] = xor(xor(h_vecs[2], v[2]), v[10]);
+    h_vecs[3] = xor(xor(h_vecs[3], v[3]), v[11]);
+    h_vecs[4] = xor(xor(h_vecs[4], v[4]), v[12]);
+    h_vecs[5] = xor(xor(h_vecs[5], v[5]), v[13]);
+    h_vecs[6] = xor(xor(h_vecs[6], v[6]), v[14]);
+    h_vecs[7] = xor(xor(h_vecs[7], v[7]), v[15]);
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn compress8_transposed(
+    h_vecs: &mut [u32x8; 8],
+    msgs: [&Block; 8],
+    count_low: &u32x8,
+    count_high: &u32x8,
+    lastblock: &u32x8,
+    lastnode: &u32x8,
+) {
+    let m = transpose_message_blocks(&msgs);
+    compress8_transposed_inline(
+        &mut *(h_vecs as *mut [u32x8; 8] as *mut [__m256i; 8]),
+        &m,
+        mem::transmute::<u32x8, __m256i>(*count_low),
+        mem::transmute::<u32x8, __m256i>(*count_high),
+        mem::transmute::<u32x8, __m256i>(*lastblock),
+        mem::transmute::<u32x8, __m256i>(*lastnode),
+    );
+}
@@ -0,0 +1,125 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::*;
+use crate::guts::u32x8;
+
+// G is the mixing function, called eight times per round in the compression
+// function. This is the same as G in BLAKE2b, except with 32-bit words and
+// different rotation constants.
+#[inline(always)]
+fn g(v: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(12);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(8);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(7);
+}
+
+#[inline(always)]
+fn round(r: usize, m: &[u32; 16], v: &mut [u32; 16]) {
+    // Select the message schedule based on the round.
+    let s = SIGMA[r];
+
+    // Mix the columns.
+    g(v, 0, 4, 8, 12, m[s[0] as usize], m[s[1] as usize]);
+    g(v, 1, 5, 9, 13, m[s[2] as usize], m[s[3] as usize]);
+    g(v, 2, 6, 10, 14, m[s[4] as usize], m[s[5] as usize]);
+    g(v, 3, 7, 11, 15, m[s[6] as usize], m[s[7] as usize]);
+
+    // Mix the rows.
+    g(v, 0, 5, 10, 15, m[s[8] as usize], m[s[9] as usize]);
+    g(v, 1, 6, 11, 12, m[s[10] as usize], m[s[11] as usize]);
+    g(v, 2, 7, 8, 13, m[s[12] as usize], m[s[13] as usize]);
+    g(v, 3, 4, 9, 14, m[s[14] as usize], m[s[15] as usize]);
+}
+
+// H is the 8-word state vector. `msg` is BLOCKBYTES of input, possibly padded
+// with zero bytes in the final block. `count` is the number of bytes fed so
+// far, including in this call, though not including padding in the final call.
+pub fn compress(h: &mut StateWords, msg: &Block, count: u64, lastblock: u32, lastnode: u32) {
+    // Initialize the compression state.
+    let mut v = [
+        h[0],
+        h[1],
+        h[2],
+        h[3],
+        h[4],
+        h[5],
+        h[6],
+        h[7],
+        IV[0],
+        IV[1],
+        IV[2],
+        IV[3],
+        IV[4] ^ count as u32,
+        IV[5] ^ (count >> 32) as u32,
+        IV[6] ^ lastblock,
+        IV[7] ^ lastnode,
+    ];
+
+    // Parse the message bytes as ints in little endian order.
+    let mut m = [0; 16];
+    LittleEndian::read_u32_into(msg, &mut m);
+
+    round(0, &m, &mut v);
+    round(1, &m, &mut v);
+    round(2, &m, &mut v);
+    round(3, &m, &mut v);
+    round(4, &m, &mut v);
+    round(5, &m, &mut v);
+    round(6, &m, &mut v);
+    round(7, &m, &mut v);
+    round(8, &m, &mut v);
+    round(9, &m, &mut v);
+
+    h[0] ^= v[0] ^ v[8];
+    h[1] ^= v[1] ^ v[9];
+    h[2] ^= v[2] ^ v[10];
+    h[3] ^= v[3] ^ v[11];
+    h[4] ^= v[4] ^ v[12];
+    h[5] ^= v[5] ^ v[13];
+    h[6] ^= v[6] ^ v[14];
+    h[7] ^= v[7] ^ v[15];
+}
+
+pub fn transpose8(words: [&StateWords; 8]) -> [u32x8; 8] {
+    let mut transposed = [u32x8([0; 8]); 8];
+    for (i, state) in words.iter().enumerate() {
+        for (vec, &word) in transposed.iter_mut().zip(state.iter()) {
+            vec[i] = word;
+        }
+    }
+    transposed
+}
+
+pub fn untranspose8(transposed: &[u32x8; 8], mut out: [&mut StateWords; 8]) {
+    for (i, state) in out.iter_mut().enumerate() {
+        for (word, vec) in state.iter_mut().zip(transposed.iter()) {
+            *word = vec[i];
+        }
+    }
+}
+
+pub fn compress8_transposed(
+    h_vecs: &mut [u32x8; 8],
+    msgs: [&Block; 8],
+    count_low: &u32x8,
+    count_high: &u32x8,
+    lastblock: &u32x8,
+    lastnode: &u32x8,
+) {
+    for (i, msg) in msgs.iter().enumerate() {
+        let mut state = [0; 8];
+        for (word, vec) in state.iter_mut().zip(h_vecs.iter()) {
+            *word = vec[i];
+        }
+        let count = count_low[i] as u64 + ((count_high[i] as u64) << 32);
+        compress(&mut state, msg, count, lastblock[i], lastnode[i]);
+        for (vec, &word) in h_vecs.iter_mut().zip(state.iter()) {
+            vec[i] = word;
+        }
+    }
+}
@@ -0,0 +1,468 @@
+#[cfg(target_arch = "x86")]
+use core::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use super::*;
+use crate::guts::u32x4;
+use crate::guts::u32x8;
+use byteorder::{ByteOrder, LittleEndian};
+use core::mem;
+use core::ptr;
+
+#[inline(always)]
+unsafe fn loadu(src: *const u32) -> __m128i {
+    _mm_loadu_si128(src as *const __m128i)
+}
+
+#[inline(always)]
+unsafe fn storeu(src: __m128i, dest: *mut u32) {
+    _mm_storeu_si128(dest as *mut __m128i, src)
+}
+
+#[inline(always)]
+unsafe fn add(a: __m128i, b: __m128i) -> __m128i {
+    _mm_add_epi32(a, b)
+}
+
+#[inline(always)]
+unsafe fn xor(a: __m128i, b: __m128i) -> __m128i {
+    _mm_xor_si128(a, b)
+}
+
+// Adapted from https://github.com/rust-lang-nursery/stdsimd/pull/479.
+macro_rules! _MM_SHUFFLE {
+    ($z:expr, $y:expr, $x:expr, $w:expr) => {
+        ($z << 6) | ($y << 4) | ($x << 2) | $w
+    };
+}
+
+#[inline(always)]
+unsafe fn rot16(x: __m128i) -> __m128i {
+    let rotate16 = _mm_setr_epi8(2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9, 14, 15, 12, 13);
+    _mm_shuffle_epi8(x, rotate16)
+}
+
+#[inline(always)]
+unsafe fn rot12(x: __m128i) -> __m128i {
+    _mm_or_si128(_mm_srli_epi32(x, 12), _mm_slli_epi32(x, 32 - 12))
+}
+
+#[inline(always)]
+unsafe fn rot8(x: __m128i) -> __m128i {
+    let rotate8 = _mm_setr_epi8(1, 2, 3, 0, 5, 6, 7, 4, 9, 10, 11, 8, 13, 14, 15, 12);
+    _mm_shuffle_epi8(x, rotate8)
+}
+
+#[inline(always)]
+unsafe fn rot7(x: __m128i) -> __m128i {
+    _mm_or_si128(_mm_srli_epi32(x, 7), _mm_slli_epi32(x, 32 - 7))
+}
+
+#[inline(always)]
+unsafe fn g1(
+    row1: &mut __m128i,
+    row2: &mut __m128i,
+    row3: &mut __m128i,
+    row4: &mut __m128i,
+    m: __m128i,
+) {
+    *row1 = add(add(*row1, m), *row2);
+    *row4 = xor(*row4, *row1);
+    *row4 = rot16(*row4);
+    *row3 = add(*row3, *row4);
+    *row2 = xor(*row2, *row3);
+    *row2 = rot12(*row2);
+}
+
+#[inline(always)]
+unsafe fn g2(
+    row1: &mut __m128i,
+    row2: &mut __m128i,
+    row3: &mut __m128i,
+    row4: &mut __m128i,
+    m: __m128i,
+) {
+    *row1 = add(add(*row1, m), *row2);
+    *row4 = xor(*row4, *row1);
+    *row4 = rot8(*row4);
+    *row3 = add(*row3, *row4);
+    *row2 = xor(*row2, *row3);
+    *row2 = rot7(*row2);
+}
+
+// Note the optimization here of leaving row2 as the unrotated row, rather than
+// row1. The diagonal message loads in round() are adjusted to compensate for
+// this. See discussion at https://github.com/sneves/blake2-avx2/pull/4
+#[inline(always)]
+unsafe fn diagonalize(row1: &mut __m128i, row3: &mut __m128i, row4: &mut __m128i) {
+    *row1 = _mm_shuffle_epi32(*row1, _MM_SHUFFLE!(2, 1, 0, 3));
+    *row4 = _mm_shuffle_epi32(*row4, _MM_SHUFFLE!(1, 0, 3, 2));
+    *row3 = _mm_shuffle_epi32(*row3, _MM_SHUFFLE!(0, 3, 2, 1));
+}
+
+#[inline(always)]
+unsafe fn undiagonalize(row1: &mut __m128i, row3: &mut __m128i, row4: &mut __m128i) {
+    *row1 = _mm_shuffle_epi32(*row1, _MM_SHUFFLE!(0, 3, 2, 1));
+    *row4 = _mm_shuffle_epi32(*row4, _MM_SHUFFLE!(1, 0, 3, 2));
+    *row3 = _mm_shuffle_epi32(*row3, _MM_SHUFFLE!(2, 1, 0, 3));
+}
+
+// Gather four message words for one half of a round, following the message
+// schedule. `first` is the index into SIGMA of the word for lane 0, and the
+// other lanes use every other index after that.
+#[inline(always)]
+unsafe fn gather(m: &[u32; 16], s: &[u8; 16], first: usize) -> __m128i {
+    _mm_set_epi32(
+        m[s[first + 6] as usize] as i32,
+        m[s[first + 4] as usize] as i32,
+        m[s[first + 2] as usize] as i32,
+        m[s[first] as usize] as i32,
+    )
+}
+
+#[inline(always)]
+unsafe fn round(
+    row1: &mut __m128i,
+    row2: &mut __m128i,
+    row3: &mut __m128i,
+    row4: &mut __m128i,
+    m: &[u32; 16],
+    r: usize,
+) {
+    let s = &SIGMA[r];
+    // Mix the columns.
+    g1(row1, row2, row3, row4, gather(m, s, 0));
+    g2(row1, row2, row3, row4, gather(m, s, 1));
+    diagonalize(row1, row3, row4);
+    // Mix the diagonals. Because row2 stays put, lane 0 of the diagonal step
+    // here is the fourth diagonal, (3, 4, 9, 14), rather than the first.
+    let diag1 = gather(m, s, 8);
+    let diag2 = gather(m, s, 9);
+    g1(
+        row1,
+        row2,
+        row3,
+        row4,
+        _mm_shuffle_epi32(diag1, _MM_SHUFFLE!(2, 1, 0, 3)),
+    );
+    g2(
+        row1,
+        row2,
+        row3,
+        row4,
+        _mm_shuffle_epi32(diag2, _MM_SHUFFLE!(2, 1, 0, 3)),
+    );
+    undiagonalize(row1, row3, row4);
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn compress(h: &mut StateWords, msg: &Block, count: u64, lastblock: u32, lastnode: u32) {
+    let mut m = [0; 16];
+    LittleEndian::read_u32_into(msg, &mut m);
+
+    let mut row1 = loadu(h.as_ptr().add(0));
+    let mut row2 = loadu(h.as_ptr().add(4));
+    let mut row3 = loadu(IV.as_ptr().add(0));
+    let mut row4 = xor(
+        loadu(IV.as_ptr().add(4)),
+        _mm_set_epi32(
+            lastnode as i32,
+            lastblock as i32,
+            (count >> 32) as i32,
+            count as i32,
+        ),
+    );
+    let h_low = row1;
+    let h_high = row2;
+
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 0);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 1);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 2);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 3);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 4);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 5);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 6);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 7);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 8);
+    round(&mut row1, &mut row2, &mut row3, &mut row4, &m, 9);
+
+    storeu(xor(h_low, xor(row1, row3)), h.as_mut_ptr().add(0));
+    storeu(xor(h_high, xor(row2, row4)), h.as_mut_ptr().add(4));
+}
+
+#[inline(always)]
+unsafe fn blake2s_round_4x(v: &mut [__m128i; 16], m: &[__m128i; 16], r: usize) {
+    v[0] = add(v[0], m[SIGMA[r][0] as usize]);
+    v[1] = add(v[1], m[SIGMA[r][2] as usize]);
+    v[2] = add(v[2], m[SIGMA[r][4] as usize]);
+    v[3] = add(v[3], m[SIGMA[r][6] as usize]);
+    v[0] = add(v[0], v[4]);
+    v[1] = add(v[1], v[5]);
+    v[2] = add(v[2], v[6]);
+    v[3] = add(v[3], v[7]);
+    v[12] = xor(v[12], v[0]);
+    v[13] = xor(v[13], v[1]);
+    v[14] = xor(v[14], v[2]);
+    v[15] = xor(v[15], v[3]);
+    v[12] = rot16(v[12]);
+    v[13] = rot16(v[13]);
+    v[14] = rot16(v[14]);
+    v[15] = rot16(v[15]);
+    v[8] = add(v[8], v[12]);
+    v[9] = add(v[9], v[13]);
+    v[10] = add(v[10], v[14]);
+    v[11] = add(v[11], v[15]);
+    v[4] = xor(v[4], v[8]);
+    v[5] = xor(v[5], v[9]);
+    v[6] = xor(v[6], v[10]);
+    v[7] = xor(v[7], v[11]);
+    v[4] = rot12(v[4]);
+    v[5] = rot12(v[5]);
+    v[6] = rot12(v[6]);
+    v[7] = rot12(v[7]);
+    v[0] = add(v[0], m[SIGMA[r][1] as usize]);
+    v[1] = add(v[1], m[SIGMA[r][3] as usize]);
+    v[2] = add(v[2], m[SIGMA[r][5] as usize]);
+    v[3] = add(v[3], m[SIGMA[r][7] as usize]);
+    v[0] = add(v[0], v[4]);
+    v[1] = add(v[1], v[5]);
+    v[2] = add(v[2], v[6]);
+    v[3] = add(v[3], v[7]);
+    v[12] = xor(v[12], v[0]);
+    v[13] = xor(v[13], v[1]);
+    v[14] = xor(v[14], v[2]);
+    v[15] = xor(v[15], v[3]);
+    v[12] = rot8(v[12]);
+    v[13] = rot8(v[13]);
+    v[14] = rot8(v[14]);
+    v[15] = rot8(v[15]);
+    v[8] = add(v[8], v[12]);
+    v[9] = add(v[9], v[13]);
+    v[10] = add(v[10], v[14]);
+    v[11] = add(v[11], v[15]);
+    v[4] = xor(v[4], v[8]);
+    v[5] = xor(v[5], v[9]);
+    v[6] = xor(v[6], v[10]);
+    v[7] = xor(v[7], v[11]);
+    v[4] = rot7(v[4]);
+    v[5] = rot7(v[5]);
+    v[6] = rot7(v[6]);
+    v[7] = rot7(v[7]);
+
+    v[0] = add(v[0], m[SIGMA[r][8] as usize]);
+    v[1] = add(v[1], m[SIGMA[r][10] as usize]);
+    v[2] = add(v[2], m[SIGMA[r][12] as usize]);
+    v[3] = add(v[3], m[SIGMA[r][14] as usize]);
+    v[0] = add(v[0], v[5]);
+    v[1] = add(v[1], v[6]);
+    v[2] = add(v[2], v[7]);
+    v[3] = add(v[3], v[4]);
+    v[15] = xor(v[15], v[0]);
+    v[12] = xor(v[12], v[1]);
+    v[13] = xor(v[13], v[2]);
+    v[14] = xor(v[14], v[3]);
+    v[15] = rot16(v[15]);
+    v[12] = rot16(v[12]);
+    v[13] = rot16(v[13]);
+    v[14] = rot16(v[14]);
+    v[10] = add(v[10], v[15]);
+    v[11] = add(v[11], v[12]);
+    v[8] = add(v[8], v[13]);
+    v[9] = add(v[9], v[14]);
+    v[5] = xor(v[5], v[10]);
+    v[6] = xor(v[6], v[11]);
+    v[7] = xor(v[7], v[8]);
+    v[4] = xor(v[4], v[9]);
+    v[5] = rot12(v[5]);
+    v[6] = rot12(v[6]);
+    v[7] = rot12(v[7]);
+    v[4] = rot12(v[4]);
+    v[0] = add(v[0], m[SIGMA[r][9] as usize]);
+    v[1] = add(v[1], m[SIGMA[r][11] as usize]);
+    v[2] = add(v[2], m[SIGMA[r][13] as usize]);
+    v[3] = add(v[3], m[SIGMA[r][15] as usize]);
+    v[0] = add(v[0], v[5]);
+    v[1] = add(v[1], v[6]);
+    v[2] = add(v[2], v[7]);
+    v[3] = add(v[3], v[4]);
+    v[15] = xor(v[15], v[0]);
+    v[12] = xor(v[12], v[1]);
+    v[13] = xor(v[13], v[2]);
+    v[14] = xor(v[14], v[3]);
+    v[15] = rot8(v[15]);
+    v[12] = rot8(v[12]);
+    v[13] = rot8(v[13]);
+    v[14] = rot8(v[14]);
+    v[10] = add(v[10], v[15]);
+    v[11] = add(v[11], v[12]);
+    v[8] = add(v[8], v[13]);
+    v[9] = add(v[9], v[14]);
+    v[5] = xor(v[5], v[10]);
+    v[6] = xor(v[6], v[11]);
+    v[7] = xor(v[7], v[8]);
+    v[4] = xor(v[4], v[9]);
+    v[5] = rot7(v[5]);
+    v[6] = rot7(v[6]);
+    v[7] = rot7(v[7]);
+    v[4] = rot7(v[4]);
+}
+
+#[inline(always)]
+unsafe fn load_transposed_word(msgs: &[&Block; 4], i: usize) -> __m128i {
+    _mm_set_epi32(
+        ptr::read_unaligned(msgs[3].as_ptr().add(i * 4) as *const i32),
+        ptr::read_unaligned(msgs[2].as_ptr().add(i * 4) as *const i32),
+        ptr::read_unaligned(msgs[1].as_ptr().add(i * 4) as *const i32),
+        ptr::read_unaligned(msgs[0].as_ptr().add(i * 4) as *const i32),
+    )
+}
+
+#[inline(always)]
+unsafe fn transpose_message_blocks(msgs: &[&Block; 4]) -> [__m128i; 16] {
+    [
+        load_transposed_word(msgs, 0),
+        load_transposed_word(msgs, 1),
+        load_transposed_word(msgs, 2),
+        load_transposed_word(msgs, 3),
+        load_transposed_word(msgs, 4),
+        load_transposed_word(msgs, 5),
+        load_transposed_word(msgs, 6),
+        load_transposed_word(msgs, 7),
+        load_transposed_word(msgs, 8),
+        load_transposed_word(msgs, 9),
+        load_transposed_word(msgs, 10),
+        load_transposed_word(msgs, 11),
+        load_transposed_word(msgs, 12),
+        load_transposed_word(msgs, 13),
+        load_transposed_word(msgs, 14),
+        load_transposed_word(msgs, 15),
+    ]
+}
+
+#[inline(always)]
+unsafe fn compress4_transposed_inline(
+    h_vecs: &mut [__m128i; 8],
+    msg_vecs: &[__m128i; 16],
+    count_low: __m128i,
+    count_high: __m128i,
+    lastblock: __m128i,
+    lastnode: __m128i,
+) {
+    let mut v = [
+        h_vecs[0],
+        h_vecs[1],
+        h_vecs[2],
+        h_vecs[3],
+        h_vecs[4],
+        h_vecs[5],
+        h_vecs[6],
+        h_vecs[7],
+        _mm_set1_epi32(IV[0] as i32),
+        _mm_set1_epi32(IV[1] as i32),
+        _mm_set1_epi32(IV[2] as i32),
+        _mm_set1_epi32(IV[3] as i32),
+        xor(_mm_set1_epi32(IV[4] as i32), count_low),
+        xor(_mm_set1_epi32(IV[5] as i32), count_high),
+        xor(_mm_set1_epi32(IV[6] as i32), lastblock),
+        xor(_mm_set1_epi32(IV[7] as i32), lastnode),
+    ];
+
+    blake2s_round_4x(&mut v, msg_vecs, 0);
+    blake2s_round_4x(&mut v, msg_vecs, 1);
+    blake2s_round_4x(&mut v, msg_vecs, 2);
+    blake2s_round_4x(&mut v, msg_vecs, 3);
+    blake2s_round_4x(&mut v, msg_vecs, 4);
+    blake2s_round_4x(&mut v, msg_vecs, 5);
+    blake2s_round_4x(&mut v, msg_vecs, 6);
+    blake2s_round_4x(&mut v, msg_vecs, 7);
+    blake2s_round_4x(&mut v, msg_vecs, 8);
+    blake2s_round_4x(&mut v, msg_vecs, 9);
+
+    h_vecs[0] = xor(xor(h_vecs[0], v[0]), v[8]);
+    h_vecs[1] = xor(xor(h_vecs[1], v[1]), v[9]);
+    h_vecs[2] = xor(xor(h_vecs[2], v[2]), v[10]);
+    h_vecs[3] = xor(xor(h_vecs[3], v[3]), v[11]);
+    h_vecs[4] = xor(xor(h_vecs[4], v[4]), v[12]);
+    h_vecs[5] = xor(xor(h_vecs[5], v[5]), v[13]);
+    h_vecs[6] = xor(xor(h_vecs[6], v[6]), v[14]);
+    h_vecs[7] = xor(xor(h_vecs[7], v[7]), v[15]);
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn compress4_transposed(
+    h_vecs: &mut [u32x4; 8],
+    msgs: [&Block; 4],
+    count_low: &u32x4,
+    count_high: &u32x4,
+    lastblock: &u32x4,
+    lastnode: &u32x4,
+) {
+    let m = transpose_message_blocks(&msgs);
+    compress4_transposed_inline(
+        &mut *(h_vecs as *mut [u32x4; 8] as *mut [__m128i; 8]),
+        &m,
+        mem::transmute::<u32x4, __m128i>(*count_low),
+        mem::transmute::<u32x4, __m128i>(*count_high),
+        mem::transmute::<u32x4, __m128i>(*lastblock),
+        mem::transmute::<u32x4, __m128i>(*lastnode),
+    );
+}
+
+#[inline(always)]
+unsafe fn load_from_8(words: &[u32x8; 8], i: usize) -> [u32x4; 8] {
+    [
+        words[0].split()[i],
+        words[1].split()[i],
+        words[2].split()[i],
+        words[3].split()[i],
+        words[4].split()[i],
+        words[5].split()[i],
+        words[6].split()[i],
+        words[7].split()[i],
+    ]
+}
+
+#[inline(always)]
+unsafe fn store_to_8(whole: &mut [u32x8; 8], part: &[u32x4; 8], i: usize) {
+    whole[0].split_mut()[i] = part[0];
+    whole[1].split_mut()[i] = part[1];
+    whole[2].split_mut()[i] = part[2];
+    whole[3].split_mut()[i] = part[3];
+    whole[4].split_mut()[i] = part[4];
+    whole[5].split_mut()[i] = part[5];
+    whole[6].split_mut()[i] = part[6];
+    whole[7].split_mut()[i] = part[7];
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn compress8_transposed(
+    h_vecs: &mut [u32x8; 8],
+    msgs: [&Block; 8],
+    count_low: &u32x8,
+    count_high: &u32x8,
+    lastblock: &u32x8,
+    lastnode: &u32x8,
+) {
+    let mut state0 = load_from_8(h_vecs, 0);
+    compress4_transposed(
+        &mut state0,
+        [msgs[0], msgs[1], msgs[2], msgs[3]],
+        &count_low.split()[0],
+        &count_high.split()[0],
+        &lastblock.split()[0],
+        &lastnode.split()[0],
+    );
+    store_to_8(h_vecs, &state0, 0);
+
+    let mut state1 = load_from_8(h_vecs, 1);
+    compress4_transposed(
+        &mut state1,
+        [msgs[4], msgs[5], msgs[6], msgs[7]],
+        &count_low.split()[1],
+        &count_high.split()[1],
+        &lastblock.split()[1],
+        &lastnode.split()[1],
+    );
+    store_to_8(h_vecs, &state1, 1);
+}
@@ -0,0 +1,574 @@
+//! An implementation of BLAKE2sp, a variant of BLAKE2s that takes advantage of the parallelism of
+//! modern processors.
+//!
+//! BLAKE2sp hashes eight leaves in parallel, which lets the AVX2 implementation use all eight
+//! 32-bit lanes of each vector. As with [BLAKE2bp](../blake2bp/index.html), note that it's a
+//! different hash function, and it gives a different hash from BLAKE2s for the same input.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::blake2sp;
+//!
+//! let hash = blake2sp::Params::new()
+//!     .hash_length(16)
+//!     .key(b"Squeamish Ossifrage")
+//!     .to_state()
+//!     .update(b"foo")
+//!     .update(b"bar")
+//!     .update(b"baz")
+//!     .finalize();
+//! assert_eq!("9a604f1653c25063debb3aeea79671c0", &hash.to_hex());
+//! ```
+
+use crate::blake2s::Hash;
+use crate::blake2s::Params as Blake2sParams;
+use crate::blake2s::BLOCKBYTES;
+use crate::blake2s::KEYBYTES;
+use crate::blake2s::OUTBYTES;
+use crate::guts;
+use byteorder::{ByteOrder, LittleEndian};
+use core::cmp;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std;
+
+const DEGREE: usize = 8;
+
+/// Compute the BLAKE2sp hash of a slice of bytes, using default parameters.
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::blake2sp::blake2sp;
+/// let expected = "050dc5786037ea72cb9ed9d0324afcab03c97ec02e8c47368fc5dfb4cf49d8c9";
+/// let hash = blake2sp(b"foo");
+/// assert_eq!(expected, &hash.to_hex());
+/// ```
+pub fn blake2sp(input: &[u8]) -> Hash {
+    State::new().update(input).finalize()
+}
+
+/// A parameter builder for BLAKE2sp, just like the [`Params`](../blake2s/struct.Params.html) type
+/// for BLAKE2s.
+///
+/// This builder only supports configuring the hash length and a secret key. This matches the
+/// options provided by the [reference
+/// implementation](https://github.com/BLAKE2/BLAKE2/blob/320c325437539ae91091ce62efec1913cd8093c2/ref/blake2.h#L158-L160).
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::blake2sp;
+/// let mut state = blake2sp::Params::new().hash_length(16).to_state();
+/// ```
+#[derive(Clone)]
+pub struct Params {
+    hash_length: u8,
+    key_length: u8,
+    key: [u8; KEYBYTES],
+}
+
+impl Params {
+    /// Equivalent to `Params::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Construct a BLAKE2sp `State` object based on these parameters.
+    pub fn to_state(&self) -> State {
+        State::with_params(self)
+    }
+
+    /// Set the length of the final hash, from 1 to `OUTBYTES` (32). Apart from controlling the
+    /// length of the final `Hash`, this is also associated data, and changing it will result in a
+    /// totally different hash.
+    pub fn hash_length(&mut self, length: usize) -> &mut Self {
+        assert!(
+            (1..=OUTBYTES).contains(&length),
+            "Bad hash length: {}",
+            length
+        );
+        self.hash_length = length as u8;
+        self
+    }
+
+    /// Use a secret key, so that BLAKE2sp acts as a MAC. The maximum key length is `KEYBYTES`
+    /// (32). An empty key is equivalent to having no key at all.
+    pub fn key(&mut self, key: &[u8]) -> &mut Self {
+        assert!(key.len() <= KEYBYTES, "Bad key length: {}", key.len());
+        self.key_length = key.len() as u8;
+        self.key = [0; KEYBYTES];
+        self.key[..key.len()].copy_from_slice(key);
+        self
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            hash_length: OUTBYTES as u8,
+            key_length: 0,
+            key: [0; KEYBYTES],
+        }
+    }
+}
+
+impl fmt::Debug for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Params {{ hash_length: {}, key_length: {} }}",
+            self.hash_length,
+            // NB: Don't print the key itself. Debug shouldn't leak secrets.
+            self.key_length,
+        )
+    }
+}
+
+/// An incremental hasher for BLAKE2sp, just like the [`State`](../blake2s/struct.State.html) type
+/// for BLAKE2s.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::blake2sp;
+///
+/// let mut state = blake2sp::State::new();
+/// state.update(b"foo");
+/// state.update(b"bar");
+/// let hash = state.finalize();
+///
+/// let expected = "268120e51df583c61d6bfb7915f1c8ead299696c42f413092cd0b2247e1a388d";
+/// assert_eq!(expected, &hash.to_hex());
+/// ```
+#[derive(Clone)]
+pub struct State {
+    transposed_leaf_words: [guts::u32x8; 8],
+    root_words: [u32; 8],
+    // Note that this buffer is twice as large as what compress8 needs. That guarantees that we
+    // have enough input when we compress to know we don't need to finalize any of the leaves.
+    buf: [u8; 2 * DEGREE * BLOCKBYTES],
+    buflen: u16,
+    count: u64,
+    hash_length: u8,
+    implementation: guts::Implementation,
+}
+
+impl State {
+    /// Equivalent to `State::default()` or `Params::default().to_state()`.
+    pub fn new() -> Self {
+        Self::with_params(&Params::default())
+    }
+
+    fn with_params(params: &Params) -> Self {
+        let implementation = guts::Implementation::detect();
+        let mut base_params = Blake2sParams::new();
+        base_params
+            .hash_length(params.hash_length as usize)
+            .key(&params.key[..params.key_length as usize])
+            .fanout(DEGREE as u8)
+            .max_depth(2)
+            .max_leaf_length(0)
+            // As in BLAKE2bp, inner_hash_length is always OUTBYTES, regardless of the
+            // hash_length parameter. This matches the reference implementation:
+            // https://github.com/BLAKE2/BLAKE2/blob/320c325437539ae91091ce62efec1913cd8093c2/ref/blake2sp-ref.c#L53
+            .inner_hash_length(OUTBYTES);
+        let leaf_words = |worker_index| {
+            base_params
+                .clone()
+                .node_offset(worker_index)
+                .node_depth(0)
+                // Note that setting the last_node flag here has no effect,
+                // because it isn't included in the state words.
+                .to_state_words()
+        };
+        let transposed_leaf_words = implementation.blake2s_transpose8([
+            &leaf_words(0),
+            &leaf_words(1),
+            &leaf_words(2),
+            &leaf_words(3),
+            &leaf_words(4),
+            &leaf_words(5),
+            &leaf_words(6),
+            &leaf_words(7),
+        ]);
+        let root_words = base_params
+            .clone()
+            .node_offset(0)
+            .node_depth(1)
+            // Note that setting the last_node flag here has no effect, because
+            // it isn't included in the state words.
+            .to_state_words();
+
+        // If a key is set, initalize the buffer to contain the key bytes. As
+        // in BLAKE2bp, only the leaves hash key bytes, and the root doesn't.
+        // This is corroborated by the official test vectors; see
+        // tests/vector_tests.rs.
+        let mut buf = [0; 2 * DEGREE * BLOCKBYTES];
+        let mut buflen = 0;
+        if params.key_length > 0 {
+            for i in 0..DEGREE {
+                let keybytes = &params.key[..params.key_length as usize];
+                buf[i * BLOCKBYTES..][..keybytes.len()].copy_from_slice(keybytes);
+                buflen = BLOCKBYTES * DEGREE;
+            }
+        }
+
+        Self {
+            transposed_leaf_words,
+            root_words,
+            buf,
+            buflen: buflen as u16,
+            count: 0, // count gets updated in self.compress()
+            hash_length: params.hash_length,
+            implementation,
+        }
+    }
+
+    fn fill_buf(&mut self, input: &mut &[u8]) {
+        let take = cmp::min(self.buf.len() - self.buflen as usize, input.len());
+        self.buf[self.buflen as usize..self.buflen as usize + take].copy_from_slice(&input[..take]);
+        self.buflen += take as u16;
+        *input = &input[take..];
+    }
+
+    fn compress(
+        input: &[u8; DEGREE * BLOCKBYTES],
+        state: &mut [guts::u32x8; 8],
+        count: &mut u64,
+        implementation: guts::Implementation,
+    ) {
+        let msg_refs = array_refs!(
+            input, BLOCKBYTES, BLOCKBYTES, BLOCKBYTES, BLOCKBYTES, BLOCKBYTES, BLOCKBYTES,
+            BLOCKBYTES, BLOCKBYTES
+        );
+        // Note that count is incremented by *one* block, not eight.
+        *count += BLOCKBYTES as u64;
+        let count_low = guts::u32x8([*count as u32; 8]);
+        let count_high = guts::u32x8([(*count >> 32) as u32; 8]);
+        let lastblock = guts::u32x8([0; 8]);
+        let lastnode = guts::u32x8([0; 8]);
+        implementation.blake2s_compress8(
+            state,
+            [
+                msg_refs.0, msg_refs.1, msg_refs.2, msg_refs.3, msg_refs.4, msg_refs.5, msg_refs.6,
+                msg_refs.7,
+            ],
+            &count_low,
+            &count_high,
+            &lastblock,
+            &lastnode,
+        );
+    }
+
+    /// Add input to the hash. You can call `update` any number of times.
+    pub fn update(&mut self, mut input: &[u8]) -> &mut Self {
+        // The buffering logic here is the same as in BLAKE2bp, just with eight leaves instead of
+        // four. See the comments there.
+        if self.buflen > 0 {
+            self.fill_buf(&mut input);
+            if !input.is_empty() {
+                Self::compress(
+                    array_ref!(self.buf, 0, DEGREE * BLOCKBYTES),
+                    &mut self.transposed_leaf_words,
+                    &mut self.count,
+                    self.implementation,
+                );
+                self.buflen -= (DEGREE * BLOCKBYTES) as u16;
+                if input.len() > (DEGREE - 1) * BLOCKBYTES {
+                    Self::compress(
+                        array_ref!(self.buf, DEGREE * BLOCKBYTES, DEGREE * BLOCKBYTES),
+                        &mut self.transposed_leaf_words,
+                        &mut self.count,
+                        self.implementation,
+                    );
+                    self.buflen = 0;
+                } else {
+                    let (left, right) = self.buf.split_at_mut(DEGREE * BLOCKBYTES);
+                    left[..self.buflen as usize].copy_from_slice(&right[..self.buflen as usize]);
+                }
+            }
+        }
+
+        // While there are more than 15 input blocks coming, then we know that we can perform a
+        // compression and still have more input coming for each leaf. (We also know that the
+        // buffer must have been emptied above.)
+        while input.len() > ((2 * DEGREE) - 1) * BLOCKBYTES {
+            debug_assert_eq!(0, self.buflen);
+            let block = array_ref!(input, 0, DEGREE * BLOCKBYTES);
+            Self::compress(
+                block,
+                &mut self.transposed_leaf_words,
+                &mut self.count,
+                self.implementation,
+            );
+            input = &input[DEGREE * BLOCKBYTES..];
+        }
+
+        // Buffer any remaining input, to be either compressed or finalized in a subsequent call.
+        self.fill_buf(&mut input);
+        debug_assert_eq!(0, input.len());
+        self
+    }
+
+    /// Finalize the state and return a `Hash`. This method is idempotent, and calling it multiple
+    /// times will give the same result. It's also possible to `update` with more input in between.
+    pub fn finalize(&mut self) -> Hash {
+        // Zero the buffer tail, since it might contain bytes from previous
+        // compressions.
+        let buflen = self.buflen as usize;
+        for i in buflen..self.buf.len() {
+            self.buf[i] = 0;
+        }
+
+        // Split the buffer into an array of blocks.
+        let mut blocks = [&[0; BLOCKBYTES]; 2 * DEGREE];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            *block = array_ref!(self.buf, i * BLOCKBYTES, BLOCKBYTES);
+        }
+
+        // Clone the leaf words. That keeps this method idempotent.
+        let mut leaves_copy = self.transposed_leaf_words;
+
+        // Count how many bytes each leaf still needs to compress. Note that
+        // even if the number is zero, each leaf is going to get at least one
+        // more compression to finalize it.
+        let remaining_bytes_fn = |i: usize| {
+            let first_block_start = i * BLOCKBYTES;
+            let first_block_end = first_block_start + BLOCKBYTES;
+            let second_block_start = (DEGREE + i) * BLOCKBYTES;
+            let second_block_end = second_block_start + BLOCKBYTES;
+            if buflen < first_block_start {
+                0
+            } else if buflen < first_block_end {
+                buflen - first_block_start
+            } else if buflen < second_block_start {
+                BLOCKBYTES
+            } else if buflen < second_block_end {
+                BLOCKBYTES + buflen - second_block_start
+            } else {
+                2 * BLOCKBYTES
+            }
+        };
+        let mut remaining = [0; DEGREE];
+        for (i, rem) in remaining.iter_mut().enumerate() {
+            *rem = remaining_bytes_fn(i);
+        }
+
+        // While all leaves still have compressions remaining, run them in a
+        // batch. This might finalize some of the leaves. This loop will either
+        // run once (even if some leaves have no input) or twice (if all leaves
+        // have more than one block).
+        let mut blocks_handled = 0;
+        let mut count = self.count;
+        loop {
+            // Unlike BLAKE2bp, the BLAKE2sp counter is small enough to
+            // overflow the low word in practice, so compute each lane's full
+            // count before splitting it.
+            let mut count_low = guts::u32x8([0; 8]);
+            let mut count_high = guts::u32x8([0; 8]);
+            let mut lastblock = guts::u32x8([0; 8]);
+            for i in 0..DEGREE {
+                let take = cmp::min(BLOCKBYTES, remaining[i]);
+                remaining[i] -= take;
+                let lane_count = count + take as u64;
+                count_low[i] = lane_count as u32;
+                count_high[i] = (lane_count >> 32) as u32;
+                lastblock[i] = if remaining[i] == 0 { !0 } else { 0 };
+            }
+            let mut lastnode = guts::u32x8([0; 8]);
+            lastnode[DEGREE - 1] = lastblock[DEGREE - 1];
+            self.implementation.blake2s_compress8(
+                &mut leaves_copy,
+                [
+                    blocks[blocks_handled],
+                    blocks[blocks_handled + 1],
+                    blocks[blocks_handled + 2],
+                    blocks[blocks_handled + 3],
+                    blocks[blocks_handled + 4],
+                    blocks[blocks_handled + 5],
+                    blocks[blocks_handled + 6],
+                    blocks[blocks_handled + 7],
+                ],
+                &count_low,
+                &count_high,
+                &lastblock,
+                &lastnode,
+            );
+            blocks_handled += DEGREE;
+            // Note that at this point `count` only applies to leaves that
+            // haven't been finalized.
+            count += BLOCKBYTES as u64;
+            if remaining.contains(&0) {
+                break;
+            }
+        }
+
+        // We just finished all the batch compressions we could. Some of the
+        // leaves might have one more block left to finalize them. Untranspose
+        // the state and then finalize those leaves, if any.
+        let mut leaves_untransposed = [[0u32; 8]; DEGREE];
+        let [state0, state1, state2, state3, state4, state5, state6, state7] =
+            &mut leaves_untransposed;
+        self.implementation.blake2s_untranspose8(
+            &leaves_copy,
+            [
+                state0, state1, state2, state3, state4, state5, state6, state7,
+            ],
+        );
+        for i in 0..DEGREE {
+            if remaining[i] > 0 {
+                self.implementation.blake2s_compress(
+                    &mut leaves_untransposed[i],
+                    blocks[blocks_handled + i],
+                    count + remaining[i] as u64,
+                    !0,
+                    if i == DEGREE - 1 { !0 } else { 0 },
+                );
+            }
+        }
+
+        // Compress each of the eight untransposed, finalized hashes into the
+        // root words as input, two per block. Again we copy the words to keep
+        // this method idempotent. Note that this uses the full-length leaf
+        // hashes, not the shortened versions, even if the hash_length
+        // parameter is set to a short value. Note also that, as mentioned
+        // above, the root node doesn't hash any key bytes.
+        let mut root_words_copy = self.root_words;
+        for i in 0..DEGREE / 2 {
+            let mut block = [0; BLOCKBYTES];
+            LittleEndian::write_u32_into(&leaves_untransposed[2 * i], &mut block[0..OUTBYTES]);
+            LittleEndian::write_u32_into(
+                &leaves_untransposed[2 * i + 1],
+                &mut block[OUTBYTES..2 * OUTBYTES],
+            );
+            self.implementation.blake2s_compress(
+                &mut root_words_copy,
+                &block,
+                ((i + 1) * BLOCKBYTES) as u64,
+                if i == DEGREE / 2 - 1 { !0 } else { 0 },
+                if i == DEGREE / 2 - 1 { !0 } else { 0 },
+            );
+        }
+
+        Hash {
+            bytes: crate::blake2s::state_words_to_bytes(&root_words_copy),
+            len: self.hash_length,
+        }
+    }
+
+    /// Return the total number of bytes input so far.
+    pub fn count(&self) -> u64 {
+        DEGREE as u64 * self.count + self.buflen as u64
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for State {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "State {{ count: {}, hash_length: {} }}",
+            self.count(),
+            self.hash_length,
+        )
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::with_params(&Params::default())
+    }
+}
+
+pub(crate) fn force_portable(state: &mut State) {
+    state.implementation = guts::Implementation::portable();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blake2bp::test::paint_input;
+
+    // A simple reference implementation without the buffering logic, like the one in the
+    // BLAKE2bp tests.
+    fn blake2sp_reference(input: &[u8]) -> Hash {
+        let leaf = |i: usize| {
+            Blake2sParams::new()
+                .fanout(DEGREE as u8)
+                .max_depth(2)
+                .node_offset(i as u64)
+                .inner_hash_length(OUTBYTES)
+                .last_node(i == DEGREE - 1)
+                .to_state()
+        };
+        let mut leaves = [
+            leaf(0),
+            leaf(1),
+            leaf(2),
+            leaf(3),
+            leaf(4),
+            leaf(5),
+            leaf(6),
+            leaf(7),
+        ];
+        for (i, chunk) in input.chunks(BLOCKBYTES).enumerate() {
+            leaves[i % DEGREE].update(chunk);
+        }
+        let mut root = Blake2sParams::new()
+            .fanout(DEGREE as u8)
+            .max_depth(2)
+            .node_depth(1)
+            .inner_hash_length(OUTBYTES)
+            .last_node(true)
+            .to_state();
+        for leaf in &mut leaves {
+            root.update(leaf.finalize().as_bytes());
+        }
+        root.finalize()
+    }
+
+    #[test]
+    fn test_against_reference() {
+        let mut buf = [0; 41 * BLOCKBYTES];
+        paint_input(&mut buf);
+        // These cover the same buffering cases as the BLAKE2bp test, scaled up to eight leaves:
+        // filling the double buffer (16 blocks), compressing once from it (17 to 23 blocks),
+        // compressing twice (24 blocks), and hashing directly from memory (32 blocks and up).
+        for num_blocks in 0..=40 {
+            for &extra in &[0, 1, BLOCKBYTES - 1] {
+                // First hash the input all at once, as a sanity check.
+                let input = &buf[..num_blocks * BLOCKBYTES + extra];
+                let expected = blake2sp_reference(input);
+                let found = blake2sp(input);
+                assert_eq!(expected, found);
+
+                // Then, do it again, but buffer 1 byte of input first. That causes the buffering
+                // branch to trigger.
+                let mut state = State::new();
+                let maybe_one = cmp::min(1, input.len());
+                state.update(&input[..maybe_one]);
+                assert_eq!(maybe_one as u64, state.count());
+                // Do a throwaway finalize here to check for idempotency.
+                state.finalize();
+                state.update(&input[maybe_one..]);
+                assert_eq!(input.len() as u64, state.count());
+                let found = state.finalize();
+                assert_eq!(expected, found);
+            }
+        }
+    }
+}
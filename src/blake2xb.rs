@@ -0,0 +1,557 @@
+//! An implementation of BLAKE2Xb, the extendable-output variant of BLAKE2b.
+//!
+//! BLAKE2Xb produces outputs of any length up to `2^32 - 2` bytes, or of an unknown length up to
+//! 256 GiB. It works in two steps. First the input is hashed into a 64-byte root hash, using
+//! BLAKE2b with the output length mixed into the parameters. Then each 64-byte block of output is
+//! an independent BLAKE2b hash of the root hash, with the block index as its node offset. That
+//! means the [`OutputReader`] can seek to any offset in the output without computing the blocks
+//! before it. For all the details, see [the BLAKE2X spec](https://blake2.net/blake2x.pdf).
+//!
+//! Note that the output length is associated data, so a 32-byte output is *not* a prefix of a
+//! 64-byte output for the same input. The unknown-length mode is the exception, where every output
+//! is a prefix of the same 256 GiB stream.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::blake2xb;
+//!
+//! let mut output = [0; 100];
+//! blake2xb::blake2xb(b"foo", &mut output);
+//!
+//! let mut reader = blake2xb::Params::new()
+//!     .output_length(100)
+//!     .to_state()
+//!     .update(b"foo")
+//!     .finalize();
+//! let mut tail = [0; 10];
+//! reader.set_position(90);
+//! reader.fill(&mut tail);
+//! assert_eq!(&output[90..], &tail[..]);
+//! ```
+//!
+//! [`OutputReader`]: struct.OutputReader.html
+
+use crate::Params as Blake2bParams;
+use crate::State as Blake2bState;
+use crate::KEYBYTES;
+use crate::OUTBYTES;
+use crate::PERSONALBYTES;
+use crate::SALTBYTES;
+use core::cmp;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std;
+
+// The output length that signals "unknown in advance" in the BLAKE2X parameter block.
+const UNKNOWN_LENGTH: u32 = u32::MAX;
+
+// In unknown-length mode, the output is limited by the 32-bit block index.
+const UNKNOWN_LENGTH_MAX_OUTPUT: u64 = (1 << 32) * OUTBYTES as u64;
+
+/// Compute the BLAKE2Xb output for a slice of bytes, using default parameters, and write it to
+/// `output`. The output length is `output.len()`, which must be between 1 and `2^32 - 2`.
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::blake2xb::blake2xb;
+/// let mut output = [0; 16];
+/// blake2xb(b"foo", &mut output);
+/// ```
+pub fn blake2xb(input: &[u8], output: &mut [u8]) {
+    assert!(
+        (output.len() as u64) < UNKNOWN_LENGTH as u64,
+        "Bad output length: {}",
+        output.len()
+    );
+    Params::new()
+        .output_length(output.len() as u32)
+        .to_state()
+        .update(input)
+        .finalize()
+        .fill(output);
+}
+
+/// A parameter builder for BLAKE2Xb.
+///
+/// BLAKE2Xb supports a key, a salt, and a personalization, with the same limits as in BLAKE2b.
+/// The other BLAKE2b parameters are fixed by the BLAKE2X spec. The output length defaults to
+/// unknown.
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::blake2xb::Params;
+/// let mut state = Params::new().output_length(1000).to_state();
+/// ```
+#[derive(Clone)]
+pub struct Params {
+    output_length: u32,
+    key_length: u8,
+    key: [u8; KEYBYTES],
+    salt: [u8; SALTBYTES],
+    personal: [u8; PERSONALBYTES],
+}
+
+impl Params {
+    /// Equivalent to `Params::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Construct a BLAKE2Xb `State` object based on these parameters.
+    pub fn to_state(&self) -> State {
+        State::with_params(self)
+    }
+
+    /// Set the output length in bytes, from 1 to `2^32 - 2`. The value `2^32 - 1` is reserved by
+    /// the spec to mean an unknown length; use `unknown_output_length` for that. Like the hash
+    /// length in BLAKE2b, this is also associated data, and changing it changes every byte of the
+    /// output.
+    pub fn output_length(&mut self, length: u32) -> &mut Self {
+        assert!(
+            length != 0 && length != UNKNOWN_LENGTH,
+            "Bad output length: {}",
+            length
+        );
+        self.output_length = length;
+        self
+    }
+
+    /// Don't commit to an output length in advance. The output reader will then produce up to
+    /// 256 GiB of output. This is the default.
+    pub fn unknown_output_length(&mut self) -> &mut Self {
+        self.output_length = UNKNOWN_LENGTH;
+        self
+    }
+
+    /// Use a secret key, so that BLAKE2Xb acts as a MAC. The maximum key length is `KEYBYTES`
+    /// (64). An empty key is equivalent to having no key at all.
+    pub fn key(&mut self, key: &[u8]) -> &mut Self {
+        assert!(key.len() <= KEYBYTES, "Bad key length: {}", key.len());
+        self.key_length = key.len() as u8;
+        self.key = [0; KEYBYTES];
+        self.key[..key.len()].copy_from_slice(key);
+        self
+    }
+
+    /// At most `SALTBYTES` (16). Shorter salts are padded with null bytes. An empty salt is
+    /// equivalent to having no salt at all.
+    pub fn salt(&mut self, salt: &[u8]) -> &mut Self {
+        assert!(salt.len() <= SALTBYTES, "Bad salt length: {}", salt.len());
+        self.salt = [0; SALTBYTES];
+        self.salt[..salt.len()].copy_from_slice(salt);
+        self
+    }
+
+    /// At most `PERSONALBYTES` (16). Shorter personalizations are padded with null bytes. An
+    /// empty personalization is equivalent to having no personalization at all.
+    pub fn personal(&mut self, personalization: &[u8]) -> &mut Self {
+        assert!(
+            personalization.len() <= PERSONALBYTES,
+            "Bad personalization length: {}",
+            personalization.len()
+        );
+        self.personal = [0; PERSONALBYTES];
+        self.personal[..personalization.len()].copy_from_slice(personalization);
+        self
+    }
+
+    fn root_params(&self) -> Blake2bParams {
+        let mut params = Blake2bParams::new();
+        params
+            .key(&self.key[..self.key_length as usize])
+            .salt(&self.salt)
+            .personal(&self.personal)
+            // The XOF length occupies the high 32 bits of the BLAKE2b node offset field.
+            .node_offset((self.output_length as u64) << 32);
+        params
+    }
+
+    fn output_params(&self) -> Blake2bParams {
+        let mut params = Blake2bParams::new();
+        params
+            .salt(&self.salt)
+            .personal(&self.personal)
+            .fanout(0)
+            .max_leaf_length(OUTBYTES as u32)
+            .inner_hash_length(OUTBYTES);
+        // The output blocks use a max depth of 0, which the public builder rejects, because it's
+        // invalid everywhere else.
+        params.max_depth = 0;
+        params
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            output_length: UNKNOWN_LENGTH,
+            key_length: 0,
+            key: [0; KEYBYTES],
+            salt: [0; SALTBYTES],
+            personal: [0; PERSONALBYTES],
+        }
+    }
+}
+
+impl fmt::Debug for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Params {{ output_length: {}, key_length: {}, salt: {:?}, personal: {:?} }}",
+            self.output_length,
+            // NB: Don't print the key itself. Debug shouldn't leak secrets.
+            self.key_length,
+            &self.salt,
+            &self.personal,
+        )
+    }
+}
+
+/// An incremental hasher for BLAKE2Xb. Finalizing it returns an [`OutputReader`].
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::blake2xb;
+///
+/// let mut state = blake2xb::Params::new().output_length(100).to_state();
+/// state.update(b"foo");
+/// state.update(b"bar");
+/// let mut output = [0; 100];
+/// state.finalize().fill(&mut output);
+/// ```
+///
+/// [`OutputReader`]: struct.OutputReader.html
+#[derive(Clone)]
+pub struct State {
+    root: Blake2bState,
+    params: Params,
+}
+
+impl State {
+    /// Equivalent to `State::default()` or `Params::default().to_state()`.
+    pub fn new() -> Self {
+        Self::with_params(&Params::default())
+    }
+
+    fn with_params(params: &Params) -> Self {
+        Self {
+            root: params.root_params().to_state(),
+            params: params.clone(),
+        }
+    }
+
+    /// Add input to the hash. You can call `update` any number of times.
+    pub fn update(&mut self, input: &[u8]) -> &mut Self {
+        self.root.update(input);
+        self
+    }
+
+    /// Finalize the root hash and return an `OutputReader` positioned at the start of the output.
+    /// This method is idempotent, and calling it multiple times will give the same result. It's
+    /// also possible to `update` with more input in between.
+    pub fn finalize(&mut self) -> OutputReader {
+        let root_hash = self.root.finalize();
+        OutputReader {
+            root_hash: root_hash.bytes,
+            output_params: self.params.output_params(),
+            output_length: self.params.output_length,
+            position: 0,
+        }
+    }
+
+    /// Return the total number of bytes input so far.
+    pub fn count(&self) -> u128 {
+        self.root.count()
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for State {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "State {{ count: {}, output_length: {} }}",
+            self.count(),
+            self.params.output_length,
+        )
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::with_params(&Params::default())
+    }
+}
+
+/// A reader for the output of BLAKE2Xb, returned by [`State::finalize`].
+///
+/// Each 64-byte block of output is computed independently, so seeking with `set_position` (or
+/// `std::io::Seek`) is cheap, and it doesn't generate any of the output before the new position.
+///
+/// [`State::finalize`]: struct.State.html#method.finalize
+#[derive(Clone)]
+pub struct OutputReader {
+    root_hash: [u8; OUTBYTES],
+    output_params: Blake2bParams,
+    output_length: u32,
+    position: u64,
+}
+
+impl OutputReader {
+    /// The total number of output bytes this reader can produce. In unknown-length mode, that's
+    /// 256 GiB.
+    pub fn output_length(&self) -> u64 {
+        if self.output_length == UNKNOWN_LENGTH {
+            UNKNOWN_LENGTH_MAX_OUTPUT
+        } else {
+            self.output_length as u64
+        }
+    }
+
+    /// The current position in the output stream, in bytes.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Seek to a new position in the output stream. Positions past the end are allowed, but
+    /// there's no output to read there.
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+
+    // Set up the BLAKE2b state for one block of output. In known-length mode, the final block is
+    // shortened to the remaining output length.
+    fn block_state(&self, block_index: u64) -> Blake2bState {
+        let block_len = cmp::min(
+            OUTBYTES as u64,
+            self.output_length() - block_index * OUTBYTES as u64,
+        );
+        let mut state = self
+            .output_params
+            .clone()
+            .hash_length(block_len as usize)
+            .node_offset(block_index | (self.output_length as u64) << 32)
+            .to_state();
+        state.update(&self.root_hash);
+        state
+    }
+
+    /// Fill `buf` with output bytes, starting at the current position, and advance the position.
+    /// This panics if it would read past the end of the output.
+    pub fn fill(&mut self, mut buf: &mut [u8]) {
+        assert!(
+            buf.len() as u64 <= self.output_length().saturating_sub(self.position),
+            "Read past the end of the output"
+        );
+        while !buf.is_empty() {
+            let block_index = self.position / OUTBYTES as u64;
+            let offset = (self.position % OUTBYTES as u64) as usize;
+            // Compute four whole blocks in parallel when we can. Blocks never need more than one
+            // compression, so this is exactly the use case of update4/finalize4.
+            if offset == 0 && buf.len() >= 4 * OUTBYTES {
+                let mut state0 = self.block_state(block_index);
+                let mut state1 = self.block_state(block_index + 1);
+                let mut state2 = self.block_state(block_index + 2);
+                let mut state3 = self.block_state(block_index + 3);
+                let hashes = crate::finalize4(&mut state0, &mut state1, &mut state2, &mut state3);
+                for hash in &hashes {
+                    buf[..OUTBYTES].copy_from_slice(hash.as_bytes());
+                    buf = &mut { buf }[OUTBYTES..];
+                }
+                self.position += 4 * OUTBYTES as u64;
+                continue;
+            }
+            let hash = self.block_state(block_index).finalize();
+            let take = cmp::min(buf.len(), hash.as_bytes().len() - offset);
+            buf[..take].copy_from_slice(&hash.as_bytes()[offset..][..take]);
+            buf = &mut { buf }[take..];
+            self.position += take as u64;
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Read for OutputReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.output_length().saturating_sub(self.position);
+        let take = cmp::min(buf.len() as u64, remaining) as usize;
+        self.fill(&mut buf[..take]);
+        Ok(take)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Seek for OutputReader {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let (base, offset) = match pos {
+            std::io::SeekFrom::Start(n) => {
+                self.position = n;
+                return Ok(n);
+            }
+            std::io::SeekFrom::Current(n) => (self.position, n),
+            std::io::SeekFrom::End(n) => (self.output_length(), n),
+        };
+        let new_position = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        match new_position {
+            Some(n) => {
+                self.position = n;
+                Ok(n)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl fmt::Debug for OutputReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: Don't print the root hash. It's equivalent to the whole output.
+        write!(
+            f,
+            "OutputReader {{ output_length: {}, position: {} }}",
+            self.output_length(),
+            self.position,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blake2bp::test::paint_input;
+
+    #[test]
+    fn test_fill_matches_one_block_at_a_time() {
+        let mut expected = [0; 1000];
+        let mut reader = Params::new()
+            .output_length(1000)
+            .to_state()
+            .update(b"foo")
+            .finalize();
+        for chunk in expected.chunks_mut(7) {
+            reader.fill(chunk);
+        }
+        assert_eq!(1000, reader.position());
+
+        // Filling all at once takes the batched path.
+        reader.set_position(0);
+        let mut found = [0; 1000];
+        reader.fill(&mut found);
+        assert_eq!(&expected[..], &found[..]);
+
+        // Seeking to an unaligned offset and then filling a large buffer takes both paths.
+        reader.set_position(33);
+        let mut found = [0; 900];
+        reader.fill(&mut found);
+        assert_eq!(&expected[33..933], &found[..]);
+    }
+
+    #[test]
+    fn test_unknown_length_is_a_prefix_stream() {
+        let mut input = [0; 300];
+        paint_input(&mut input);
+        let mut state = Params::new().key(b"key").to_state();
+        state.update(&input);
+        let mut reader = state.finalize();
+        assert_eq!(UNKNOWN_LENGTH_MAX_OUTPUT, reader.output_length());
+        let mut long = [0; 500];
+        reader.fill(&mut long);
+        let mut short = [0; 100];
+        state.finalize().fill(&mut short);
+        assert_eq!(&long[..100], &short[..]);
+
+        // Unlike unknown-length mode, a known length changes the whole output.
+        let mut known = [0; 100];
+        Params::new()
+            .key(b"key")
+            .output_length(100)
+            .to_state()
+            .update(&input)
+            .finalize()
+            .fill(&mut known);
+        assert_ne!(&known[..], &short[..]);
+
+        // The end of the unknown-length stream is reachable by seeking.
+        reader.set_position(UNKNOWN_LENGTH_MAX_OUTPUT - 3);
+        let mut end = [0; 3];
+        reader.fill(&mut end);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_and_seek() {
+        use std::io::prelude::*;
+        use std::io::SeekFrom;
+
+        let mut expected = [0; 200];
+        blake2xb(b"foo", &mut expected);
+        let mut reader = Params::new()
+            .output_length(200)
+            .to_state()
+            .update(b"foo")
+            .finalize();
+
+        let mut buf = [0; 50];
+        assert_eq!(150, reader.seek(SeekFrom::End(-50)).unwrap());
+        assert_eq!(50, reader.read(&mut buf).unwrap());
+        assert_eq!(&expected[150..], &buf[..]);
+        // At the end, reads return 0.
+        assert_eq!(0, reader.read(&mut buf).unwrap());
+
+        assert_eq!(100, reader.seek(SeekFrom::Current(-100)).unwrap());
+        assert_eq!(
+            &expected[100..150],
+            &{
+                reader.read_exact(&mut buf).unwrap();
+                buf
+            }[..]
+        );
+        assert!(reader.seek(SeekFrom::Current(-1000)).is_err());
+
+        let mut all = Vec::new();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(&expected[..], &all[..]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_fill_past_the_end_panics() {
+        let mut reader = Params::new().output_length(10).to_state().finalize();
+        reader.fill(&mut [0; 11]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_output_length_panics() {
+        Params::new().output_length(0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_reserved_output_length_panics() {
+        Params::new().output_length(u32::MAX);
+    }
+}
@@ -0,0 +1,440 @@
+//! Multihash and CID encoding for BLAKE2b hashes, for IPFS-compatible content addressing.
+//!
+//! A [multihash] is a hash prefixed with the varint code of its hash function and the varint
+//! length of the digest. The multicodec table gives BLAKE2b with an `N`-byte output the code
+//! `0xb200 + N`, from [`BLAKE2B_8`] (`0xb201`) to [`BLAKE2B_512`] (`0xb240`), so the code follows
+//! from [`Params::hash_length`]. See [`Hash::to_multihash`] and [`Hash::from_multihash`].
+//!
+//! A version 1 [CID] is the varint version number 1, the varint multicodec code of the content
+//! type, like [`RAW`] or [`DAG_CBOR`], and then the multihash. As a string, it's prefixed with a
+//! multibase character that says how it's encoded. [`Cid`] produces lowercase base32 (`b`), which
+//! is the usual form of a version 1 CID, or base58btc (`z`), and it parses either, as well as
+//! uppercase base32 (`B`). Version 0 CIDs are always SHA-256, so they aren't supported here.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::cid::{self, Cid};
+//! use blake2b_simd::Params;
+//!
+//! let hash = Params::new().hash_length(32).to_state().update(b"abc").finalize();
+//! let cid = Cid::new(cid::DAG_CBOR, hash);
+//! let expected = "bafy2bzacec653aj4mnbds4rrohxt73uyk6nzjfsohoy4wpscojrmrqdi2urrs";
+//! assert_eq!(expected, &*cid.to_base32());
+//! assert_eq!(cid, expected.parse().unwrap());
+//! assert_eq!(cid, cid.to_base58btc().parse().unwrap());
+//! ```
+//!
+//! [multihash]: https://multiformats.io/multihash/
+//! [CID]: https://github.com/multiformats/cid
+//! [`BLAKE2B_8`]: constant.BLAKE2B_8.html
+//! [`BLAKE2B_512`]: constant.BLAKE2B_512.html
+//! [`Params::hash_length`]: ../struct.Params.html#method.hash_length
+//! [`Hash::to_multihash`]: ../struct.Hash.html#method.to_multihash
+//! [`Hash::from_multihash`]: ../struct.Hash.html#method.from_multihash
+//! [`RAW`]: constant.RAW.html
+//! [`DAG_CBOR`]: constant.DAG_CBOR.html
+//! [`Cid`]: struct.Cid.html
+
+use crate::encoding;
+use crate::EncodedString;
+use crate::Hash;
+use crate::OUTBYTES;
+use arrayvec::ArrayVec;
+use core::fmt;
+use core::str::FromStr;
+
+#[cfg(feature = "std")]
+use std;
+
+/// The multicodec code for BLAKE2b with a 1-byte output. The code for an `N`-byte output is
+/// `BLAKE2B_8 + N - 1`.
+pub const BLAKE2B_8: u64 = 0xb201;
+/// The multicodec code for BLAKE2b with a 64-byte output.
+pub const BLAKE2B_512: u64 = 0xb240;
+
+/// The multicodec code for raw binary content.
+pub const RAW: u64 = 0x55;
+/// The multicodec code for MerkleDAG protobuf content.
+pub const DAG_PB: u64 = 0x70;
+/// The multicodec code for MerkleDAG CBOR content.
+pub const DAG_CBOR: u64 = 0x71;
+
+const CID_VERSION: u64 = 1;
+
+// Multiformats varints are limited to 9 bytes, or 63 bits.
+const MAX_VARINT_BYTES: usize = 9;
+const MAX_VARINT: u64 = (1 << 63) - 1;
+
+// A multihash is at most a 3-byte code, a 1-byte length, and the digest. A CID adds a 1-byte
+// version and a content code. These round up to sizes that arrayvec supports.
+const MAX_CID_BYTES: usize = 1 + MAX_VARINT_BYTES + 3 + 1 + OUTBYTES;
+type MultihashBytes = ArrayVec<[u8; 72]>;
+type CidBytes = ArrayVec<[u8; 96]>;
+
+fn write_varint<A: arrayvec::Array<Item = u8>>(mut n: u64, out: &mut ArrayVec<A>) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, Error> {
+    let mut n = 0;
+    for i in 0..MAX_VARINT_BYTES {
+        let byte = *input.get(i).ok_or(Error::InvalidVarint)?;
+        n |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            // Varints have to be minimal, so a zero byte can't end a longer varint.
+            if byte == 0 && i > 0 {
+                return Err(Error::InvalidVarint);
+            }
+            *input = &input[i + 1..];
+            return Ok(n);
+        }
+    }
+    Err(Error::InvalidVarint)
+}
+
+fn write_multihash<A: arrayvec::Array<Item = u8>>(hash: &Hash, out: &mut ArrayVec<A>) {
+    let len = hash.as_bytes().len() as u64;
+    write_varint(BLAKE2B_8 + len - 1, out);
+    write_varint(len, out);
+    for &b in hash.as_bytes() {
+        out.push(b);
+    }
+}
+
+fn read_multihash(input: &mut &[u8]) -> Result<Hash, Error> {
+    let code = read_varint(input)?;
+    if !(BLAKE2B_8..=BLAKE2B_512).contains(&code) {
+        return Err(Error::UnsupportedHash);
+    }
+    let len = read_varint(input)?;
+    if len != code - BLAKE2B_8 + 1 || (input.len() as u64) < len {
+        return Err(Error::InvalidLength);
+    }
+    let (digest, rest) = input.split_at(len as usize);
+    *input = rest;
+    Ok(Hash::from_bytes(digest).expect("the length is between 1 and 64"))
+}
+
+impl Hash {
+    /// Encode the hash as a multihash: the varint multicodec code for BLAKE2b with this hash's
+    /// length, the varint length, and then the hash bytes. See the [`cid`] module.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use blake2b_simd::{blake2b, Hash};
+    ///
+    /// let hash = blake2b(b"foo");
+    /// let multihash = hash.to_multihash();
+    /// // 0xb240 as a varint, then 64 as a varint.
+    /// assert_eq!(&[0xc0, 0xe4, 0x02, 0x40], &multihash[..4]);
+    /// assert_eq!(hash, Hash::from_multihash(&multihash).unwrap());
+    /// ```
+    ///
+    /// [`cid`]: cid/index.html
+    pub fn to_multihash(&self) -> MultihashBytes {
+        let mut multihash = ArrayVec::new();
+        write_multihash(self, &mut multihash);
+        multihash
+    }
+
+    /// Parse a BLAKE2b multihash, as produced by [`to_multihash`]. This returns an error if the
+    /// multihash uses a different hash function, if the length doesn't match the code, or if
+    /// there are extra bytes at the end.
+    ///
+    /// [`to_multihash`]: #method.to_multihash
+    pub fn from_multihash(mut multihash: &[u8]) -> Result<Self, Error> {
+        let hash = read_multihash(&mut multihash)?;
+        if !multihash.is_empty() {
+            return Err(Error::InvalidLength);
+        }
+        Ok(hash)
+    }
+}
+
+/// A version 1 CID for content hashed with BLAKE2b.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cid {
+    codec: u64,
+    hash: Hash,
+}
+
+impl Cid {
+    /// Create a CID from the multicodec code of the content type, like [`RAW`] or [`DAG_CBOR`],
+    /// and the hash of the content. The code must fit in 63 bits.
+    ///
+    /// [`RAW`]: constant.RAW.html
+    /// [`DAG_CBOR`]: constant.DAG_CBOR.html
+    pub fn new(codec: u64, hash: Hash) -> Self {
+        assert!(codec <= MAX_VARINT, "Bad codec: {}", codec);
+        Self { codec, hash }
+    }
+
+    /// The multicodec code of the content type.
+    pub fn codec(&self) -> u64 {
+        self.codec
+    }
+
+    /// The hash of the content.
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    /// Encode the CID as bytes: the varint version, the varint content code, and the multihash.
+    pub fn to_bytes(&self) -> CidBytes {
+        let mut bytes = ArrayVec::new();
+        write_varint(CID_VERSION, &mut bytes);
+        write_varint(self.codec, &mut bytes);
+        write_multihash(&self.hash, &mut bytes);
+        bytes
+    }
+
+    /// Parse a CID from bytes, as produced by [`to_bytes`].
+    ///
+    /// [`to_bytes`]: #method.to_bytes
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        if read_varint(&mut bytes)? != CID_VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+        let codec = read_varint(&mut bytes)?;
+        let hash = read_multihash(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(Error::InvalidLength);
+        }
+        Ok(Self { codec, hash })
+    }
+
+    /// Encode the CID as a lowercase base32 multibase string, starting with `b`. This is also
+    /// what the `Display` impl does.
+    pub fn to_base32(&self) -> EncodedString {
+        let mut s = EncodedString::new();
+        s.push('b');
+        s.push_str(&encoding::BASE32.encode(&self.to_bytes()));
+        s
+    }
+
+    /// Encode the CID as a base58btc multibase string, starting with `z`.
+    pub fn to_base58btc(&self) -> EncodedString {
+        let mut s = EncodedString::new();
+        s.push('z');
+        encoding::base58btc_encode(&self.to_bytes(), &mut s);
+        s
+    }
+}
+
+impl fmt::Display for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_base32())
+    }
+}
+
+/// Parses a multibase string in base32 (`b` or `B`) or base58btc (`z`).
+impl FromStr for Cid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut bytes = [0; MAX_CID_BYTES];
+        let prefix = s.chars().next().ok_or(Error::InvalidMultibase)?;
+        let rest = &s[prefix.len_utf8()..];
+        let decoded = match prefix {
+            'b' | 'B' => encoding::BASE32.decode(rest, &mut bytes),
+            'z' => encoding::base58btc_decode(rest, &mut bytes),
+            _ => return Err(Error::InvalidMultibase),
+        };
+        let len = decoded.map_err(|_| Error::InvalidMultibase)?;
+        Self::from_bytes(&bytes[..len])
+    }
+}
+
+/// The error returned when parsing a multihash or a CID fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A varint is truncated, longer than 9 bytes, or not minimally encoded.
+    InvalidVarint,
+    /// The multihash isn't BLAKE2b.
+    UnsupportedHash,
+    /// The CID isn't version 1.
+    UnsupportedVersion,
+    /// The digest length doesn't match the multihash code, or there are extra bytes at the end.
+    InvalidLength,
+    /// The string doesn't start with a supported multibase prefix, or it isn't valid in that
+    /// base.
+    InvalidMultibase,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidVarint => write!(f, "invalid varint"),
+            Error::UnsupportedHash => write!(f, "multihash isn't BLAKE2b"),
+            Error::UnsupportedVersion => write!(f, "CID isn't version 1"),
+            Error::InvalidLength => write!(f, "invalid multihash length"),
+            Error::InvalidMultibase => write!(f, "invalid multibase string"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Params;
+
+    fn hash(input: &[u8], hash_length: usize) -> Hash {
+        Params::new()
+            .hash_length(hash_length)
+            .to_state()
+            .update(input)
+            .finalize()
+    }
+
+    // The input, hash length, codec, multihash, base32 CID, and base58btc CID.
+    type Vector = (
+        &'static [u8],
+        usize,
+        u64,
+        &'static str,
+        &'static str,
+        &'static str,
+    );
+
+    // Computed with a short Python script, using hashlib, base64, and a hand-written base58.
+    const VECTORS: &[Vector] = &[
+        (
+            b"abc",
+            32,
+            DAG_CBOR,
+            "a0e40220bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319",
+            "bafy2bzacec653aj4mnbds4rrohxt73uyk6nzjfsohoy4wpscojrmrqdi2urrs",
+            "zDPWYqFD4V3EcMB1N9Hc4nLmMQrZFsri8oo5tGNQAoF6BLX1EPLk",
+        ),
+        (
+            b"abc",
+            64,
+            RAW,
+            "c0e40240ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab\
+             792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+            "bafk4bzacic5ibjj7taoe2dlke6l3nhys63uuyijpcrufvrfxjmjlw36376rnc7mhyu4svk3zfxbffvo6iuz4\
+             zfiy2ofkrw7rsjnlsi4g5xkabgjd",
+            "z4QM3CM1XkWHAQYuGVXYbnVpYCeVRRcs8PYWnd4NAWLeiseR5qtgLYJdPAQYLtGnitCnDGxRimMvSzBxuBYpU\
+             fskGwjxuWFL",
+        ),
+        (b"", 1, DAG_PB, "81e402012e", "bafyidzacaexa", "z4AWLu6vzh"),
+        (
+            b"foo",
+            20,
+            RAW,
+            "94e40214983ceba2afea8694cc933336b27b907f90c53a88",
+            "bafkzjzaccsmdz25cv7vinfgmsmztnmt3sb7zbrj2ra",
+            "z3NSpwuhhAcqw4zAQgnTvNtJsePTws8H4Wzs",
+        ),
+    ];
+
+    #[test]
+    fn test_vectors() {
+        for &(input, hash_length, codec, multihash, base32, base58btc) in VECTORS {
+            let hash = hash(input, hash_length);
+            assert_eq!(multihash, hex::encode(hash.to_multihash()));
+            assert_eq!(hash, Hash::from_multihash(&hash.to_multihash()).unwrap());
+
+            let cid = Cid::new(codec, hash);
+            assert_eq!(base32, &*cid.to_base32());
+            assert_eq!(base58btc, &*cid.to_base58btc());
+            assert_eq!(cid, base32.parse().unwrap());
+            assert_eq!(cid, base58btc.parse().unwrap());
+            let mut upper = cid.to_base32();
+            upper.make_ascii_uppercase();
+            assert_eq!(cid, upper.parse().unwrap());
+            assert_eq!(cid, Cid::from_bytes(&cid.to_bytes()).unwrap());
+            assert_eq!(codec, cid.codec());
+            assert_eq!(hash, cid.hash());
+        }
+    }
+
+    #[test]
+    fn test_every_length() {
+        for hash_length in 1..=OUTBYTES {
+            let hash = hash(b"foo", hash_length);
+            let multihash = hash.to_multihash();
+            assert_eq!(hash, Hash::from_multihash(&multihash).unwrap());
+            // The largest codec makes the longest CID, which still fits in the string types.
+            for &codec in &[RAW, MAX_VARINT] {
+                let cid = Cid::new(codec, hash);
+                assert_eq!(cid, cid.to_base32().parse().unwrap());
+                assert_eq!(cid, cid.to_base58btc().parse().unwrap());
+            }
+        }
+        let longest = Cid::new(MAX_VARINT, hash(b"foo", OUTBYTES)).to_bytes();
+        assert_eq!(MAX_CID_BYTES, longest.len());
+    }
+
+    #[test]
+    fn test_multihash_errors() {
+        let multihash = hash(b"abc", 32).to_multihash();
+        let cases: &[(&[u8], Error)] = &[
+            (&[], Error::InvalidVarint),
+            (&multihash[..1], Error::InvalidVarint),
+            (&multihash[..3], Error::InvalidVarint),
+            (&multihash[..multihash.len() - 1], Error::InvalidLength),
+            // SHA-256
+            (&[0x12, 0x20], Error::UnsupportedHash),
+            // BLAKE2b-0 and BLAKE2b-520 don't exist.
+            (&[0x80, 0xe4, 0x02, 0x00], Error::UnsupportedHash),
+            (&[0xc1, 0xe4, 0x02, 0x41], Error::UnsupportedHash),
+            // BLAKE2b-8 with a 2-byte digest.
+            (&[0x81, 0xe4, 0x02, 0x02, 0xff, 0xff], Error::InvalidLength),
+            // A non-minimal varint.
+            (&[0x81, 0xe4, 0x82, 0x00, 0x01, 0xff], Error::InvalidVarint),
+        ];
+        for &(bytes, error) in cases {
+            assert_eq!(Err(error), Hash::from_multihash(bytes), "{:?}", bytes);
+        }
+        let mut extra = multihash.clone();
+        extra.push(0);
+        assert_eq!(Err(Error::InvalidLength), Hash::from_multihash(&extra));
+    }
+
+    #[test]
+    fn test_cid_errors() {
+        let cid = Cid::new(RAW, hash(b"abc", 32));
+        let mut bytes = cid.to_bytes();
+        bytes[0] = 0;
+        assert_eq!(Err(Error::UnsupportedVersion), Cid::from_bytes(&bytes));
+        let bytes = cid.to_bytes();
+        assert_eq!(
+            Err(Error::InvalidLength),
+            Cid::from_bytes(&bytes[..bytes.len() - 1])
+        );
+
+        let base32 = cid.to_base32();
+        let base58btc = cid.to_base58btc();
+        for &s in &[
+            "",
+            "b",
+            "z",
+            // A version 0 CID, which has no multibase prefix.
+            "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n",
+            // base64, which isn't supported
+            "mAVUSIA",
+            &base32[1..],
+            &base58btc[..base58btc.len() - 1],
+        ] {
+            assert!(s.parse::<Cid>().is_err(), "{:?}", s);
+        }
+        assert_eq!(Err(Error::InvalidMultibase), "b0".parse::<Cid>());
+        assert_eq!(Err(Error::InvalidMultibase), "z0".parse::<Cid>());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_long_codec_panics() {
+        Cid::new(MAX_VARINT + 1, hash(b"abc", 32));
+    }
+}
@@ -0,0 +1,289 @@
+//! Implementations of the RustCrypto `digest` and `crypto-mac` traits, with the optional `digest`
+//! Cargo feature.
+//!
+//! Generic code written against the [`digest`] 0.8 and [`crypto-mac`] 0.7 traits, like the `hmac`
+//! and `hkdf` crates, can use BLAKE2b through this module:
+//!
+//! - [`State`] and [`blake2bp::State`] implement `Input` and `VariableOutput`. `VariableOutput::new`
+//!   takes the output length, as [`Params::hash_length`] does, and
+//!   `VariableOutput::output_size` returns the length that the state was configured with, whether
+//!   or not it was created through the trait. These types don't implement `Reset`, because a
+//!   state doesn't keep a copy of its key or parameters.
+//! - [`Blake2b256`] and [`Blake2b512`] are unkeyed BLAKE2b with fixed 32-byte and 64-byte outputs.
+//!   They implement `Input`, `BlockInput`, `FixedOutput`, `Reset`, `Default`, and `Clone`, and so
+//!   they implement `Digest`.
+//! - [`Blake2bMac`] is keyed BLAKE2b with a 64-byte output, and it implements `Mac`. Keys given to
+//!   `Mac::new_varkey` can be between 1 and `KEYBYTES` (64) bytes long.
+//!
+//! # Example
+//!
+//! ```
+//! # extern crate digest_crate as digest;
+//! use blake2b_simd::digest::Blake2b512;
+//! use digest::Digest;
+//!
+//! let mut hasher = Blake2b512::new();
+//! hasher.input(b"foo");
+//! assert_eq!(blake2b_simd::blake2b(b"foo").as_bytes(), &hasher.result()[..]);
+//! ```
+//!
+//! [`digest`]: https://docs.rs/digest/0.8
+//! [`crypto-mac`]: https://docs.rs/crypto-mac/0.7
+//! [`State`]: ../struct.State.html
+//! [`blake2bp::State`]: ../blake2bp/struct.State.html
+//! [`Params::hash_length`]: ../struct.Params.html#method.hash_length
+//! [`Blake2b256`]: struct.Blake2b256.html
+//! [`Blake2b512`]: struct.Blake2b512.html
+//! [`Blake2bMac`]: struct.Blake2bMac.html
+
+use crate::blake2bp;
+use crate::Params;
+use crate::State;
+use crate::KEYBYTES;
+use crate::OUTBYTES;
+use core::fmt;
+use crypto_mac::{InvalidKeyLength, Mac, MacResult};
+use digest_crate::generic_array::typenum::{U128, U32, U64};
+use digest_crate::generic_array::GenericArray;
+use digest_crate::{BlockInput, FixedOutput, Input, InvalidOutputSize, Reset, VariableOutput};
+
+#[cfg(feature = "std")]
+use std;
+
+impl Input for State {
+    fn input<B: AsRef<[u8]>>(&mut self, data: B) {
+        self.update(data.as_ref());
+    }
+}
+
+impl BlockInput for State {
+    type BlockSize = U128;
+}
+
+impl VariableOutput for State {
+    fn new(output_size: usize) -> Result<Self, InvalidOutputSize> {
+        if output_size == 0 || output_size > OUTBYTES {
+            return Err(InvalidOutputSize);
+        }
+        Ok(Params::new().hash_length(output_size).to_state())
+    }
+
+    fn output_size(&self) -> usize {
+        self.hash_length as usize
+    }
+
+    fn variable_result<F: FnOnce(&[u8])>(mut self, f: F) {
+        f(self.finalize().as_bytes())
+    }
+}
+
+impl Input for blake2bp::State {
+    fn input<B: AsRef<[u8]>>(&mut self, data: B) {
+        self.update(data.as_ref());
+    }
+}
+
+impl VariableOutput for blake2bp::State {
+    fn new(output_size: usize) -> Result<Self, InvalidOutputSize> {
+        if output_size == 0 || output_size > OUTBYTES {
+            return Err(InvalidOutputSize);
+        }
+        Ok(blake2bp::Params::new().hash_length(output_size).to_state())
+    }
+
+    fn output_size(&self) -> usize {
+        self.hash_length as usize
+    }
+
+    fn variable_result<F: FnOnce(&[u8])>(mut self, f: F) {
+        f(self.finalize().as_bytes())
+    }
+}
+
+macro_rules! fixed_output_hasher {
+    ($name:ident, $output_size:ty, $length:expr, $doc:expr) => {
+        #[doc = $doc]
+        #[derive(Clone, Debug)]
+        pub struct $name {
+            state: State,
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self {
+                    state: Params::new().hash_length($length).to_state(),
+                }
+            }
+        }
+
+        impl Input for $name {
+            fn input<B: AsRef<[u8]>>(&mut self, data: B) {
+                self.state.update(data.as_ref());
+            }
+        }
+
+        impl BlockInput for $name {
+            type BlockSize = U128;
+        }
+
+        impl FixedOutput for $name {
+            type OutputSize = $output_size;
+
+            fn fixed_result(mut self) -> GenericArray<u8, $output_size> {
+                GenericArray::clone_from_slice(self.state.finalize().as_bytes())
+            }
+        }
+
+        impl Reset for $name {
+            fn reset(&mut self) {
+                *self = Self::default();
+            }
+        }
+
+        #[cfg(feature = "std")]
+        impl std::io::Write for $name {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.state.update(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+    };
+}
+
+fixed_output_hasher!(
+    Blake2b256,
+    U32,
+    32,
+    "Unkeyed BLAKE2b with a 32-byte output, implementing `Digest`."
+);
+
+fixed_output_hasher!(
+    Blake2b512,
+    U64,
+    OUTBYTES,
+    "Unkeyed BLAKE2b with a 64-byte output, implementing `Digest`."
+);
+
+/// Keyed BLAKE2b with a 64-byte output, implementing `Mac`.
+///
+/// This keeps a copy of the state as it was right after keying, which `Mac::reset` goes back to.
+#[derive(Clone)]
+pub struct Blake2bMac {
+    keyed_state: State,
+    state: State,
+}
+
+impl Mac for Blake2bMac {
+    type OutputSize = U64;
+    type KeySize = U64;
+
+    fn new(key: &GenericArray<u8, U64>) -> Self {
+        Self::new_varkey(key).expect("a 64-byte key is always valid")
+    }
+
+    fn new_varkey(key: &[u8]) -> Result<Self, InvalidKeyLength> {
+        if key.is_empty() || key.len() > KEYBYTES {
+            return Err(InvalidKeyLength);
+        }
+        let keyed_state = Params::new().key(key).to_state();
+        Ok(Self {
+            state: keyed_state.clone(),
+            keyed_state,
+        })
+    }
+
+    fn input(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    fn reset(&mut self) {
+        self.state = self.keyed_state.clone();
+    }
+
+    fn result(mut self) -> MacResult<U64> {
+        MacResult::new(GenericArray::clone_from_slice(
+            self.state.finalize().as_bytes(),
+        ))
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for Blake2bMac {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.state.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Blake2bMac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: The State doesn't print its words, so it won't leak the key.
+        write!(f, "Blake2bMac {{ state: {:?} }}", self.state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use digest_crate::Digest;
+
+    #[test]
+    fn test_abc_vectors() {
+        // Computed with Python's hashlib.blake2b.
+        let expected_256 = "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319";
+        let expected_512 = "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+                            7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923";
+        let expected_160 = "384264f676f39536840523f284921cdc68b6846b";
+        let expected_mac = "5c6a9a4ae911c02fb7e71a991eb9aea371ae993d4842d206e6020d46f5e41358\
+                            c6d5c277c110ef86c959ed63e6ecaaaceaaff38019a43264ae06acf73b9550b1";
+        assert_eq!(expected_256, hex::encode(Blake2b256::digest(b"abc")));
+        assert_eq!(expected_512, hex::encode(Blake2b512::digest(b"abc")));
+        let mut state = <State as VariableOutput>::new(20).unwrap();
+        state.input(b"abc");
+        state.variable_result(|res| assert_eq!(expected_160, hex::encode(res)));
+        let mut mac = Blake2bMac::new_varkey(b"key").unwrap();
+        mac.input(b"abc");
+        assert_eq!(expected_mac, hex::encode(mac.result().code()));
+    }
+
+    // The `digest` and `crypto-mac` conformance tests are in tests/digest_tests.rs, since their
+    // macros load vectors relative to the calling file.
+
+    #[test]
+    fn test_mac_full_length_key() {
+        let full_key = GenericArray::clone_from_slice(&[0xff; KEYBYTES]);
+        let mut mac = Blake2bMac::new(&full_key);
+        mac.input(b"foo");
+        let expected = Params::new()
+            .key(&[0xff; KEYBYTES])
+            .to_state()
+            .update(b"foo")
+            .finalize();
+        assert_eq!(expected.as_bytes(), &mac.result().code()[..]);
+    }
+
+    #[test]
+    fn test_output_size() {
+        let state = Params::new().hash_length(17).to_state();
+        assert_eq!(17, VariableOutput::output_size(&state));
+        assert_eq!(64, Blake2b512::output_size());
+        assert!(<State as VariableOutput>::new(0).is_err());
+        assert!(<State as VariableOutput>::new(OUTBYTES + 1).is_err());
+        assert!(<blake2bp::State as VariableOutput>::new(0).is_err());
+        assert!(<blake2bp::State as VariableOutput>::new(OUTBYTES + 1).is_err());
+    }
+
+    #[test]
+    fn test_invalid_mac_keys() {
+        assert!(Blake2bMac::new_varkey(b"").is_err());
+        assert!(Blake2bMac::new_varkey(&[0; KEYBYTES + 1]).is_err());
+    }
+}
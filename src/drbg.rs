@@ -0,0 +1,321 @@
+//! A deterministic random bit generator built on keyed BLAKE2b.
+//!
+//! [`Drbg`] runs BLAKE2b in counter mode. The seed is the BLAKE2b key, and block `n` of the output
+//! is the 64-byte keyed hash of an empty input with the `node_offset` parameter set to `n`, so
+//! each block costs a single compression. Blocks are computed four at a time with [`finalize4`].
+//! The same seed always produces the same stream, which makes this suitable for
+//! reproducible simulations and property tests, and since the output is keyed BLAKE2b, it's also
+//! unpredictable to anyone who doesn't know the seed.
+//!
+//! A generator can have a personalization of up to 16 bytes, which gives it an independent stream
+//! for the same seed. [`fork`] derives a child generator from the parent's seed and a new
+//! personalization, and [`reseed`] mixes new entropy into the seed. Those derivations set
+//! `node_depth` to 1 and 2 respectively, which keeps them separate from the output blocks, where
+//! it's 0.
+//!
+//! With the optional `rand_core` Cargo feature, [`Drbg`] implements the [`RngCore`],
+//! [`CryptoRng`], and [`SeedableRng`] traits from `rand_core` 0.5.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::drbg::Drbg;
+//!
+//! let mut rng = Drbg::new(&[42; 32]);
+//! let mut bytes = [0; 100];
+//! rng.fill_bytes(&mut bytes);
+//! let x = rng.next_u64();
+//!
+//! // The same seed gives the same output.
+//! let mut rng2 = Drbg::new(&[42; 32]);
+//! let mut bytes2 = [0; 100];
+//! rng2.fill_bytes(&mut bytes2);
+//! assert_eq!(&bytes[..], &bytes2[..]);
+//! assert_eq!(x, rng2.next_u64());
+//!
+//! // Child generators are independent of each other and of the parent.
+//! let mut network = rng.fork(b"network");
+//! let mut disk = rng.fork(b"disk");
+//! assert!(network.next_u64() != disk.next_u64());
+//! ```
+//!
+//! [`Drbg`]: struct.Drbg.html
+//! [`finalize4`]: ../fn.finalize4.html
+//! [`fork`]: struct.Drbg.html#method.fork
+//! [`reseed`]: struct.Drbg.html#method.reseed
+//! [`RngCore`]: https://docs.rs/rand_core/0.5/rand_core/trait.RngCore.html
+//! [`CryptoRng`]: https://docs.rs/rand_core/0.5/rand_core/trait.CryptoRng.html
+//! [`SeedableRng`]: https://docs.rs/rand_core/0.5/rand_core/trait.SeedableRng.html
+
+use crate::finalize4;
+use crate::Params;
+use crate::OUTBYTES;
+use byteorder::{ByteOrder, LittleEndian};
+use core::cmp;
+use core::fmt;
+
+/// The length of a seed.
+pub const SEEDBYTES: usize = 32;
+
+const BUFFER_LENGTH: usize = 4 * OUTBYTES;
+const FORK_DEPTH: u8 = 1;
+const RESEED_DEPTH: u8 = 2;
+
+/// A deterministic random bit generator, running keyed BLAKE2b in counter mode.
+#[derive(Clone)]
+pub struct Drbg {
+    params: Params,
+    seed: [u8; SEEDBYTES],
+    personal: [u8; 16],
+    counter: u64,
+    buffer: [u8; BUFFER_LENGTH],
+    buffer_position: usize,
+}
+
+impl Drbg {
+    /// Create a generator from a 32-byte seed.
+    pub fn new(seed: &[u8; SEEDBYTES]) -> Self {
+        Self::with_personal(seed, &[])
+    }
+
+    /// Create a generator from a 32-byte seed and a personalization of up to 16 bytes. Different
+    /// personalizations give independent streams for the same seed.
+    pub fn with_personal(seed: &[u8; SEEDBYTES], personal: &[u8]) -> Self {
+        let mut params = Params::new();
+        params.key(seed).personal(personal);
+        let personal = params.personal;
+        Self {
+            params,
+            seed: *seed,
+            personal,
+            counter: 0,
+            buffer: [0; BUFFER_LENGTH],
+            buffer_position: BUFFER_LENGTH,
+        }
+    }
+
+    fn refill(&mut self) {
+        let mut state0 = self.params.clone().node_offset(self.counter).to_state();
+        let mut state1 = self.params.clone().node_offset(self.counter + 1).to_state();
+        let mut state2 = self.params.clone().node_offset(self.counter + 2).to_state();
+        let mut state3 = self.params.clone().node_offset(self.counter + 3).to_state();
+        let hashes = finalize4(&mut state0, &mut state1, &mut state2, &mut state3);
+        for (chunk, hash) in self.buffer.chunks_mut(OUTBYTES).zip(hashes.iter()) {
+            chunk.copy_from_slice(hash.as_bytes());
+        }
+        self.counter += 4;
+        self.buffer_position = 0;
+    }
+
+    /// Fill `dest` with the next bytes of output.
+    pub fn fill_bytes(&mut self, mut dest: &mut [u8]) {
+        while !dest.is_empty() {
+            if self.buffer_position == BUFFER_LENGTH {
+                self.refill();
+            }
+            let take = cmp::min(dest.len(), BUFFER_LENGTH - self.buffer_position);
+            let buffer_end = self.buffer_position + take;
+            dest[..take].copy_from_slice(&self.buffer[self.buffer_position..buffer_end]);
+            self.buffer_position = buffer_end;
+            dest = &mut dest[take..];
+        }
+    }
+
+    /// Return the next 4 bytes of output as a little-endian `u32`.
+    pub fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        LittleEndian::read_u32(&bytes)
+    }
+
+    /// Return the next 8 bytes of output as a little-endian `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        LittleEndian::read_u64(&bytes)
+    }
+
+    /// Derive a child generator with the given personalization, of up to 16 bytes. The child's
+    /// seed is a keyed hash of the parent's seed, so it doesn't depend on how much output the
+    /// parent has produced, and forking twice with the same personalization gives the same child.
+    /// Use different personalizations for independent children.
+    pub fn fork(&self, personal: &[u8]) -> Self {
+        let child_seed = Params::new()
+            .hash_length(SEEDBYTES)
+            .key(&self.seed)
+            .personal(personal)
+            .node_depth(FORK_DEPTH)
+            .to_state()
+            .finalize();
+        Self::with_personal(array_ref!(child_seed.as_bytes(), 0, SEEDBYTES), personal)
+    }
+
+    /// Mix `entropy` into the seed, and start a new stream. The new seed is a keyed hash of the
+    /// entropy, with the old seed as the key, so reseeding with the same entropy is deterministic
+    /// too.
+    pub fn reseed(&mut self, entropy: &[u8]) {
+        let new_seed = Params::new()
+            .hash_length(SEEDBYTES)
+            .key(&self.seed)
+            .personal(&self.personal)
+            .node_depth(RESEED_DEPTH)
+            .to_state()
+            .update(entropy)
+            .finalize();
+        *self = Self::with_personal(
+            array_ref!(new_seed.as_bytes(), 0, SEEDBYTES),
+            &self.personal,
+        );
+    }
+}
+
+impl fmt::Debug for Drbg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: Don't print the seed or the buffered output. Debug shouldn't leak secrets.
+        write!(
+            f,
+            "Drbg {{ personal: {:?}, counter: {} }}",
+            &self.personal, self.counter,
+        )
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::RngCore for Drbg {
+    fn next_u32(&mut self) -> u32 {
+        Drbg::next_u32(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        Drbg::next_u64(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        Drbg::fill_bytes(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        Drbg::fill_bytes(self, dest);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::CryptoRng for Drbg {}
+
+#[cfg(feature = "rand_core")]
+impl rand_core::SeedableRng for Drbg {
+    type Seed = [u8; SEEDBYTES];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Drbg::new(&seed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // These were computed with Python's hashlib, one keyed BLAKE2b hash per node offset, with the
+    // seed 0x00..0x1f.
+    const STREAM: &str = "84bfa69f0d90df7db2a3ee026042988b5bd9caa2320af1f371823dd28351202f8e6277c40c050711c8dd4e2c1ac30c34c9aed0bddd468b031287fe872675e0cc0569de6a436a927d59d0235a833be0b3c3ece138a1a7cc1f32f589311e1fbfcbc63a1baa92fa9aae7b6e45bfc736a73929fce1cb0f8e4ef1f5b749c4b168e1e8dad80aca441b973bd138c0ce450bbd17a914070c11a62f4d4113240891fc1435bbe1b8715f4e86b781715bd2924a2f221d44c2436ef7eb8eaca37b1c3b73633c641af6d7d0cc4e61b070602dbf0878b8d6cab7410e9ea74a21bf70862e561bb2a43f75e35e312f1c58a90074bcf5618acffd60a0338b6c0d2b5dfe99c68eef8e3627a612d68fc15b0e8d46574508b213f58e01bfc5a1aabbecbb212e1c380d96b7f946cda60970ce7f4b6721";
+    const PERSONAL_STREAM: &str = "0e86532eead37c94c92ab62f010561a6dc9378c4000d0b2b576bebcd334d0dccb4e44b1cdf90b2826837fb2d436a0acd7fe7a51201e0eb325d6f294fc886b5b23003f730a671a6da229a036a26d55ac2e66a54ce586ebe46afa429e7e0ae97d9e8e16e10";
+    const FORK_STREAM: &str = "251e55c6e07b84fd72a8d7bae6964b5a98dbf1d8da48def1e48aaadff3c3193f44c0977b90fd76ede11d9800a74fffd4e89c4280b9a8a22f6f93d6e1cd27f74d";
+    const RESEED_STREAM: &str = "5b25870b817c051a393b6f2471e7ba8cba49e00780c488f41d2b84a774e84ec5c925c20f357da6c4c89fcfb6054007239a7f447a9b10d14bff630887c73701bc";
+
+    fn test_seed() -> [u8; SEEDBYTES] {
+        let mut seed = [0; SEEDBYTES];
+        for (i, byte) in seed.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        seed
+    }
+
+    fn check_stream(rng: &mut Drbg, expected: &str) {
+        let expected = hex::decode(expected).unwrap();
+        let mut output = [0; 300];
+        let output = &mut output[..expected.len()];
+        rng.fill_bytes(output);
+        assert_eq!(&expected[..], &output[..]);
+    }
+
+    #[test]
+    fn test_vectors() {
+        check_stream(&mut Drbg::new(&test_seed()), STREAM);
+        check_stream(
+            &mut Drbg::with_personal(&test_seed(), b"drbg test"),
+            PERSONAL_STREAM,
+        );
+        check_stream(&mut Drbg::new(&test_seed()).fork(b"child"), FORK_STREAM);
+        let mut rng = Drbg::with_personal(&test_seed(), b"drbg test");
+        rng.reseed(b"entropy");
+        check_stream(&mut rng, RESEED_STREAM);
+    }
+
+    #[test]
+    fn test_read_sizes() {
+        let expected = hex::decode(STREAM).unwrap();
+        for &chunk_size in &[1, 7, 63, 64, 65, 256, 257] {
+            let mut rng = Drbg::new(&test_seed());
+            let mut output = [0; 300];
+            for chunk in output.chunks_mut(chunk_size) {
+                rng.fill_bytes(chunk);
+            }
+            assert_eq!(&expected[..], &output[..]);
+        }
+
+        let mut rng = Drbg::new(&test_seed());
+        assert_eq!(LittleEndian::read_u32(&expected[..4]), rng.next_u32());
+        assert_eq!(LittleEndian::read_u64(&expected[4..12]), rng.next_u64());
+    }
+
+    #[test]
+    fn test_fork_and_reseed() {
+        let mut parent = Drbg::new(&test_seed());
+        let child = parent.fork(b"child");
+        // Forking doesn't depend on the parent's position.
+        parent.fill_bytes(&mut [0; 1000]);
+        let mut child_again = parent.fork(b"child");
+        assert_eq!(child.clone().next_u64(), child_again.next_u64());
+        assert!(child.clone().next_u64() != parent.fork(b"other").next_u64());
+
+        // Reseeding starts a new stream, which depends on the entropy.
+        let mut reseeded1 = Drbg::new(&test_seed());
+        reseeded1.reseed(b"foo");
+        let mut reseeded2 = Drbg::new(&test_seed());
+        reseeded2.fill_bytes(&mut [0; 1000]);
+        reseeded2.reseed(b"foo");
+        let mut reseeded3 = Drbg::new(&test_seed());
+        reseeded3.reseed(b"bar");
+        let x = reseeded1.next_u64();
+        assert_eq!(x, reseeded2.next_u64());
+        assert!(x != reseeded3.next_u64());
+        assert!(x != Drbg::new(&test_seed()).next_u64());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_long_personal_panics() {
+        Drbg::with_personal(&test_seed(), &[0; 17]);
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn test_rand_core() {
+        use rand_core::{RngCore, SeedableRng};
+
+        let mut rng = Drbg::from_seed(test_seed());
+        let mut expected = Drbg::new(&test_seed());
+        assert_eq!(expected.next_u32(), RngCore::next_u32(&mut rng));
+        assert_eq!(expected.next_u64(), RngCore::next_u64(&mut rng));
+        let mut output = [0; 100];
+        let mut expected_output = [0; 100];
+        expected.fill_bytes(&mut expected_output);
+        rng.try_fill_bytes(&mut output).unwrap();
+        assert_eq!(&expected_output[..], &output[..]);
+
+        let mut rng1 = Drbg::seed_from_u64(42);
+        let mut rng2 = Drbg::seed_from_u64(42);
+        assert_eq!(rng1.next_u64(), rng2.next_u64());
+    }
+}
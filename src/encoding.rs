@@ -0,0 +1,193 @@
+// The RFC 4648 base64, base64url, and base32 encodings, for Hash. These are small enough to write
+// out here rather than take a dependency, and a hash is at most 64 bytes, so every encoded hash
+// fits in an EncodedString.
+//
+// Decoding is strict: after decoding, the result is encoded again and compared to the input. That
+// rejects wrong padding and nonzero trailing bits, so each hash has exactly one encoding.
+
+use crate::EncodedString;
+use crate::Hash;
+use crate::ParseHashError;
+use crate::OUTBYTES;
+
+pub(crate) struct Encoding {
+    alphabet: &'static [u8],
+    bits: u32,
+    // Pad the output with '=' to a multiple of this many characters. 1 means no padding.
+    pad_to: usize,
+    case_insensitive: bool,
+}
+
+pub(crate) const BASE64: Encoding = Encoding {
+    alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+    bits: 6,
+    pad_to: 4,
+    case_insensitive: false,
+};
+
+pub(crate) const BASE64URL: Encoding = Encoding {
+    alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+    bits: 6,
+    pad_to: 1,
+    case_insensitive: false,
+};
+
+pub(crate) const BASE32: Encoding = Encoding {
+    alphabet: b"abcdefghijklmnopqrstuvwxyz234567",
+    bits: 5,
+    pad_to: 1,
+    case_insensitive: true,
+};
+
+impl Encoding {
+    pub(crate) fn encode(&self, bytes: &[u8]) -> EncodedString {
+        let mask = (1 << self.bits) - 1;
+        let mut s = EncodedString::new();
+        let mut acc: u32 = 0;
+        let mut acc_bits = 0;
+        for &b in bytes {
+            acc = (acc << 8) | b as u32;
+            acc_bits += 8;
+            while acc_bits >= self.bits {
+                acc_bits -= self.bits;
+                s.push(self.alphabet[((acc >> acc_bits) & mask) as usize] as char);
+            }
+        }
+        if acc_bits > 0 {
+            s.push(self.alphabet[((acc << (self.bits - acc_bits)) & mask) as usize] as char);
+        }
+        while s.len() / self.pad_to * self.pad_to != s.len() {
+            s.push('=');
+        }
+        s
+    }
+
+    // Decode into `out` and return the number of bytes written. This fails if the input doesn't
+    // fit, but it doesn't check that the input is canonical. See is_canonical.
+    fn decode_unchecked(&self, input: &str, out: &mut [u8]) -> Result<usize, ParseHashError> {
+        let mut len = 0;
+        let mut acc: u32 = 0;
+        let mut acc_bits = 0;
+        for &c in input.as_bytes() {
+            if c == b'=' && self.pad_to > 1 {
+                // Anything after the first '=' gets checked by is_canonical.
+                break;
+            }
+            let c = if self.case_insensitive {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            };
+            let value = match self.alphabet.iter().position(|&a| a == c) {
+                Some(value) => value as u32,
+                None => return Err(ParseHashError::InvalidEncoding),
+            };
+            acc = ((acc << self.bits) | value) & 0xffff;
+            acc_bits += self.bits;
+            if acc_bits >= 8 {
+                if len == out.len() {
+                    return Err(ParseHashError::InvalidLength);
+                }
+                acc_bits -= 8;
+                out[len] = (acc >> acc_bits) as u8;
+                len += 1;
+            }
+        }
+        Ok(len)
+    }
+
+    fn is_canonical(&self, input: &str, bytes: &[u8]) -> bool {
+        let canonical = self.encode(bytes);
+        if self.case_insensitive {
+            canonical.eq_ignore_ascii_case(input)
+        } else {
+            canonical.as_str() == input
+        }
+    }
+
+    pub(crate) fn decode(&self, input: &str, out: &mut [u8]) -> Result<usize, ParseHashError> {
+        let len = self.decode_unchecked(input, out)?;
+        if !self.is_canonical(input, &out[..len]) {
+            return Err(ParseHashError::InvalidEncoding);
+        }
+        Ok(len)
+    }
+
+    pub(crate) fn decode_hash(&self, input: &str) -> Result<Hash, ParseHashError> {
+        let mut bytes = [0; OUTBYTES];
+        let len = self.decode_unchecked(input, &mut bytes)?;
+        // Check the length first, so that empty input is a length error.
+        let hash = Hash::from_bytes(&bytes[..len])?;
+        if !self.is_canonical(input, hash.as_bytes()) {
+            return Err(ParseHashError::InvalidEncoding);
+        }
+        Ok(hash)
+    }
+}
+
+const BASE58BTC_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+// Base58btc, the Bitcoin alphabet, which multibase uses for CIDs. It treats the input as a
+// big-endian number, except that each leading zero byte becomes a leading '1'. The quadratic
+// conversion here is fine for inputs the size of a CID. Unlike the RFC 4648 encodings, every
+// string decodes to a different input, so there's no canonical check.
+pub(crate) fn base58btc_encode(bytes: &[u8], out: &mut EncodedString) {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    // Digits of the number in base 58, least significant first. 128 digits hold 93 bytes.
+    let mut digits = [0u8; 128];
+    let mut num_digits = 0;
+    for &b in &bytes[zeros..] {
+        let mut carry = b as u32;
+        for digit in &mut digits[..num_digits] {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits[num_digits] = (carry % 58) as u8;
+            num_digits += 1;
+            carry /= 58;
+        }
+    }
+    for _ in 0..zeros {
+        out.push('1');
+    }
+    for &digit in digits[..num_digits].iter().rev() {
+        out.push(BASE58BTC_ALPHABET[digit as usize] as char);
+    }
+}
+
+pub(crate) fn base58btc_decode(input: &str, out: &mut [u8]) -> Result<usize, ParseHashError> {
+    let input = input.as_bytes();
+    let zeros = input.iter().take_while(|&&c| c == b'1').count();
+    if zeros > out.len() {
+        return Err(ParseHashError::InvalidLength);
+    }
+    // Bytes of the number, least significant first, after the leading zeros.
+    let (leading, rest) = out.split_at_mut(zeros);
+    for byte in leading.iter_mut() {
+        *byte = 0;
+    }
+    let mut len = 0;
+    for &c in &input[zeros..] {
+        let mut carry = match BASE58BTC_ALPHABET.iter().position(|&a| a == c) {
+            Some(value) => value as u32,
+            None => return Err(ParseHashError::InvalidEncoding),
+        };
+        for byte in &mut rest[..len] {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            if len == rest.len() {
+                return Err(ParseHashError::InvalidLength);
+            }
+            rest[len] = carry as u8;
+            len += 1;
+            carry >>= 8;
+        }
+    }
+    rest[..len].reverse();
+    Ok(zeros + len)
+}
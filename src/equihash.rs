@@ -0,0 +1,444 @@
+//! Verification of [Equihash] proof-of-work solutions, as used by Zcash.
+//!
+//! An Equihash solution for parameters `n` and `k` is a list of `2^k` distinct indices, whose
+//! `n`-bit BLAKE2b outputs XOR to zero. The indices also have to form a binary tree, where each
+//! pair of sibling subtrees collides on the next `n / (k + 1)` bits, and where the left subtree
+//! always starts with the smaller index. Zcash uses `n = 200, k = 9`, and some other chains use
+//! `n = 144, k = 5`.
+//!
+//! The outputs come from a BLAKE2b state personalized with `"ZcashPoW"` followed by `n` and `k`
+//! as little-endian 32-bit integers, which hashes the block header and the nonce, and then a
+//! 32-bit little-endian counter. Each hash is long enough for several outputs. A solution needs
+//! `2^k` of these hashes, so this module computes them four at a time with [`update4`] and
+//! [`finalize4`].
+//!
+//! Solutions are in the "minimal" encoding from the [Zcash protocol spec], where each index takes
+//! `n / (k + 1) + 1` bits, big-endian. That's 1344 bytes for `n = 200, k = 9`, and 100 bytes for
+//! `n = 144, k = 5`.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::equihash;
+//!
+//! // A solution for n = 48, k = 5, which is small enough for tests.
+//! let header = b"Equihash is an asymmetric PoW based on the Generalised Birthday problem.";
+//! let mut nonce = [0; 32];
+//! nonce[0] = 1;
+//! let solution = hex::decode(
+//!     "043943a8b7357f89fb0f5da13a820969b7f6173fc9f06188ccc0cb17ad9516e3de6f3df8",
+//! ).unwrap();
+//! assert!(equihash::verify_solution(48, 5, header, &nonce, &solution).is_ok());
+//!
+//! nonce[0] = 2;
+//! assert!(equihash::verify_solution(48, 5, header, &nonce, &solution).is_err());
+//! ```
+//!
+//! [Equihash]: https://eprint.iacr.org/2015/946.pdf
+//! [`update4`]: ../fn.update4.html
+//! [`finalize4`]: ../fn.finalize4.html
+//! [Zcash protocol spec]: https://zips.z.cash/protocol/protocol.pdf
+
+use crate::finalize4;
+use crate::update4;
+use crate::Params as Blake2bParams;
+use crate::State;
+use byteorder::{ByteOrder, LittleEndian};
+use core::fmt;
+
+use std;
+use std::vec::Vec;
+
+/// The ways a solution can fail to verify.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// `n` and `k` aren't valid Equihash parameters.
+    InvalidParams,
+    /// The solution isn't the right length for `n` and `k`.
+    InvalidSolutionLength,
+    /// Two sibling subtrees don't collide on the bits they need to.
+    Collision,
+    /// Two sibling subtrees are in the wrong order. The left subtree has to start with the smaller
+    /// index.
+    OutOfOrder,
+    /// An index appears more than once.
+    DuplicateIndices,
+    /// All the collisions are correct, but the outputs don't XOR to zero.
+    NonZeroRootHash,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidParams => write!(f, "invalid Equihash parameters"),
+            Error::InvalidSolutionLength => write!(f, "invalid solution length"),
+            Error::Collision => write!(f, "invalid collision length between subtrees"),
+            Error::OutOfOrder => write!(f, "subtrees out of order"),
+            Error::DuplicateIndices => write!(f, "duplicate indices"),
+            Error::NonZeroRootHash => write!(f, "root hash is not zero"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Clone, Copy, Debug)]
+struct EquihashParams {
+    n: u32,
+    k: u32,
+}
+
+impl EquihashParams {
+    fn new(n: u32, k: u32) -> Result<Self, Error> {
+        // These are the same checks as Zcash, plus limits on the index size and the number of
+        // indices, so that the indices fit in a u32 and the solution length doesn't overflow.
+        // Note that k < n means the collision length is at least one bit.
+        if n / 8 * 8 != n || n > 512 || !(3..32).contains(&k) || k >= n {
+            return Err(Error::InvalidParams);
+        }
+        let collision_bits = n / (k + 1);
+        if collision_bits * (k + 1) != n || collision_bits >= 32 {
+            return Err(Error::InvalidParams);
+        }
+        Ok(Self { n, k })
+    }
+
+    fn collision_bit_length(&self) -> usize {
+        (self.n / (self.k + 1)) as usize
+    }
+
+    fn collision_byte_length(&self) -> usize {
+        (self.collision_bit_length() - 1) / 8 + 1
+    }
+
+    fn indices_per_hash_output(&self) -> u32 {
+        512 / self.n
+    }
+
+    fn hash_output(&self) -> usize {
+        (self.indices_per_hash_output() * self.n / 8) as usize
+    }
+
+    fn solution_length(&self) -> u64 {
+        (1 << self.k) * (self.collision_bit_length() as u64 + 1) / 8
+    }
+
+    fn personal(&self) -> [u8; 16] {
+        let mut personal = [0; 16];
+        personal[..8].copy_from_slice(b"ZcashPoW");
+        LittleEndian::write_u32(&mut personal[8..12], self.n);
+        LittleEndian::write_u32(&mut personal[12..], self.k);
+        personal
+    }
+}
+
+/// Check an Equihash solution for the parameters `n` and `k`, the block header `header` (which
+/// doesn't include the nonce), and `nonce`. The solution is in the minimal encoding. In Zcash,
+/// the header is the first 108 bytes of the block header, and the nonce is 32 bytes.
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::equihash;
+/// // Zcash solutions are 1344 bytes.
+/// assert_eq!(
+///     Err(equihash::Error::InvalidSolutionLength),
+///     equihash::verify_solution(200, 9, b"header", &[0; 32], &[0; 1343]),
+/// );
+/// ```
+pub fn verify_solution(
+    n: u32,
+    k: u32,
+    header: &[u8],
+    nonce: &[u8],
+    solution: &[u8],
+) -> Result<(), Error> {
+    let params = EquihashParams::new(n, k)?;
+    let indices = indices_from_minimal(&params, solution)?;
+
+    let mut base_state = Blake2bParams::new()
+        .hash_length(params.hash_output())
+        .personal(&params.personal())
+        .to_state();
+    base_state.update(header);
+    base_state.update(nonce);
+
+    let leaves = leaf_nodes(&params, &base_state, &indices);
+    let root = validate_subtree(&params, &leaves)?;
+    if root.hash.iter().all(|&b| b == 0) {
+        Ok(())
+    } else {
+        Err(Error::NonZeroRootHash)
+    }
+}
+
+// Unpack the (c + 1)-bit big-endian indices of the minimal encoding.
+fn indices_from_minimal(params: &EquihashParams, solution: &[u8]) -> Result<Vec<u32>, Error> {
+    if solution.len() as u64 != params.solution_length() {
+        return Err(Error::InvalidSolutionLength);
+    }
+    let index_bits = params.collision_bit_length() + 1;
+    let mut indices = Vec::with_capacity(1 << params.k);
+    let mut accumulator = 0u64;
+    let mut accumulator_bits = 0;
+    for &byte in solution {
+        accumulator = (accumulator << 8) | byte as u64;
+        accumulator_bits += 8;
+        while accumulator_bits >= index_bits {
+            accumulator_bits -= index_bits;
+            indices.push((accumulator >> accumulator_bits) as u32);
+            accumulator &= (1 << accumulator_bits) - 1;
+        }
+    }
+    Ok(indices)
+}
+
+struct Node {
+    // The remaining collision chunks, each padded to a whole number of bytes.
+    hash: Vec<u8>,
+    indices: Vec<u32>,
+}
+
+// Compute the output for each index, four BLAKE2b hashes at a time. Index i comes from the hash
+// of counter i / indices_per_hash_output. There are 2^k indices, and k is at least 3, so they
+// always fill all four lanes.
+fn leaf_nodes(params: &EquihashParams, base_state: &State, indices: &[u32]) -> Vec<Node> {
+    let per_hash = params.indices_per_hash_output();
+    let output_bytes = params.n as usize / 8;
+    let mut leaves = Vec::with_capacity(indices.len());
+    for chunk in indices.chunks(4) {
+        let mut counters = [[0; 4]; 4];
+        for (counter, &index) in counters.iter_mut().zip(chunk) {
+            LittleEndian::write_u32(counter, index / per_hash);
+        }
+        let mut state0 = base_state.clone();
+        let mut state1 = base_state.clone();
+        let mut state2 = base_state.clone();
+        let mut state3 = base_state.clone();
+        update4(
+            &mut state0,
+            &mut state1,
+            &mut state2,
+            &mut state3,
+            &counters[0],
+            &counters[1],
+            &counters[2],
+            &counters[3],
+        );
+        let hashes = finalize4(&mut state0, &mut state1, &mut state2, &mut state3);
+        for (hash, &index) in hashes.iter().zip(chunk) {
+            let start = (index % per_hash) as usize * output_bytes;
+            leaves.push(Node {
+                hash: expand(params, &hash.as_bytes()[start..][..output_bytes]),
+                indices: vec![index],
+            });
+        }
+    }
+    leaves
+}
+
+// Split an n-bit output into k + 1 chunks of c bits, and right-align each chunk in its own
+// collision_byte_length bytes, so that collisions can be compared bytewise.
+fn expand(params: &EquihashParams, output: &[u8]) -> Vec<u8> {
+    let chunk_bits = params.collision_bit_length();
+    let chunk_bytes = params.collision_byte_length();
+    let mut expanded = Vec::with_capacity((params.k as usize + 1) * chunk_bytes);
+    let mut accumulator = 0u64;
+    let mut accumulator_bits = 0;
+    for &byte in output {
+        accumulator = (accumulator << 8) | byte as u64;
+        accumulator_bits += 8;
+        while accumulator_bits >= chunk_bits {
+            accumulator_bits -= chunk_bits;
+            let chunk = accumulator >> accumulator_bits;
+            for i in (0..chunk_bytes).rev() {
+                expanded.push((chunk >> (8 * i)) as u8);
+            }
+            accumulator &= (1 << accumulator_bits) - 1;
+        }
+    }
+    expanded
+}
+
+fn validate_subtree(params: &EquihashParams, leaves: &[Node]) -> Result<Node, Error> {
+    if leaves.len() == 1 {
+        return Ok(Node {
+            hash: leaves[0].hash.clone(),
+            indices: leaves[0].indices.clone(),
+        });
+    }
+    let mid = leaves.len() / 2;
+    let left = validate_subtree(params, &leaves[..mid])?;
+    let right = validate_subtree(params, &leaves[mid..])?;
+    let trim = params.collision_byte_length();
+    if left.hash[..trim] != right.hash[..trim] {
+        return Err(Error::Collision);
+    }
+    if right.indices[0] < left.indices[0] {
+        return Err(Error::OutOfOrder);
+    }
+    if left
+        .indices
+        .iter()
+        .any(|index| right.indices.contains(index))
+    {
+        return Err(Error::DuplicateIndices);
+    }
+    let hash = left.hash[trim..]
+        .iter()
+        .zip(&right.hash[trim..])
+        .map(|(a, b)| a ^ b)
+        .collect();
+    let mut indices = left.indices;
+    indices.extend_from_slice(&right.indices);
+    Ok(Node { hash, indices })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEADER: &[u8] =
+        b"Equihash is an asymmetric PoW based on the Generalised Birthday problem.";
+
+    // These solutions were found with an independent Python implementation of Wagner's algorithm,
+    // using hashlib's BLAKE2b. The nonce is the given integer as 32 little-endian bytes. They
+    // supplement the zcashd vectors in tests/equihash_tests.rs with smaller parameters.
+    const SOLUTIONS: &[(u32, u32, u8, &str)] = &[
+        (16, 3, 6, "0f8f04c659"),
+        (48, 5, 1, "043943a8b7357f89fb0f5da13a820969b7f6173fc9f06188ccc0cb17ad9516e3de6f3df8"),
+        (96, 5, 0, "0777227d57329d40f498a724be1bbf197a19aded2c9ff37e5602b73c0b80ecb5a99c19b2ea9dd258cc7223d0d595fd9ab1a5621d4be541cd327ab9c481b6b17149a98848"),
+        (96, 5, 0, "061d513acededa4a68b24565735909bfcf13941ff092907b97523efdac38a4cdb68708191e15c530f1de82b64581a1641be87215fc74a7edb47ae2074e5d9666f8f7b1fe"),
+        (200, 9, 1, "000bd1567c9891b4f65411794eea00713c6e92dcef2a0c7af4e46becd58c4646481a4d6f3ae772ba19fb263a87742621a5971ad814948eb9e5d574cf1e43b0483e85d736e23627b6112485ed7720f94a7257e31902722c83b2c60d0c45ef62e619eec7067b211f1cfa1154fdbd50a922456d20c11b511349aee0997892bd09e40fe69f5ac9e1ec9683e17cbdb71f1e891f00a740527275c02fa60ff2834479afdb7869f46d79573f009687134502b132b7f344de59a8c1e9a0f61666e1075e55ad4086a351dbf5f2d9df6b0dd0bd3a997f9b033ea880440526d5d25710ae8bf546c90d7c2a356705144c1d1289ae5cfcc282e4174c56b8f7527976b608c2c8464b5504f10d45135cdf4e6569c92a35f80a1b0e51d26bc9218789f4626830d8fba7c13a7f59672234079b5463e509709a7419767fb8191e56c9773b654b76e5745cb39b6547296d496da69e9763351ff80168363819166d0b23ba610245b1fb3121de3ce41b0b7c10e2ec466affa1576165a26d5932a9ed766f2715306594f118a31ec80f11bf6bf9eff6531bdcb2401c618ccf6572b519e7da13aca7667a5595be99665f041890a98ea38cc9bb735482aef73a862167fcccb70cffc347a0d7f8b1bc99b6694ee75442918c5b46f5383346f36b1390e0c3cf89ab6bda94e273745d5646526a64de23def1d59bafd554825b7c46cce41ad5ec075a14f51c936c9d3c4891d6097ff28200af72e54e1df11b664f0905cd9c72427f9f3df8c8a8b5e8c1b90d9b82a72f6b0a7ff9e0c2398a29570549679c60ae0f9d641eb0de3ad378f96315ec3a51f5a57fdd37e209ba154e71218e73c871917814eb1440815d7e44031daa4401c0cf29ed4a8a746de2d76a214572de16881bdf7464e6518af511d9f57bc2365f26fc20f9e50427ab9bb66056a3d7019a7819895f584699d23acf0901a6103d2c5c69910647d58d6baf4157364d1a6dce0285fad71512e21357a60350e54ad8faea799adacc13c91cfc954ce47fe48291f95b71c95b52367e7c872e2513d7396cb44b8fe706b75e75f76a133ada1fbb06a4fb7afd6b86c7667242409e4ef02dfccfdf42c31ac2f3b1c268f2d9bf6574ab51d5dcd6a99216cf2f169175352b0bae4afc77d5f81e344aaa34b53b302e29a3a55dcba0ffd9caef4342ce78e81d54075c88e9045f6b008f9c2e8b9be06596ef6cc96e4c4dfbffe757c88f3ca762df17950f37189f75c133b15bbda2002e8cb4ada990fe75f21ee3e05a4132f9106dd8f9cc3fd59ac9e8a0c6e10fec046e26c49e3a711a39a70d1864d6c428cb41d15ae4c4ab10f598217fbd8d2bb02b9e6d4a62a68a754e104478ca400675d3b9720ad5384917fa68932983a8d2263e5165f9de89e1b5d0c34a2c278b3ba391a15df0c9ebf8d1bcf7aa0ffe1f4201cb7b2ac3ac9029cded24e6b2592af3522c3f63f218f583e1bb9852b9776692d1364ad1f1e772b863ff3cafd2614ea4a791b10b46e26a436dea593414a8a145156fbc41d3e590ec46091f2965af032536faed110d11897efb6435fff8f603c0d865c754ffbd96d6224dfc24384fe01b8bc74296a2de763a92d3f5b7bc8c16cdfd80e8666b8ba0b5f464e4c7d7065e6f9a814e5d2e34d2a76710fdd6e49794b2eeb25ea56e98d1a5073e613d718cd28b6ef0517515e12ea1c376b108c55bdf447678e976919b50780b3ee0c36a5252787f61169f6e7bb3550e3b51eba64a7432f6c9c5cebab8af247c3e19b4725477c6496410c64ef9ea86083c68fa1258d700ce5f3d5322d684b6866db26efcac1dea9a33da5dad40d4e962ee92c413ff2d67253913763acf2d6b742424d1c9eae5dbb2e43b6a0b99dac7d1fa776397556543f6f11bd2bfc9e0eb6ab07add6e184213"),
+    ];
+
+    // Every collision is correct in this n = 48, k = 5 solution for nonce 1, except the last one
+    // is only on the first chunk of the remaining bits, so the root isn't zero.
+    const NONZERO_ROOT: &str =
+        "002253124446853bb129f86ebbb375e647782b3265fd445c6d475131a99c1455f6bdb3a4";
+
+    fn nonce(n: u8) -> [u8; 32] {
+        let mut nonce = [0; 32];
+        nonce[0] = n;
+        nonce
+    }
+
+    fn minimal_from_indices(params: &EquihashParams, indices: &[u32]) -> Vec<u8> {
+        let index_bits = params.collision_bit_length() + 1;
+        let mut solution = Vec::new();
+        let mut accumulator = 0u64;
+        let mut accumulator_bits = 0;
+        for &index in indices {
+            accumulator = (accumulator << index_bits) | index as u64;
+            accumulator_bits += index_bits;
+            while accumulator_bits >= 8 {
+                accumulator_bits -= 8;
+                solution.push((accumulator >> accumulator_bits) as u8);
+            }
+        }
+        solution
+    }
+
+    #[test]
+    fn test_valid_solutions() {
+        for &(n, k, nonce_byte, solution) in SOLUTIONS {
+            let solution = hex::decode(solution).unwrap();
+            assert_eq!(
+                Ok(()),
+                verify_solution(n, k, HEADER, &nonce(nonce_byte), &solution)
+            );
+            // A different nonce or header ruins the collisions.
+            assert_eq!(
+                Err(Error::Collision),
+                verify_solution(n, k, HEADER, &nonce(nonce_byte + 1), &solution)
+            );
+            assert_eq!(
+                Err(Error::Collision),
+                verify_solution(n, k, b"other header", &nonce(nonce_byte), &solution)
+            );
+        }
+    }
+
+    #[test]
+    fn test_minimal_encoding_round_trip() {
+        for &(n, k, _, solution) in SOLUTIONS {
+            let params = EquihashParams::new(n, k).unwrap();
+            let solution = hex::decode(solution).unwrap();
+            let indices = indices_from_minimal(&params, &solution).unwrap();
+            assert_eq!(1 << k, indices.len());
+            assert!(indices.iter().all(|&i| i < 1 << (n / (k + 1) + 1)));
+            assert_eq!(solution, minimal_from_indices(&params, &indices));
+        }
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        for &(n, k) in &[
+            (0, 0),
+            (201, 9),
+            (200, 2),
+            (200, 8),
+            (256, 7),
+            (520, 12),
+            (8, 8),
+        ] {
+            assert_eq!(
+                Err(Error::InvalidParams),
+                verify_solution(n, k, HEADER, &nonce(0), &[0; 1344])
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_solution_length() {
+        for &(n, k, length) in &[(200, 9, 1344), (144, 5, 100), (96, 5, 68), (48, 5, 36)] {
+            for &bad_length in &[0, length - 1, length + 1] {
+                assert_eq!(
+                    Err(Error::InvalidSolutionLength),
+                    verify_solution(n, k, HEADER, &nonce(0), &vec![0; bad_length])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_tampered_solutions() {
+        let params = EquihashParams::new(48, 5).unwrap();
+        // The n = 48, k = 5 solution for nonce 1.
+        let solution = hex::decode(SOLUTIONS[1].3).unwrap();
+        let indices = indices_from_minimal(&params, &solution).unwrap();
+        let verify = |indices: &[u32]| {
+            let solution = minimal_from_indices(&params, indices);
+            verify_solution(48, 5, HEADER, &nonce(1), &solution)
+        };
+        assert_eq!(Ok(()), verify(&indices));
+
+        // Changing any index breaks a collision.
+        for i in 0..indices.len() {
+            let mut bad = indices.clone();
+            bad[i] ^= 1;
+            assert_eq!(Err(Error::Collision), verify(&bad));
+        }
+
+        // Swapping sibling subtrees keeps the collisions, but breaks the ordering.
+        for &half in &[1, 2, 4, 8, 16] {
+            let mut bad = indices.clone();
+            for i in 0..half {
+                bad.swap(i, i + half);
+            }
+            assert_eq!(Err(Error::OutOfOrder), verify(&bad));
+        }
+
+        // Repeating a subtree collides with itself, but it repeats the indices.
+        let mut bad = indices.clone();
+        let (left, right) = bad.split_at_mut(16);
+        right.copy_from_slice(left);
+        assert_eq!(Err(Error::DuplicateIndices), verify(&bad));
+
+        let bad = hex::decode(NONZERO_ROOT).unwrap();
+        assert_eq!(
+            Err(Error::NonZeroRootHash),
+            verify_solution(48, 5, HEADER, &nonce(1), &bad)
+        );
+    }
+}
@@ -103,6 +103,34 @@ impl Implementation {
         }
     }
 
+    // The same as compress, but with a variable number of rounds. The message
+    // schedule repeats every 10 rounds, so 12 rounds is regular BLAKE2b.
+    pub fn compress_rounds(
+        &self,
+        state_words: &mut [u64; 8],
+        msg: &[u8; BLOCKBYTES],
+        count: u128,
+        lastblock: u64,
+        lastnode: u64,
+        rounds: u32,
+    ) {
+        match self.0 {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 => unsafe {
+                avx2::compress_rounds(state_words, msg, count, lastblock, lastnode, rounds)
+            },
+            // There is no SSE4.1 implementation of compress_rounds. Fall back
+            // to portable.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE41 => {
+                portable::compress_rounds(state_words, msg, count, lastblock, lastnode, rounds)
+            }
+            Platform::Portable => {
+                portable::compress_rounds(state_words, msg, count, lastblock, lastnode, rounds)
+            }
+        }
+    }
+
     pub fn transpose2(&self, words0: &[u64; 8], words1: &[u64; 8]) -> [u64x2; 8] {
         // Currently there's only the portable implementation of transpose2.
         portable::transpose2(words0, words1)
@@ -242,6 +270,211 @@ impl Implementation {
             }
         }
     }
+
+    pub fn transpose8(&self, words: [&[u64; 8]; 8]) -> [u64x8; 8] {
+        // There are no eight-way transpositions yet. Do two four-way ones.
+        let low = self.transpose4(words[0], words[1], words[2], words[3]);
+        let high = self.transpose4(words[4], words[5], words[6], words[7]);
+        let mut transposed = [u64x8([0; 8]); 8];
+        for ((vec, low_half), high_half) in transposed.iter_mut().zip(&low).zip(&high) {
+            let halves = vec.split_mut();
+            halves[0] = *low_half;
+            halves[1] = *high_half;
+        }
+        transposed
+    }
+
+    pub fn untranspose8(&self, transposed: &[u64x8; 8], out: [&mut [u64; 8]; 8]) {
+        let [out0, out1, out2, out3, out4, out5, out6, out7] = out;
+        let mut low = [u64x4([0; 4]); 8];
+        let mut high = [u64x4([0; 4]); 8];
+        for ((vec, low_half), high_half) in transposed.iter().zip(&mut low).zip(&mut high) {
+            *low_half = vec.split()[0];
+            *high_half = vec.split()[1];
+        }
+        self.untranspose4(&low, out0, out1, out2, out3);
+        self.untranspose4(&high, out4, out5, out6, out7);
+    }
+
+    pub fn compress8(
+        &self,
+        transposed_state_words: &mut [u64x8; 8],
+        msgs: [&[u8; BLOCKBYTES]; 8],
+        count_low: &u64x8,
+        count_high: &u64x8,
+        lastblock: &u64x8,
+        lastnode: &u64x8,
+    ) {
+        match self.0 {
+            // AVX2 runs the two halves with their rounds interleaved. A future
+            // AVX-512 implementation could do all eight lanes in one pass.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 => unsafe {
+                avx2::compress8_transposed(
+                    transposed_state_words,
+                    msgs,
+                    count_low,
+                    count_high,
+                    lastblock,
+                    lastnode,
+                );
+            },
+            // There is no SSE4.1 implementation of compress8. Fall back to
+            // two compress4 calls.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE41 => self.compress8_in_halves(
+                transposed_state_words,
+                msgs,
+                count_low,
+                count_high,
+                lastblock,
+                lastnode,
+            ),
+            Platform::Portable => self.compress8_in_halves(
+                transposed_state_words,
+                msgs,
+                count_low,
+                count_high,
+                lastblock,
+                lastnode,
+            ),
+        }
+    }
+
+    fn compress8_in_halves(
+        &self,
+        transposed_state_words: &mut [u64x8; 8],
+        msgs: [&[u8; BLOCKBYTES]; 8],
+        count_low: &u64x8,
+        count_high: &u64x8,
+        lastblock: &u64x8,
+        lastnode: &u64x8,
+    ) {
+        for half in 0..2 {
+            let mut state_half = [u64x4([0; 4]); 8];
+            for (vec_half, vec) in state_half.iter_mut().zip(transposed_state_words.iter()) {
+                *vec_half = vec.split()[half];
+            }
+            self.compress4(
+                &mut state_half,
+                msgs[4 * half],
+                msgs[4 * half + 1],
+                msgs[4 * half + 2],
+                msgs[4 * half + 3],
+                &count_low.split()[half],
+                &count_high.split()[half],
+                &lastblock.split()[half],
+                &lastnode.split()[half],
+            );
+            for (vec_half, vec) in state_half.iter().zip(transposed_state_words.iter_mut()) {
+                vec.split_mut()[half] = *vec_half;
+            }
+        }
+    }
+
+    // One round of the BlaMka permutation from Argon2. This is the BLAKE2b
+    // round with no message words, where each addition also adds twice the
+    // product of the low 32 bits of its operands.
+    pub fn blamka_round(&self, v: &mut [u64; 16]) {
+        match self.0 {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 => unsafe { avx2::blamka_round(v) },
+            // There is no SSE4.1 implementation of blamka_round. Fall back to
+            // portable.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE41 => portable::blamka_round(v),
+            Platform::Portable => portable::blamka_round(v),
+        }
+    }
+
+    pub fn blake2s_compress(
+        &self,
+        state_words: &mut [u32; 8],
+        msg: &[u8; blake2s::BLOCKBYTES],
+        count: u64,
+        lastblock: u32,
+        lastnode: u32,
+    ) {
+        match self.0 {
+            // There is no AVX2 implementation of the single-state BLAKE2s
+            // compression function. It's limited by the width of a single
+            // state, so fall back to SSE4.1.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 | Platform::SSE41 => unsafe {
+                blake2s::sse41::compress(state_words, msg, count, lastblock, lastnode)
+            },
+            Platform::Portable => {
+                blake2s::portable::compress(state_words, msg, count, lastblock, lastnode)
+            }
+        }
+    }
+
+    pub fn blake2s_transpose8(&self, words: [&[u32; 8]; 8]) -> [u32x8; 8] {
+        match self.0 {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 => unsafe { blake2s::avx2::transpose8(words) },
+            // There is no SSE4.1 implementation of transpose8 yet.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE41 => blake2s::portable::transpose8(words),
+            Platform::Portable => blake2s::portable::transpose8(words),
+        }
+    }
+
+    pub fn blake2s_untranspose8(&self, transposed: &[u32x8; 8], out: [&mut [u32; 8]; 8]) {
+        match self.0 {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 => unsafe { blake2s::avx2::untranspose8(transposed, out) },
+            // There is no SSE4.1 implementation of untranspose8 yet.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE41 => blake2s::portable::untranspose8(transposed, out),
+            Platform::Portable => blake2s::portable::untranspose8(transposed, out),
+        }
+    }
+
+    pub fn blake2s_compress8(
+        &self,
+        transposed_state_words: &mut [u32x8; 8],
+        msgs: [&[u8; blake2s::BLOCKBYTES]; 8],
+        count_low: &u32x8,
+        count_high: &u32x8,
+        lastblock: &u32x8,
+        lastnode: &u32x8,
+    ) {
+        match self.0 {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 => unsafe {
+                blake2s::avx2::compress8_transposed(
+                    transposed_state_words,
+                    msgs,
+                    count_low,
+                    count_high,
+                    lastblock,
+                    lastnode,
+                );
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE41 => unsafe {
+                blake2s::sse41::compress8_transposed(
+                    transposed_state_words,
+                    msgs,
+                    count_low,
+                    count_high,
+                    lastblock,
+                    lastnode,
+                );
+            },
+            Platform::Portable => {
+                blake2s::portable::compress8_transposed(
+                    transposed_state_words,
+                    msgs,
+                    count_low,
+                    count_high,
+                    lastblock,
+                    lastnode,
+                );
+            }
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -298,6 +531,96 @@ impl core::ops::DerefMut for u64x4 {
     }
 }
 
+#[derive(Copy, Clone, Debug)]
+#[repr(C, align(64))]
+pub struct u64x8(pub [u64; 8]);
+
+impl u64x8 {
+    #[inline(always)]
+    pub(crate) fn split(&self) -> &[u64x4; 2] {
+        // Safety note: The 64-byte alignment of u64x8 guarantees that each
+        // half of it will be 32-byte aligned, and the C repr guarantees that
+        // the layout is exactly eight packed u64's.
+        unsafe { &*(self as *const u64x8 as *const [u64x4; 2]) }
+    }
+
+    #[inline(always)]
+    pub(crate) fn split_mut(&mut self) -> &mut [u64x4; 2] {
+        // Safety note: The 64-byte alignment of u64x8 guarantees that each
+        // half of it will be 32-byte aligned, and the C repr guarantees that
+        // the layout is exactly eight packed u64's.
+        unsafe { &mut *(self as *mut u64x8 as *mut [u64x4; 2]) }
+    }
+}
+
+impl core::ops::Deref for u64x8 {
+    type Target = [u64; 8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl core::ops::DerefMut for u64x8 {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, align(16))]
+pub struct u32x4(pub [u32; 4]);
+
+impl core::ops::Deref for u32x4 {
+    type Target = [u32; 4];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl core::ops::DerefMut for u32x4 {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, align(32))]
+pub struct u32x8(pub [u32; 8]);
+
+impl u32x8 {
+    #[inline(always)]
+    pub(crate) fn split(&self) -> &[u32x4; 2] {
+        // Safety note: The 32-byte alignment of u32x8 guarantees that each
+        // half of it will be 16-byte aligned, and the C repr guarantees that
+        // the layout is exactly eight packed u32's.
+        unsafe { &*(self as *const u32x8 as *const [u32x4; 2]) }
+    }
+
+    #[inline(always)]
+    pub(crate) fn split_mut(&mut self) -> &mut [u32x4; 2] {
+        // Safety note: The 32-byte alignment of u32x8 guarantees that each
+        // half of it will be 16-byte aligned, and the C repr guarantees that
+        // the layout is exactly eight packed u32's.
+        unsafe { &mut *(self as *mut u32x8 as *mut [u32x4; 2]) }
+    }
+}
+
+impl core::ops::Deref for u32x8 {
+    type Target = [u32; 8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl core::ops::DerefMut for u32x8 {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -362,6 +685,18 @@ mod test {
         state
     }
 
+    fn exercise_rounds(imp: Implementation, i: u64, rounds: u32) -> [u64; 8] {
+        let mut state = input_state_words(i);
+        let block = input_msg_block(0x10 + i);
+        let count_low = 0x20 + i;
+        let count_high = 0x30 + i;
+        let count = count_low as u128 + ((count_high as u128) << 64);
+        let lastblock = 0x40 + i;
+        let lastnode = 0x50 + i;
+        imp.compress_rounds(&mut state, &block, count, lastblock, lastnode, rounds);
+        state
+    }
+
     fn exercise_2(imp: Implementation, i: u64) -> [[u64; 8]; 2] {
         let mut state0 = input_state_words(i);
         let mut state1 = input_state_words(i + 1);
@@ -420,6 +755,123 @@ mod test {
         [state0, state1, state2, state3]
     }
 
+    fn exercise_8(imp: Implementation, i: u64) -> [[u64; 8]; 8] {
+        let mut states = [[0; 8]; 8];
+        let mut blocks = [[0; 128]; 8];
+        let mut count_low = u64x8([0; 8]);
+        let mut count_high = u64x8([0; 8]);
+        let mut lastblock = u64x8([0; 8]);
+        let mut lastnode = u64x8([0; 8]);
+        for j in 0..8 {
+            states[j] = input_state_words(i + j as u64);
+            blocks[j] = input_msg_block(0x10 + i + j as u64);
+            count_low[j] = 0x20 + i + j as u64;
+            count_high[j] = 0x30 + i + j as u64;
+            lastblock[j] = 0x40 + i + j as u64;
+            lastnode[j] = 0x50 + i + j as u64;
+        }
+        let mut transposed = imp.transpose8([
+            &states[0], &states[1], &states[2], &states[3], &states[4], &states[5], &states[6],
+            &states[7],
+        ]);
+        imp.compress8(
+            &mut transposed,
+            [
+                &blocks[0], &blocks[1], &blocks[2], &blocks[3], &blocks[4], &blocks[5], &blocks[6],
+                &blocks[7],
+            ],
+            &count_low,
+            &count_high,
+            &lastblock,
+            &lastnode,
+        );
+        let [state0, state1, state2, state3, state4, state5, state6, state7] = &mut states;
+        imp.untranspose8(
+            &transposed,
+            [
+                state0, state1, state2, state3, state4, state5, state6, state7,
+            ],
+        );
+        states
+    }
+
+    fn exercise_blamka(imp: Implementation, i: u64) -> [u64; 16] {
+        let mut v = [0; 16];
+        for (j, word) in v.iter_mut().enumerate() {
+            *word = (0x0123456789abcdef * (i + j as u64 + 1)).rotate_left(j as u32);
+        }
+        imp.blamka_round(&mut v);
+        v
+    }
+
+    fn blake2s_input_state_words(i: u32) -> [u32; 8] {
+        let mut words = [0; 8];
+        for (j, word) in words.iter_mut().enumerate() {
+            *word = i + j as u32;
+        }
+        words
+    }
+
+    fn blake2s_input_msg_block(i: u32) -> [u8; 64] {
+        let mut block = [0; 64];
+        for (j, byte) in block.iter_mut().enumerate() {
+            *byte = (i + j as u32) as u8;
+        }
+        block
+    }
+
+    fn blake2s_exercise_1(imp: Implementation, i: u32) -> [u32; 8] {
+        let mut state = blake2s_input_state_words(i);
+        let block = blake2s_input_msg_block(0x10 + i);
+        let count_low = 0x20 + i;
+        let count_high = 0x30 + i;
+        let count = count_low as u64 + ((count_high as u64) << 32);
+        let lastblock = 0x40 + i;
+        let lastnode = 0x50 + i;
+        imp.blake2s_compress(&mut state, &block, count, lastblock, lastnode);
+        state
+    }
+
+    fn blake2s_exercise_8(imp: Implementation, i: u32) -> [[u32; 8]; 8] {
+        let mut states = [[0; 8]; 8];
+        let mut blocks = [[0; 64]; 8];
+        let mut count_low = u32x8([0; 8]);
+        let mut count_high = u32x8([0; 8]);
+        let mut lastblock = u32x8([0; 8]);
+        let mut lastnode = u32x8([0; 8]);
+        for j in 0..8 {
+            states[j] = blake2s_input_state_words(i + j as u32);
+            blocks[j] = blake2s_input_msg_block(0x10 + i + j as u32);
+            count_low[j] = 0x20 + i + j as u32;
+            count_high[j] = 0x30 + i + j as u32;
+            lastblock[j] = 0x40 + i + j as u32;
+            lastnode[j] = 0x50 + i + j as u32;
+        }
+        let mut transposed = imp.blake2s_transpose8([
+            &states[0], &states[1], &states[2], &states[3], &states[4], &states[5], &states[6],
+            &states[7],
+        ]);
+        imp.blake2s_compress8(
+            &mut transposed,
+            [
+                &blocks[0], &blocks[1], &blocks[2], &blocks[3], &blocks[4], &blocks[5], &blocks[6],
+                &blocks[7],
+            ],
+            &count_low,
+            &count_high,
+            &lastblock,
+            &lastnode,
+        );
+        let [state0, state1, state2, state3, state4, state5, state6, state7] = &mut states;
+        imp.blake2s_untranspose8(
+            &transposed,
+            [
+                state0, state1, state2, state3, state4, state5, state6, state7,
+            ],
+        );
+        states
+    }
+
     // Make sure the different portable APIs all agree with each other. We
     // don't use known test vectors here; that happens in vector_tests.rs.
     #[test]
@@ -446,6 +898,22 @@ mod test {
         assert_eq!(expected1, four_at_a_time[1]);
         assert_eq!(expected2, four_at_a_time[2]);
         assert_eq!(expected3, four_at_a_time[3]);
+
+        // Check that compress8 gives the same answer.
+        let eight_at_a_time = exercise_8(portable, 0);
+        for (i, &state) in eight_at_a_time.iter().enumerate() {
+            assert_eq!(exercise_1(portable, i as u64), state);
+        }
+
+        // Check that compress_rounds with 12 rounds gives the same answer.
+        assert_eq!(expected0, exercise_rounds(portable, 0, 12));
+        assert_eq!(expected1, exercise_rounds(portable, 1, 12));
+
+        // Check that the BLAKE2s compress8 gives the same answer as one at a time.
+        let eight_at_a_time = blake2s_exercise_8(portable, 0);
+        for (i, &state) in eight_at_a_time.iter().enumerate() {
+            assert_eq!(blake2s_exercise_1(portable, i as u32), state);
+        }
     }
 
     // Make sure that SSE41 agrees with portable. We don't use known test
@@ -463,6 +931,22 @@ mod test {
         assert_eq!(exercise_1(portable, 0), exercise_1(sse41, 0));
         assert_eq!(exercise_2(portable, 0), exercise_2(sse41, 0));
         assert_eq!(exercise_4(portable, 0), exercise_4(sse41, 0));
+        assert_eq!(exercise_8(portable, 0), exercise_8(sse41, 0));
+        assert_eq!(exercise_blamka(portable, 0), exercise_blamka(sse41, 0));
+        for &rounds in &[0, 1, 10, 12, 23] {
+            assert_eq!(
+                exercise_rounds(portable, 0, rounds),
+                exercise_rounds(sse41, 0, rounds)
+            );
+        }
+        assert_eq!(
+            blake2s_exercise_1(portable, 0),
+            blake2s_exercise_1(sse41, 0)
+        );
+        assert_eq!(
+            blake2s_exercise_8(portable, 0),
+            blake2s_exercise_8(sse41, 0)
+        );
     }
 
     // Make sure that AVX2 agrees with portable. We don't use known test
@@ -480,5 +964,15 @@ mod test {
         assert_eq!(exercise_1(portable, 0), exercise_1(avx2, 0));
         assert_eq!(exercise_2(portable, 0), exercise_2(avx2, 0));
         assert_eq!(exercise_4(portable, 0), exercise_4(avx2, 0));
+        assert_eq!(exercise_8(portable, 0), exercise_8(avx2, 0));
+        assert_eq!(exercise_blamka(portable, 0), exercise_blamka(avx2, 0));
+        for &rounds in &[0, 1, 10, 12, 23] {
+            assert_eq!(
+                exercise_rounds(portable, 0, rounds),
+                exercise_rounds(avx2, 0, rounds)
+            );
+        }
+        assert_eq!(blake2s_exercise_1(portable, 0), blake2s_exercise_1(avx2, 0));
+        assert_eq!(blake2s_exercise_8(portable, 0), blake2s_exercise_8(avx2, 0));
     }
 }
@@ -0,0 +1,188 @@
+//! Keyed BLAKE2b as a `core::hash::Hasher`, for hash maps that take untrusted input.
+//!
+//! A hash map whose keys come from an attacker is open to "HashDoS" if the attacker can find many
+//! keys that land in the same bucket. Keying the hash function with a secret key prevents that.
+//! [`BuildBlake2bHasher`] implements `BuildHasher`, so it can be passed to
+//! `HashMap::with_hasher`, and it hands out [`Blake2bHasher`]s, which compute the 8-byte keyed
+//! BLAKE2b hash of everything written to them and return it as a little-endian `u64`.
+//!
+//! Keyed BLAKE2b normally hashes the key as a block of its own. The builder compresses that block
+//! once, when it's created, so each hasher starts from the keyed state, and hashing an input of up
+//! to 128 bytes, like an integer or a short string, takes a single compression. The key should be
+//! secret and random, and there should be a new one for each process or each map.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::hasher::BuildBlake2bHasher;
+//! use std::collections::HashMap;
+//!
+//! // In practice, use a random key.
+//! let mut map = HashMap::with_hasher(BuildBlake2bHasher::new(b"secret key"));
+//! map.insert("foo", 1);
+//! assert_eq!(Some(&1), map.get("foo"));
+//! ```
+//!
+//! [`BuildBlake2bHasher`]: struct.BuildBlake2bHasher.html
+//! [`Blake2bHasher`]: struct.Blake2bHasher.html
+
+use crate::Params;
+use crate::State;
+use crate::BLOCKBYTES;
+use core::fmt;
+use core::hash;
+
+const HASH_LENGTH: usize = 8;
+
+/// A `BuildHasher` for keyed BLAKE2b, which caches the keyed state.
+#[derive(Clone)]
+pub struct BuildBlake2bHasher {
+    state: State,
+    empty_hash: u64,
+}
+
+impl BuildBlake2bHasher {
+    /// Create a builder with the given key, which must be between 1 and `KEYBYTES` (64) bytes
+    /// long.
+    pub fn new(key: &[u8]) -> Self {
+        assert!(!key.is_empty(), "Bad key length: {}", key.len());
+        let mut state = Params::new().hash_length(HASH_LENGTH).key(key).to_state();
+        // With no input, the key block is the last block. Hash that case now, so that the key
+        // block can be compressed as a regular block below.
+        let empty_hash = finish_words(&state);
+        state
+            .implementation
+            .compress(&mut state.h, &state.buf, state.count, 0, 0);
+        state.buflen = 0;
+        Self { state, empty_hash }
+    }
+}
+
+impl hash::BuildHasher for BuildBlake2bHasher {
+    type Hasher = Blake2bHasher;
+
+    fn build_hasher(&self) -> Blake2bHasher {
+        Blake2bHasher {
+            state: self.state.clone(),
+            empty_hash: self.empty_hash,
+        }
+    }
+}
+
+impl fmt::Debug for BuildBlake2bHasher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: The State doesn't print its words, so it won't leak the key.
+        write!(f, "BuildBlake2bHasher {{ state: {:?} }}", self.state)
+    }
+}
+
+/// A `Hasher` for keyed BLAKE2b, created by [`BuildBlake2bHasher`].
+///
+/// [`BuildBlake2bHasher`]: struct.BuildBlake2bHasher.html
+#[derive(Clone)]
+pub struct Blake2bHasher {
+    state: State,
+    empty_hash: u64,
+}
+
+impl hash::Hasher for Blake2bHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.state.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        // The key block has already been compressed. If nothing's been written since, return the
+        // hash of the empty input, which the builder computed.
+        if self.state.count == BLOCKBYTES as u128 {
+            self.empty_hash
+        } else {
+            finish_words(&self.state)
+        }
+    }
+}
+
+impl fmt::Debug for Blake2bHasher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: The State doesn't print its words, so it won't leak the key.
+        write!(f, "Blake2bHasher {{ state: {:?} }}", self.state)
+    }
+}
+
+// This is the same as State::finalize, except that it doesn't need &mut, and it skips converting
+// the words to bytes. An 8-byte hash is the first state word, in little-endian order.
+fn finish_words(state: &State) -> u64 {
+    let mut block = [0; BLOCKBYTES];
+    block[..state.buflen as usize].copy_from_slice(&state.buf[..state.buflen as usize]);
+    let last_node = if state.last_node { !0 } else { 0 };
+    let mut words = state.h;
+    state
+        .implementation
+        .compress(&mut words, &block, state.count, !0, last_node);
+    words[0]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use byteorder::{ByteOrder, LittleEndian, NativeEndian};
+    use core::hash::{BuildHasher, Hasher};
+
+    fn expected(key: &[u8], input: &[u8]) -> u64 {
+        let hash = Params::new()
+            .hash_length(HASH_LENGTH)
+            .key(key)
+            .to_state()
+            .update(input)
+            .finalize();
+        LittleEndian::read_u64(hash.as_bytes())
+    }
+
+    #[test]
+    fn test_matches_keyed_blake2b() {
+        let mut input = [0; 1000];
+        for (i, byte) in input.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        for &key in &[&b"k"[..], b"secret key", &[0xff; 64]] {
+            let builder = BuildBlake2bHasher::new(key);
+            for &length in &[0, 1, 8, 127, 128, 129, 256, 257, 1000] {
+                let mut hasher = builder.build_hasher();
+                hasher.write(&input[..length]);
+                assert_eq!(expected(key, &input[..length]), hasher.finish());
+                // finish doesn't change the hasher.
+                assert_eq!(expected(key, &input[..length]), hasher.finish());
+
+                // Small writes give the same answer.
+                let mut hasher = builder.build_hasher();
+                for chunk in input[..length].chunks(3) {
+                    hasher.write(chunk);
+                }
+                assert_eq!(expected(key, &input[..length]), hasher.finish());
+            }
+        }
+    }
+
+    #[test]
+    fn test_integers() {
+        let builder = BuildBlake2bHasher::new(b"secret key");
+        let mut hasher = builder.build_hasher();
+        hasher.write_u64(42);
+        // Integers are hashed in native byte order.
+        let mut bytes = [0; 8];
+        NativeEndian::write_u64(&mut bytes, 42);
+        assert_eq!(expected(b"secret key", &bytes), hasher.finish());
+
+        let mut other = builder.build_hasher();
+        other.write_u64(43);
+        assert!(hasher.finish() != other.finish());
+        let mut other_key = BuildBlake2bHasher::new(b"other key").build_hasher();
+        other_key.write_u64(42);
+        assert!(hasher.finish() != other_key.finish());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_key_panics() {
+        BuildBlake2bHasher::new(b"");
+    }
+}
@@ -0,0 +1,247 @@
+//! HMAC-BLAKE2b and HKDF-BLAKE2b.
+//!
+//! BLAKE2b has its own keyed mode (see [`Params::key`] and the [`mac`] module), which is simpler
+//! and faster than HMAC, and new designs should prefer it. But some protocols are specified in
+//! terms of HMAC, like the BLAKE2b suite of the [Noise protocol framework], and they need the
+//! real thing. This module implements [HMAC] over BLAKE2b with its 128-byte block size and 64-byte
+//! output, [HKDF] on top of that, and the `HKDF` function from the Noise spec, as [`hkdf2`] and
+//! [`hkdf3`].
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::hmac;
+//!
+//! let tag = hmac::Hmac::new(b"key").update(b"foo").finalize();
+//! assert_eq!(hmac::hmac(b"key", b"foo"), tag);
+//!
+//! let mut okm = [0; 100];
+//! hmac::hkdf(b"salt", b"input key material", b"info", &mut okm);
+//! ```
+//!
+//! [`Params::key`]: ../struct.Params.html#method.key
+//! [`mac`]: ../mac/index.html
+//! [Noise protocol framework]: https://noiseprotocol.org/noise.html
+//! [HMAC]: https://tools.ietf.org/html/rfc2104
+//! [HKDF]: https://tools.ietf.org/html/rfc5869
+//! [`hkdf2`]: fn.hkdf2.html
+//! [`hkdf3`]: fn.hkdf3.html
+
+use crate::blake2b;
+use crate::Hash;
+use crate::State;
+use crate::BLOCKBYTES;
+use crate::OUTBYTES;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std;
+
+const IPAD: u8 = 0x36;
+const OPAD: u8 = 0x5c;
+
+/// The longest output that [`hkdf_expand`] can produce, 255 times the hash length.
+///
+/// [`hkdf_expand`]: fn.hkdf_expand.html
+pub const HKDF_MAX_OUTPUT: usize = 255 * OUTBYTES;
+
+/// An incremental HMAC-BLAKE2b computation.
+#[derive(Clone)]
+pub struct Hmac {
+    inner: State,
+    outer: State,
+}
+
+impl Hmac {
+    /// Create an HMAC with the given key. The key can be any length. Keys longer than
+    /// `BLOCKBYTES` (128) are hashed first, as the HMAC spec requires.
+    pub fn new(key: &[u8]) -> Self {
+        let hashed_key;
+        let key = if key.len() > BLOCKBYTES {
+            hashed_key = blake2b(key);
+            hashed_key.as_bytes()
+        } else {
+            key
+        };
+        let mut inner_pad = [IPAD; BLOCKBYTES];
+        let mut outer_pad = [OPAD; BLOCKBYTES];
+        for ((i, o), &k) in inner_pad.iter_mut().zip(outer_pad.iter_mut()).zip(key) {
+            *i ^= k;
+            *o ^= k;
+        }
+        let mut inner = State::new();
+        inner.update(&inner_pad);
+        let mut outer = State::new();
+        outer.update(&outer_pad);
+        Self { inner, outer }
+    }
+
+    /// Add input to the HMAC. You can call `update` any number of times.
+    pub fn update(&mut self, input: &[u8]) -> &mut Self {
+        self.inner.update(input);
+        self
+    }
+
+    /// Finalize the HMAC and return the 64-byte tag. You can keep adding input after calling
+    /// `finalize`.
+    pub fn finalize(&mut self) -> Hash {
+        let inner_hash = self.inner.finalize();
+        let mut outer = self.outer.clone();
+        outer.update(inner_hash.as_bytes());
+        outer.finalize()
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for Hmac {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Hmac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: The States don't print their words, so they won't leak the key.
+        write!(f, "Hmac {{ inner: {:?} }}", self.inner)
+    }
+}
+
+/// Compute the HMAC-BLAKE2b of `input` with the given key.
+pub fn hmac(key: &[u8], input: &[u8]) -> Hash {
+    Hmac::new(key).update(input).finalize()
+}
+
+/// The HKDF extract step, which returns the pseudorandom key for [`hkdf_expand`]. An empty salt
+/// is the same as the default salt of 64 null bytes.
+///
+/// [`hkdf_expand`]: fn.hkdf_expand.html
+pub fn hkdf_extract(salt: &[u8], input_key_material: &[u8]) -> Hash {
+    hmac(salt, input_key_material)
+}
+
+/// The HKDF expand step, which fills `output` with key material derived from the pseudorandom
+/// key `prk` and the context `info`. The output can be at most `HKDF_MAX_OUTPUT` (16320) bytes.
+pub fn hkdf_expand(prk: &[u8], info: &[u8], output: &mut [u8]) {
+    assert!(
+        output.len() <= HKDF_MAX_OUTPUT,
+        "Bad output length: {}",
+        output.len()
+    );
+    let hmac = Hmac::new(prk);
+    let mut previous: Option<Hash> = None;
+    for (i, chunk) in output.chunks_mut(OUTBYTES).enumerate() {
+        let mut block_hmac = hmac.clone();
+        if let Some(previous) = &previous {
+            block_hmac.update(previous.as_bytes());
+        }
+        block_hmac.update(info);
+        block_hmac.update(&[i as u8 + 1]);
+        let block = block_hmac.finalize();
+        chunk.copy_from_slice(&block.as_bytes()[..chunk.len()]);
+        previous = Some(block);
+    }
+}
+
+/// Extract and expand in one step. See [`hkdf_extract`] and [`hkdf_expand`].
+///
+/// [`hkdf_extract`]: fn.hkdf_extract.html
+/// [`hkdf_expand`]: fn.hkdf_expand.html
+pub fn hkdf(salt: &[u8], input_key_material: &[u8], info: &[u8], output: &mut [u8]) {
+    let prk = hkdf_extract(salt, input_key_material);
+    hkdf_expand(prk.as_bytes(), info, output);
+}
+
+// The HKDF function from the Noise spec. It's HKDF with the chaining key as the salt and an empty
+// info, where each output is a full hash.
+fn noise_hkdf(chaining_key: &[u8], input_key_material: &[u8], outputs: &mut [Hash]) {
+    let temp_key = hmac(chaining_key, input_key_material);
+    let hmac = Hmac::new(temp_key.as_bytes());
+    for i in 0..outputs.len() {
+        let mut output_hmac = hmac.clone();
+        if i > 0 {
+            output_hmac.update(outputs[i - 1].as_bytes());
+        }
+        output_hmac.update(&[i as u8 + 1]);
+        outputs[i] = output_hmac.finalize();
+    }
+}
+
+/// The Noise `HKDF` function with two outputs, as used by `MixKey` and `Split`.
+pub fn hkdf2(chaining_key: &[u8], input_key_material: &[u8]) -> [Hash; 2] {
+    let empty = blake2b(b"");
+    let mut outputs = [empty; 2];
+    noise_hkdf(chaining_key, input_key_material, &mut outputs);
+    outputs
+}
+
+/// The Noise `HKDF` function with three outputs, as used by `MixKeyAndHash`.
+pub fn hkdf3(chaining_key: &[u8], input_key_material: &[u8]) -> [Hash; 3] {
+    let empty = blake2b(b"");
+    let mut outputs = [empty; 3];
+    noise_hkdf(chaining_key, input_key_material, &mut outputs);
+    outputs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_incremental() {
+        let input = [0x42; 1000];
+        let expected = hmac(b"key", &input);
+        let mut state = Hmac::new(b"key");
+        for chunk in input.chunks(99) {
+            state.update(chunk);
+        }
+        assert_eq!(expected, state.finalize());
+        assert_eq!(expected, state.finalize());
+    }
+
+    #[test]
+    fn test_key_lengths() {
+        // A key shorter than a block is padded with zeros, so an empty key matches all zeros.
+        assert_eq!(hmac(&[], b"foo"), hmac(&[0; BLOCKBYTES], b"foo"));
+        // A key longer than a block is replaced by its hash.
+        let long_key = [0xaa; BLOCKBYTES + 1];
+        assert_eq!(
+            hmac(&long_key, b"foo"),
+            hmac(blake2b(&long_key).as_bytes(), b"foo")
+        );
+    }
+
+    #[test]
+    fn test_hkdf_lengths() {
+        let mut long = [0; HKDF_MAX_OUTPUT];
+        hkdf(b"salt", b"ikm", b"info", &mut long);
+        // Shorter outputs are prefixes of longer ones.
+        for &len in &[0, 1, 63, 64, 65, 1000] {
+            let mut output = [0; 1000];
+            hkdf(b"salt", b"ikm", b"info", &mut output[..len]);
+            assert_eq!(&long[..len], &output[..len]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_hkdf_too_long_panics() {
+        let mut output = [0; HKDF_MAX_OUTPUT + 1];
+        hkdf_expand(&[0; OUTBYTES], b"", &mut output);
+    }
+
+    #[test]
+    fn test_hkdf2_is_a_prefix_of_hkdf3() {
+        let two = hkdf2(b"chaining key", b"input key material");
+        let three = hkdf3(b"chaining key", b"input key material");
+        assert_eq!(two[..], three[..2]);
+        let mut okm = [0; 2 * OUTBYTES];
+        hkdf(b"chaining key", b"input key material", b"", &mut okm);
+        assert_eq!(two[0], okm[..OUTBYTES]);
+        assert_eq!(two[1], okm[OUTBYTES..]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Subkey derivation, compatible with libsodium's `crypto_kdf`.
+//!
+//! This derives any number of subkeys from a single 32-byte master key. Each subkey is identified
+//! by a 64-bit subkey ID and an 8-byte context, which describes what the subkeys are for, like
+//! `b"UserName"` or `b"__auth__"`. The master key is the BLAKE2b key, the subkey ID goes in the
+//! salt as 8 little-endian bytes, the context goes in the personalization, and the input is empty.
+//! That matches [`crypto_kdf_derive_from_key`] in libsodium, so the two derive the same subkeys.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::kdf;
+//!
+//! let master_key = [0x42; kdf::KEYBYTES];
+//! let mut subkey1 = [0; 32];
+//! let mut subkey2 = [0; 32];
+//! kdf::derive_from_key(&master_key, 1, b"Examples", &mut subkey1);
+//! kdf::derive_from_key(&master_key, 2, b"Examples", &mut subkey2);
+//! assert!(subkey1 != subkey2);
+//! ```
+//!
+//! [`crypto_kdf_derive_from_key`]: https://doc.libsodium.org/key_derivation
+
+use crate::Params;
+use byteorder::{ByteOrder, LittleEndian};
+
+/// The length of a master key.
+pub const KEYBYTES: usize = 32;
+
+/// The length of a context.
+pub const CONTEXTBYTES: usize = 8;
+
+/// The shortest subkey that can be derived.
+pub const BYTES_MIN: usize = 16;
+
+/// The longest subkey that can be derived.
+pub const BYTES_MAX: usize = 64;
+
+/// Derive the subkey with the given ID and context from a master key, and write it to `subkey`.
+/// The subkey length is `subkey.len()`, which must be between `BYTES_MIN` (16) and `BYTES_MAX`
+/// (64). Note that the length is associated data, so a 16-byte subkey is *not* a prefix of the
+/// 32-byte subkey with the same ID.
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::kdf;
+/// let mut subkey = [0; 16];
+/// kdf::derive_from_key(&[0; kdf::KEYBYTES], 0, b"Examples", &mut subkey);
+/// ```
+pub fn derive_from_key(
+    key: &[u8; KEYBYTES],
+    subkey_id: u64,
+    context: &[u8; CONTEXTBYTES],
+    subkey: &mut [u8],
+) {
+    assert!(
+        BYTES_MIN <= subkey.len() && subkey.len() <= BYTES_MAX,
+        "Bad subkey length: {}",
+        subkey.len()
+    );
+    let mut salt = [0; 8];
+    LittleEndian::write_u64(&mut salt, subkey_id);
+    let hash = Params::new()
+        .hash_length(subkey.len())
+        .key(key)
+        .salt(&salt)
+        .personal(context)
+        .to_state()
+        .finalize();
+    subkey.copy_from_slice(hash.as_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // These come from libsodium's crypto_kdf_blake2b_derive_from_key, with the master key
+    // 0x00..0x1f and the context "KDF test".
+    const VECTORS: &[(u64, &str)] = &[
+        (0, "e9136a52b9690eb4df4e9665e819a6d3"),
+        (1, "13fea52bb8cba063f3ed93de27ed07e06d8c6367474e6ae4c9282913ac3c3a03"),
+        (2, "1944da61ff18dc2028c3578ac85be904931b83860896598f62468f1cb5471c6a344c945dbc62c9aaf70feb62472d17775ea5db6ed5494c68b7a9a59761f39614"),
+        (0x0102030405060708, "a4b6f0b2cbc8a097f569706ed312459ea50e2f905ab95175714662c3c949de91"),
+        (u64::MAX, "6be4464350f6934d151c1bb8f555bc18e75028be95b892c6dca047101f2827a1950b2b0fb35e996a2782db9a760e76c8b8da52e362f741bf5bcfefff0fc943fc"),
+        (7, "088d41e8f5a7037163a26fac9e8e629e46"),
+    ];
+
+    #[test]
+    fn test_libsodium_vectors() {
+        let mut key = [0; KEYBYTES];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        for &(subkey_id, expected) in VECTORS {
+            let expected = hex::decode(expected).unwrap();
+            let mut subkey = [0; BYTES_MAX];
+            let subkey = &mut subkey[..expected.len()];
+            derive_from_key(&key, subkey_id, b"KDF test", subkey);
+            assert_eq!(&expected[..], &subkey[..]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_short_subkey_panics() {
+        derive_from_key(&[0; KEYBYTES], 0, b"Examples", &mut [0; BYTES_MIN - 1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_long_subkey_panics() {
+        derive_from_key(&[0; KEYBYTES], 0, b"Examples", &mut [0; BYTES_MAX + 1]);
+    }
+}
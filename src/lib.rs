@@ -20,7 +20,49 @@
 //!   but it's twice as fast as BLAKE2b, because it uses AVX2 more efficiently. It's available on
 //!   the command line as `b2sum --blake2bp`.
 //! - Support for computing multiple BLAKE2b hashes in parallel, matching the throughput of
-//!   BLAKE2bp. See [`update4`] and [`finalize4`].
+//!   BLAKE2bp. See [`update4`] and [`finalize4`], the eight-lane [`update8`] and [`finalize8`],
+//!   and the two-lane [`update2`] and [`finalize2`] for machines with SSE4.1 but not AVX2.
+//! - [`hash_many`], which hashes a batch of inputs of different lengths with all four AVX2 lanes
+//!   busy, by swapping the next input into a lane as soon as it's free.
+//! - Fast paths for inputs of one block or less, like fixed-size keys and hashes. See
+//!   [`blake2b_exact`] and [`hash4_exact`].
+//! - The variable-length hash `H'` from Argon2, for outputs of any length, as [`blake2b_long`].
+//! - The compression function `F` with any number of rounds, as in Ethereum's EIP-152 precompile,
+//!   as [`compress_rounds`].
+//! - Saving an in-progress BLAKE2b or BLAKE2bp state as bytes with [`State::save`], to finish the
+//!   hash later or in another process. States also implement the `serde` traits behind the optional
+//!   `serde` Cargo feature.
+//! - The raw 64-byte parameter block, with [`Params::to_param_block`] and `from_param_block`, to
+//!   compare configurations byte for byte with C implementations like libb2.
+//! - Parsing and encoding a [`Hash`] as hex, base64, base64url, or base32, and serializing it with
+//!   `serde`, as hex in human-readable formats and raw bytes in binary ones.
+//! - [Multihash and CID] encoding, for content addresses in IPFS-compatible stores.
+//! - [ASN.1 OIDs and DER `DigestInfo`] encoding, for RSA PKCS#1 v1.5 signatures.
+//! - [Subkey derivation] from a master key, compatible with libsodium's `crypto_kdf`.
+//! - A [deterministic random bit generator] running keyed BLAKE2b in counter mode, with forking and
+//!   reseeding. It implements the `rand_core` traits behind the optional `rand_core` Cargo feature.
+//! - [HMAC-BLAKE2b and HKDF-BLAKE2b], for protocols like Noise that are specified in terms of HMAC.
+//! - The [Argon2] password hash, with PHC string encoding, behind the optional `argon2` Cargo
+//!   feature.
+//! - A [`Mac`] type for keyed BLAKE2b, which requires a key and verifies tags in constant time,
+//!   including truncated tags.
+//! - A keyed [`Hasher` and `BuildHasher`] for `HashMap`s that take untrusted input, which hashes
+//!   short keys with a single compression.
+//! - Binary [Merkle trees] with inclusion proofs, using the BLAKE2 tree parameters to separate
+//!   leaves from parent nodes.
+//! - [Verified streaming] in the style of Bao, with a decoder that checks each chunk against the
+//!   root hash as it reads, and supports seeking and extracting slices.
+//! - [Equihash] proof-of-work verification, for Zcash-style solutions, using the four-lane API to
+//!   compute the personalized hashes.
+//! - Implementations of [BLAKE2s] and its parallel variant [BLAKE2sp], with SSE4.1 and AVX2
+//!   support. AVX2 computes all eight BLAKE2sp leaves at once.
+//! - The [BLAKE2Xb] extendable-output function, for outputs longer than 64 bytes, with a seekable
+//!   output reader.
+//! - General BLAKE2b [tree hashing], driven by the fanout, depth, and leaf length parameters.
+//! - Multithreaded BLAKE2bp hashing for large inputs with [`update_rayon`], behind the optional
+//!   `rayon` Cargo feature.
+//! - Implementations of the RustCrypto [`digest` and `crypto-mac` traits], behind the optional
+//!   `digest` Cargo feature.
 //!
 //! # Example
 //!
@@ -109,8 +151,37 @@
 //! [Samuel Neves' implementation]: https://github.com/sneves/blake2-avx2
 //! [included in libsodium]: https://github.com/jedisct1/libsodium/commit/0131a720826045e476e6dd6a8e7a1991f1d941aa
 //! [BLAKE2bp]: https://docs.rs/blake2b_simd/latest/blake2b_simd/blake2bp/index.html
+//! [BLAKE2s]: https://docs.rs/blake2b_simd/latest/blake2b_simd/blake2s/index.html
+//! [BLAKE2sp]: https://docs.rs/blake2b_simd/latest/blake2b_simd/blake2sp/index.html
+//! [BLAKE2Xb]: https://docs.rs/blake2b_simd/latest/blake2b_simd/blake2xb/index.html
+//! [tree hashing]: https://docs.rs/blake2b_simd/latest/blake2b_simd/tree/index.html
+//! [`update_rayon`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/blake2bp/struct.State.html#method.update_rayon
+//! [`digest` and `crypto-mac` traits]: https://docs.rs/blake2b_simd/latest/blake2b_simd/digest/index.html
 //! [`update4`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.update4.html
 //! [`finalize4`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.finalize4.html
+//! [`update8`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.update8.html
+//! [`finalize8`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.finalize8.html
+//! [`update2`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.update2.html
+//! [`finalize2`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.finalize2.html
+//! [`hash_many`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.hash_many.html
+//! [`blake2b_exact`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.blake2b_exact.html
+//! [`hash4_exact`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.hash4_exact.html
+//! [`blake2b_long`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.blake2b_long.html
+//! [`compress_rounds`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/fn.compress_rounds.html
+//! [`State::save`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/struct.State.html#method.save
+//! [`Params::to_param_block`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/struct.Params.html#method.to_param_block
+//! [`Hash`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/struct.Hash.html
+//! [Multihash and CID]: https://docs.rs/blake2b_simd/latest/blake2b_simd/cid/index.html
+//! [ASN.1 OIDs and DER `DigestInfo`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/asn1/index.html
+//! [Subkey derivation]: https://docs.rs/blake2b_simd/latest/blake2b_simd/kdf/index.html
+//! [deterministic random bit generator]: https://docs.rs/blake2b_simd/latest/blake2b_simd/drbg/index.html
+//! [HMAC-BLAKE2b and HKDF-BLAKE2b]: https://docs.rs/blake2b_simd/latest/blake2b_simd/hmac/index.html
+//! [Argon2]: https://docs.rs/blake2b_simd/latest/blake2b_simd/argon2/index.html
+//! [`Mac`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/mac/struct.Mac.html
+//! [`Hasher` and `BuildHasher`]: https://docs.rs/blake2b_simd/latest/blake2b_simd/hasher/index.html
+//! [Merkle trees]: https://docs.rs/blake2b_simd/latest/blake2b_simd/merkle/index.html
+//! [Verified streaming]: https://docs.rs/blake2b_simd/latest/blake2b_simd/verified/index.html
+//! [Equihash]: https://docs.rs/blake2b_simd/latest/blake2b_simd/equihash/index.html
 // Note that the links above wind up in README.md, so they need to be absolute.
 
 #![cfg_attr(not(feature = "std"), no_std)]
@@ -130,12 +201,37 @@ use core::fmt;
 
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod avx2;
+mod encoding;
 mod portable;
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod sse41;
 
+#[cfg(feature = "argon2")]
+pub mod argon2;
+pub mod asn1;
 pub mod blake2bp;
+pub mod blake2s;
+pub mod blake2sp;
+pub mod blake2xb;
+pub mod cid;
+#[cfg(feature = "digest")]
+pub mod digest;
+pub mod drbg;
+#[cfg(feature = "std")]
+pub mod equihash;
 pub mod guts;
+pub mod hasher;
+pub mod hmac;
+pub mod kdf;
+pub mod mac;
+#[cfg(feature = "std")]
+pub mod merkle;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "std")]
+pub mod tree;
+#[cfg(feature = "std")]
+pub mod verified;
 
 #[cfg(test)]
 mod test;
@@ -151,6 +247,8 @@ pub const PERSONALBYTES: usize = 16;
 /// The number input bytes passed to each call to the compression function. Small benchmarks need
 /// to use an even multiple of `BLOCKBYTES`, or else their apparent throughput will be low.
 pub const BLOCKBYTES: usize = 128;
+/// The length of a state saved with [`State::save`](struct.State.html#method.save).
+pub const SAVED_STATE_BYTES: usize = 2 + 64 + 16 + 3 + BLOCKBYTES;
 
 const IV: [u64; 8] = [
     0x6A09E667F3BCC908,
@@ -181,6 +279,8 @@ const SIGMA: [[u8; 16]; 12] = [
 type StateWords = [u64; 8];
 type Block = [u8; BLOCKBYTES];
 type HexString = arrayvec::ArrayString<[u8; 2 * OUTBYTES]>;
+// Base32 is the longest of the other encodings, at 103 characters for a 64-byte hash.
+type EncodedString = arrayvec::ArrayString<[u8; 2 * OUTBYTES]>;
 
 /// Compute the BLAKE2b hash of a slice of bytes, using default parameters.
 ///
@@ -197,6 +297,37 @@ pub fn blake2b(input: &[u8]) -> Hash {
     State::new().update(input).finalize()
 }
 
+/// Compute the BLAKE2b hash of an input of at most one block (`BLOCKBYTES`, 128 bytes), using the
+/// given parameters. This panics if the input is longer than that.
+///
+/// This is a fast path for short fixed-size inputs, like 32-byte keys or 64-byte hashes. It
+/// doesn't set up a `State` or copy the input into its buffer. Inputs of exactly `BLOCKBYTES` are
+/// compressed in place, and shorter ones are only copied once, to pad them with zeros. The result
+/// is the same as hashing the input with a `State` built from `params`. See also [`hash4_exact`],
+/// which hashes four such inputs at once.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::{blake2b, blake2b_exact, Params};
+///
+/// let input = [42; 64];
+/// assert_eq!(blake2b(&input), blake2b_exact(&Params::new(), &input));
+/// ```
+///
+/// [`hash4_exact`]: fn.hash4_exact.html
+pub fn blake2b_exact(params: &Params, input: &[u8]) -> Hash {
+    let implementation = guts::Implementation::detect();
+    let exact = ExactParams::new(params, implementation);
+    let mut padded = [0; BLOCKBYTES];
+    let (mut words, block, count) = exact.final_block(input, &mut padded);
+    implementation.compress(&mut words, block, count, !0, exact.lastnode);
+    Hash {
+        bytes: state_words_to_bytes(&words),
+        len: params.hash_length,
+    }
+}
+
 /// A parameter builder for `State` that exposes all the various BLAKE2 features.
 ///
 /// Apart from `hash_length`, which controls the length of the final `Hash`, all of these
@@ -235,23 +366,91 @@ impl Params {
     }
 
     fn to_state_words(&self) -> StateWords {
-        let (salt_left, salt_right) = array_refs!(&self.salt, 8, 8);
-        let (personal_left, personal_right) = array_refs!(&self.personal, 8, 8);
-        [
-            IV[0]
-                ^ self.hash_length as u64
-                ^ (self.key_length as u64) << 8
-                ^ (self.fanout as u64) << 16
-                ^ (self.max_depth as u64) << 24
-                ^ (self.max_leaf_length as u64) << 32,
-            IV[1] ^ self.node_offset,
-            IV[2] ^ self.node_depth as u64 ^ (self.inner_hash_length as u64) << 8,
-            IV[3],
-            IV[4] ^ LittleEndian::read_u64(salt_left),
-            IV[5] ^ LittleEndian::read_u64(salt_right),
-            IV[6] ^ LittleEndian::read_u64(personal_left),
-            IV[7] ^ LittleEndian::read_u64(personal_right),
-        ]
+        let mut words = [0; 8];
+        LittleEndian::read_u64_into(&self.to_param_block(), &mut words);
+        for (word, iv) in words.iter_mut().zip(IV.iter()) {
+            *word ^= iv;
+        }
+        words
+    }
+
+    /// Return the 64-byte parameter block, in the layout from section 2.8 of the BLAKE2 spec.
+    /// BLAKE2b XORs this block with the IV to get its initial state words. The layout matches the
+    /// `blake2b_param` struct in the reference implementation and libb2, so the two can be
+    /// compared byte for byte. Bytes 18 through 31 are reserved and always zero.
+    ///
+    /// The block includes the key length but not the key itself. It doesn't include the
+    /// [`last_node`] flag either, which only affects the finalization of the last block.
+    ///
+    /// [`last_node`]: #method.last_node
+    pub fn to_param_block(&self) -> [u8; 64] {
+        let mut block = [0; 64];
+        block[0] = self.hash_length;
+        block[1] = self.key_length;
+        block[2] = self.fanout;
+        block[3] = self.max_depth;
+        LittleEndian::write_u32(&mut block[4..8], self.max_leaf_length);
+        LittleEndian::write_u64(&mut block[8..16], self.node_offset);
+        block[16] = self.node_depth;
+        block[17] = self.inner_hash_length;
+        block[32..48].copy_from_slice(&self.salt);
+        block[48..64].copy_from_slice(&self.personal);
+        block
+    }
+
+    /// Parse a parameter block in the layout of [`to_param_block`]. Each parameter must be in the
+    /// range its setter accepts, and the reserved bytes must be zero. The [`last_node`] flag is
+    /// false.
+    ///
+    /// The key isn't part of the parameter block, so a block with a nonzero key length returns
+    /// `ParamBlockError::KeyLengthMismatch`. Use [`from_param_block_with_key`] for keyed blocks.
+    ///
+    /// [`to_param_block`]: #method.to_param_block
+    /// [`last_node`]: #method.last_node
+    /// [`from_param_block_with_key`]: #method.from_param_block_with_key
+    pub fn from_param_block(block: &[u8; 64]) -> Result<Self, ParamBlockError> {
+        Self::from_param_block_with_key(block, &[])
+    }
+
+    /// Like [`from_param_block`], but with the key passed separately, as it would be to [`key`].
+    /// Its length must match the key length in the block, or else this returns
+    /// `ParamBlockError::KeyLengthMismatch`.
+    ///
+    /// [`from_param_block`]: #method.from_param_block
+    /// [`key`]: #method.key
+    pub fn from_param_block_with_key(
+        block: &[u8; 64],
+        key: &[u8],
+    ) -> Result<Self, ParamBlockError> {
+        if block[18..32].iter().any(|&b| b != 0) {
+            return Err(ParamBlockError::NonzeroReserved);
+        }
+        if !(1..=OUTBYTES).contains(&(block[0] as usize))
+            || block[1] as usize > KEYBYTES
+            || block[3] == 0
+            || block[17] as usize > OUTBYTES
+        {
+            return Err(ParamBlockError::InvalidParameter);
+        }
+        if key.len() != block[1] as usize {
+            return Err(ParamBlockError::KeyLengthMismatch);
+        }
+        let mut params = Self {
+            hash_length: block[0],
+            key_length: 0,
+            key: [0; KEYBYTES],
+            salt: *array_ref!(block, 32, SALTBYTES),
+            personal: *array_ref!(block, 48, PERSONALBYTES),
+            fanout: block[2],
+            max_depth: block[3],
+            max_leaf_length: LittleEndian::read_u32(&block[4..8]),
+            node_offset: LittleEndian::read_u64(&block[8..16]),
+            node_depth: block[16],
+            inner_hash_length: block[17],
+            last_node: false,
+        };
+        params.key(key);
+        Ok(params)
     }
 
     /// Construct a `State` object based on these parameters.
@@ -403,6 +602,39 @@ impl fmt::Debug for Params {
     }
 }
 
+/// The error returned by [`Params::from_param_block`] and [`blake2bp::Params::from_param_block`],
+/// and their `_with_key` variants.
+///
+/// [`Params::from_param_block`]: struct.Params.html#method.from_param_block
+/// [`blake2bp::Params::from_param_block`]: blake2bp/struct.Params.html#method.from_param_block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamBlockError {
+    /// A parameter is outside the range its setter accepts, or for BLAKE2bp, the block isn't one
+    /// of the BLAKE2bp leaf or root blocks.
+    InvalidParameter,
+    /// One of the reserved bytes isn't zero.
+    NonzeroReserved,
+    /// The length of the key doesn't match the key length in the block.
+    KeyLengthMismatch,
+}
+
+impl fmt::Display for ParamBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamBlockError::InvalidParameter => write!(f, "invalid parameter in parameter block"),
+            ParamBlockError::NonzeroReserved => {
+                write!(f, "nonzero reserved bytes in parameter block")
+            }
+            ParamBlockError::KeyLengthMismatch => {
+                write!(f, "key doesn't match the key length in parameter block")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParamBlockError {}
+
 /// An incremental hasher for BLAKE2b.
 ///
 /// # Example
@@ -491,8 +723,8 @@ impl State {
         // Buffer any remaining input, to be either compressed or finalized in a subsequent call.
         // Note that this represents some copying overhead, which in theory we could avoid in
         // all-at-once setting. A function hardcoded for exactly BLOCKSIZE input bytes is about 10%
-        // faster than using this implementation for the same input. The hash4_exact interface
-        // benefits from that for single-block inputs.
+        // faster than using this implementation for the same input. The blake2b_exact and
+        // hash4_exact interfaces benefit from that for single-block inputs.
         self.fill_buf(&mut input);
         self
     }
@@ -530,8 +762,147 @@ impl State {
     pub fn count(&self) -> u128 {
         self.count
     }
+
+    /// Save the state, including any buffered input, as `SAVED_STATE_BYTES` (213) bytes. The
+    /// hash can be continued later, possibly in another process or on another machine, by
+    /// passing those bytes to [`restore`].
+    ///
+    /// **The saved bytes are as sensitive as the key.** They contain the state words, which the
+    /// `Debug` impl deliberately doesn't print, and anyone who has them can extend the hash with
+    /// more input. If the state is keyed and hasn't compressed any input yet, they also contain
+    /// the key itself.
+    ///
+    /// The format is little-endian and versioned, and it doesn't depend on which SIMD
+    /// implementation is in use. `restore` detects the implementation again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use blake2b_simd::{blake2b, State};
+    ///
+    /// let mut state = State::new();
+    /// state.update(b"foo");
+    /// let saved = state.save();
+    ///
+    /// let mut restored = State::restore(&saved).unwrap();
+    /// restored.update(b"bar");
+    /// assert_eq!(blake2b(b"foobar"), restored.finalize());
+    /// ```
+    ///
+    /// [`restore`]: #method.restore
+    pub fn save(&self) -> [u8; SAVED_STATE_BYTES] {
+        let mut saved = [0; SAVED_STATE_BYTES];
+        {
+            let (header, words, count, buflen, last_node, hash_length, buf) =
+                mut_array_refs!(&mut saved, 2, 64, 16, 1, 1, 1, BLOCKBYTES);
+            *header = [SAVED_STATE_VERSION, SAVED_STATE_BLAKE2B];
+            LittleEndian::write_u64_into(&self.h, words);
+            write_u128(count, self.count);
+            buflen[0] = self.buflen;
+            last_node[0] = self.last_node as u8;
+            hash_length[0] = self.hash_length;
+            // Leave the bytes past buflen zero. They might be left over from earlier input.
+            buf[..self.buflen as usize].copy_from_slice(&self.buf[..self.buflen as usize]);
+        }
+        saved
+    }
+
+    /// Restore a state saved with [`save`]. This returns an error if `saved` is the wrong length,
+    /// if it comes from an unknown version of the format, or if its fields are inconsistent with
+    /// each other, which means it wasn't produced by `save`. It doesn't authenticate the saved
+    /// bytes, though, so callers who store them somewhere untrusted need to add a MAC.
+    ///
+    /// [`save`]: #method.save
+    pub fn restore(saved: &[u8]) -> Result<Self, RestoreError> {
+        if saved.len() != SAVED_STATE_BYTES {
+            return Err(RestoreError::WrongLength);
+        }
+        let (header, words, count, buflen, last_node, hash_length, buf) = array_refs!(
+            array_ref!(saved, 0, SAVED_STATE_BYTES),
+            2,
+            64,
+            16,
+            1,
+            1,
+            1,
+            BLOCKBYTES
+        );
+        if *header != [SAVED_STATE_VERSION, SAVED_STATE_BLAKE2B] {
+            return Err(RestoreError::UnknownFormat);
+        }
+        let count = read_u128(count);
+        let buflen = buflen[0];
+        let hash_length = hash_length[0];
+        // The buffer only gets compressed when it's full, and it's only empty before any input.
+        let compressed = count.wrapping_sub(buflen as u128);
+        if buflen as usize > BLOCKBYTES
+            || count < buflen as u128
+            || compressed / BLOCKBYTES as u128 * BLOCKBYTES as u128 != compressed
+            || (buflen == 0) != (count == 0)
+            || last_node[0] > 1
+            || hash_length == 0
+            || hash_length as usize > OUTBYTES
+        {
+            return Err(RestoreError::InvalidState);
+        }
+        let mut state = Self {
+            h: [0; 8],
+            buf: [0; BLOCKBYTES],
+            buflen,
+            count,
+            last_node: last_node[0] == 1,
+            hash_length,
+            implementation: guts::Implementation::detect(),
+        };
+        LittleEndian::read_u64_into(words, &mut state.h);
+        state.buf[..buflen as usize].copy_from_slice(&buf[..buflen as usize]);
+        Ok(state)
+    }
 }
 
+// Saved states start with the format version and a byte for the algorithm, so that a saved
+// BLAKE2bp state can't be restored as BLAKE2b or vice versa.
+pub(crate) const SAVED_STATE_VERSION: u8 = 1;
+const SAVED_STATE_BLAKE2B: u8 = b'b';
+pub(crate) const SAVED_STATE_BLAKE2BP: u8 = b'p';
+
+pub(crate) fn write_u128(bytes: &mut [u8; 16], x: u128) {
+    LittleEndian::write_u64(&mut bytes[..8], x as u64);
+    LittleEndian::write_u64(&mut bytes[8..], (x >> 64) as u64);
+}
+
+pub(crate) fn read_u128(bytes: &[u8; 16]) -> u128 {
+    LittleEndian::read_u64(&bytes[..8]) as u128
+        | (LittleEndian::read_u64(&bytes[8..]) as u128) << 64
+}
+
+/// The error returned by [`State::restore`] and [`blake2bp::State::restore`].
+///
+/// [`State::restore`]: struct.State.html#method.restore
+/// [`blake2bp::State::restore`]: blake2bp/struct.State.html#method.restore
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestoreError {
+    /// The saved state isn't the right length.
+    WrongLength,
+    /// The saved state is from an unknown version of the format, or from a different algorithm.
+    UnknownFormat,
+    /// The fields of the saved state are inconsistent with each other.
+    InvalidState,
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RestoreError::WrongLength => write!(f, "wrong saved state length"),
+            RestoreError::UnknownFormat => write!(f, "unknown saved state format"),
+            RestoreError::InvalidState => write!(f, "invalid saved state"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RestoreError {}
+
 fn state_words_to_bytes(state_words: &StateWords) -> [u8; OUTBYTES] {
     let mut bytes = [0; OUTBYTES];
     {
@@ -596,6 +967,95 @@ impl Hash {
     pub fn to_hex(&self) -> HexString {
         bytes_to_hex(self.as_bytes())
     }
+
+    /// Create a `Hash` from bytes, for example a hash that was stored in a database. The length
+    /// can be anything from 1 to `OUTBYTES` (64).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseHashError> {
+        if bytes.is_empty() || bytes.len() > OUTBYTES {
+            return Err(ParseHashError::InvalidLength);
+        }
+        let mut hash = Self {
+            bytes: [0; OUTBYTES],
+            len: bytes.len() as u8,
+        };
+        hash.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(hash)
+    }
+
+    /// Parse a hash from hexadecimal, as produced by [`to_hex`]. Uppercase and lowercase digits
+    /// are both accepted. This is also what the `FromStr` impl does.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use blake2b_simd::{blake2b, Hash};
+    ///
+    /// let hash = blake2b(b"foo");
+    /// assert_eq!(hash, Hash::from_hex(&hash.to_hex()).unwrap());
+    /// assert_eq!(hash, hash.to_string().parse::<Hash>().unwrap());
+    /// ```
+    ///
+    /// [`to_hex`]: #method.to_hex
+    pub fn from_hex(hex: &str) -> Result<Self, ParseHashError> {
+        let hex = hex.as_bytes();
+        if hex.is_empty() || hex.len() > 2 * OUTBYTES || hex.len() / 2 * 2 != hex.len() {
+            return Err(ParseHashError::InvalidLength);
+        }
+        let mut bytes = [0; OUTBYTES];
+        for (byte, pair) in bytes.iter_mut().zip(hex.chunks(2)) {
+            *byte = (hex_digit(pair[0])? << 4) | hex_digit(pair[1])?;
+        }
+        Self::from_bytes(&bytes[..hex.len() / 2])
+    }
+
+    /// Convert the hash to standard base64, with padding, as in RFC 4648.
+    pub fn to_base64(&self) -> EncodedString {
+        encoding::BASE64.encode(self.as_bytes())
+    }
+
+    /// Parse a hash from standard base64, as produced by [`to_base64`]. The padding is required.
+    ///
+    /// [`to_base64`]: #method.to_base64
+    pub fn from_base64(base64: &str) -> Result<Self, ParseHashError> {
+        encoding::BASE64.decode_hash(base64)
+    }
+
+    /// Convert the hash to the URL-safe base64 variant from RFC 4648, without padding, for use in
+    /// URLs and filenames.
+    pub fn to_base64url(&self) -> EncodedString {
+        encoding::BASE64URL.encode(self.as_bytes())
+    }
+
+    /// Parse a hash from URL-safe base64, as produced by [`to_base64url`]. Padding isn't
+    /// accepted.
+    ///
+    /// [`to_base64url`]: #method.to_base64url
+    pub fn from_base64url(base64url: &str) -> Result<Self, ParseHashError> {
+        encoding::BASE64URL.decode_hash(base64url)
+    }
+
+    /// Convert the hash to base32 with the RFC 4648 alphabet, in lowercase and without padding,
+    /// which is the form that multibase and CIDs use.
+    pub fn to_base32(&self) -> EncodedString {
+        encoding::BASE32.encode(self.as_bytes())
+    }
+
+    /// Parse a hash from base32, as produced by [`to_base32`]. Uppercase and lowercase are both
+    /// accepted, but padding isn't.
+    ///
+    /// [`to_base32`]: #method.to_base32
+    pub fn from_base32(base32: &str) -> Result<Self, ParseHashError> {
+        encoding::BASE32.decode_hash(base32)
+    }
+}
+
+fn hex_digit(c: u8) -> Result<u8, ParseHashError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(ParseHashError::InvalidEncoding),
+    }
 }
 
 fn bytes_to_hex(bytes: &[u8]) -> HexString {
@@ -636,6 +1096,50 @@ impl fmt::Debug for Hash {
     }
 }
 
+/// Formats the hash as lowercase hexadecimal, the same as [`to_hex`].
+///
+/// [`to_hex`]: struct.Hash.html#method.to_hex
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+/// Parses hexadecimal, the same as [`Hash::from_hex`].
+///
+/// [`Hash::from_hex`]: struct.Hash.html#method.from_hex
+impl core::str::FromStr for Hash {
+    type Err = ParseHashError;
+
+    fn from_str(s: &str) -> Result<Self, ParseHashError> {
+        Self::from_hex(s)
+    }
+}
+
+/// The error returned when parsing a [`Hash`] fails.
+///
+/// [`Hash`]: struct.Hash.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseHashError {
+    /// The hash would be empty or longer than `OUTBYTES` (64) bytes.
+    InvalidLength,
+    /// The input contains a character outside the encoding's alphabet, or it isn't the exact
+    /// encoding of any hash, for example because of missing padding.
+    InvalidEncoding,
+}
+
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseHashError::InvalidLength => write!(f, "invalid hash length"),
+            ParseHashError::InvalidEncoding => write!(f, "invalid hash encoding"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseHashError {}
+
 /// Update four `State` objects at the same time.
 ///
 /// This implementation isn't multithreaded. Rather, it uses AVX2 (if available) to hash the four
@@ -654,9 +1158,8 @@ impl fmt::Debug for Hash {
 /// the best throughput, use inputs that are roughly the same length.
 ///
 /// Unlike BLAKE2bp, which is specifically designed to have four lanes, parallel BLAKE2b isn't tied
-/// to any particular number of lanes. When the AVX-512 instruction set becomes more widespread,
-/// for example, we could add an `update8` implementation to take full advantage of it. We could
-/// also add an SSE-based `update2` implementation to support older machines.
+/// to any particular number of lanes. See [`update8`] for an eight-lane version, and [`update2`]
+/// for a two-lane version that's useful on older machines with SSE4.1 but not AVX2.
 ///
 /// # Example
 ///
@@ -691,6 +1194,8 @@ impl fmt::Debug for Hash {
 /// ```
 ///
 /// [`update`]: struct.State.html#method.update
+/// [`update8`]: fn.update8.html
+/// [`update2`]: fn.update2.html
 pub fn update4(
     state0: &mut State,
     state1: &mut State,
@@ -913,6 +1418,857 @@ pub fn finalize4(
     ]
 }
 
+/// Update two `State` objects at the same time.
+///
+/// This is the two-lane version of [`update4`], with the same semantics. It uses SSE4.1 (if
+/// available) to hash the two inputs in parallel on a single thread. On machines with AVX2,
+/// [`update4`] is faster, but on machines with only SSE4.1 this is faster than hashing the inputs
+/// one at a time.
+///
+/// Like `update4`, this can only operate in parallel as long as both inputs still have bytes left.
+/// Once one of the inputs is exhausted, it falls back to regular serial hashing for the rest.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::{blake2b, finalize2, update2, State};
+///
+/// let mut state0 = State::new();
+/// let mut state1 = State::new();
+///
+/// update2(&mut state0, &mut state1, b"foo", b"bar");
+///
+/// let parallel_hashes = finalize2(&mut state0, &mut state1);
+///
+/// let serial_hashes = [blake2b(b"foo"), blake2b(b"bar")];
+/// assert_eq!(serial_hashes, parallel_hashes);
+/// ```
+///
+/// [`update4`]: fn.update4.html
+pub fn update2(state0: &mut State, state1: &mut State, mut input0: &[u8], mut input1: &[u8]) {
+    let implementation = guts::Implementation::detect();
+
+    // First we need to make sure all the buffers are clear.
+    state0.compress_buffer_if_possible(&mut input0);
+    state1.compress_buffer_if_possible(&mut input1);
+
+    // Compute the number of full chunks we can compress in parallel. As in update4, we can only
+    // compress chunks here if we're certain there's more input coming.
+    let min_len = cmp::min(input0.len(), input1.len());
+    let full_chunks = min_len.saturating_sub(1) / BLOCKBYTES;
+
+    if full_chunks > 0 {
+        // Transpose the two separate state words arrays into vectorized form.
+        let mut transposed_state = implementation.transpose2(&state0.h, &state1.h);
+
+        for _ in 0..full_chunks {
+            state0.count += BLOCKBYTES as u128;
+            state1.count += BLOCKBYTES as u128;
+            let count_low = guts::u64x2([state0.count as u64, state1.count as u64]);
+            let count_high =
+                guts::u64x2([(state0.count >> 64) as u64, (state1.count >> 64) as u64]);
+            implementation.compress2(
+                &mut transposed_state,
+                array_ref!(input0, 0, BLOCKBYTES),
+                array_ref!(input1, 0, BLOCKBYTES),
+                &count_low,
+                &count_high,
+                &guts::u64x2([0, 0]),
+                &guts::u64x2([0, 0]),
+            );
+            input0 = &input0[BLOCKBYTES..];
+            input1 = &input1[BLOCKBYTES..];
+        }
+
+        // With the main loop finished, untranspose the states back out.
+        implementation.untranspose2(&transposed_state, &mut state0.h, &mut state1.h);
+    }
+
+    // Finally, buffer/hash any remaining input the usual one-at-a-time way.
+    state0.update(input0);
+    state1.update(input1);
+}
+
+/// Finalize two `State` objects at the same time.
+///
+/// This is the counterpart to [`update2`]. Like the regular [`finalize`], this is idempotent.
+/// Calling it multiple times on the same states will produce the same output, and it's possible to
+/// add more input in between calls. See [`update2`] for an example.
+///
+/// [`update2`]: fn.update2.html
+/// [`finalize`]: struct.State.html#method.finalize
+pub fn finalize2(state0: &mut State, state1: &mut State) -> [Hash; 2] {
+    let implementation = guts::Implementation::detect();
+
+    // Zero out the buffer tails, which might contain bytes from previous blocks.
+    for i in state0.buflen as usize..BLOCKBYTES {
+        state0.buf[i] = 0;
+    }
+    for i in state1.buflen as usize..BLOCKBYTES {
+        state1.buf[i] = 0;
+    }
+
+    // Transpose the state words into vectorized form. We won't write these
+    // words back into the State object, which makes finalize idempotent.
+    let mut transposed_state = implementation.transpose2(&state0.h, &state1.h);
+
+    // Do the final parallel compression step.
+    let count_low = guts::u64x2([state0.count as u64, state1.count as u64]);
+    let count_high = guts::u64x2([(state0.count >> 64) as u64, (state1.count >> 64) as u64]);
+    let lastnode = guts::u64x2([
+        if state0.last_node { !0 } else { 0 },
+        if state1.last_node { !0 } else { 0 },
+    ]);
+    implementation.compress2(
+        &mut transposed_state,
+        &state0.buf,
+        &state1.buf,
+        &count_low,
+        &count_high,
+        &guts::u64x2([!0, !0]),
+        &lastnode,
+    );
+
+    // Extract the resulting hashes.
+    let mut bytes0 = [0; 8];
+    let mut bytes1 = [0; 8];
+    implementation.untranspose2(&transposed_state, &mut bytes0, &mut bytes1);
+    [
+        Hash {
+            bytes: state_words_to_bytes(&bytes0),
+            len: state0.hash_length,
+        },
+        Hash {
+            bytes: state_words_to_bytes(&bytes1),
+            len: state1.hash_length,
+        },
+    ]
+}
+
+/// Update eight `State` objects at the same time.
+///
+/// This is the eight-lane version of [`update4`], with the same caveats: it's parallel on a single
+/// thread, and it falls back to regular serial hashing for the rest of the input as soon as any
+/// one of the inputs is exhausted. Currently with AVX2, this runs two four-lane compressions side
+/// by side, which is a bit faster than `update4`. CPUs with AVX-512 could do all eight lanes at
+/// once, and this interface is meant to let a future implementation take advantage of that.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::{blake2b, finalize8, update8, State};
+///
+/// let mut states = [
+///     State::new(),
+///     State::new(),
+///     State::new(),
+///     State::new(),
+///     State::new(),
+///     State::new(),
+///     State::new(),
+///     State::new(),
+/// ];
+/// let inputs: [&[u8]; 8] = [b"a", b"b", b"c", b"d", b"e", b"f", b"g", b"h"];
+///
+/// {
+///     let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+///     update8([s0, s1, s2, s3, s4, s5, s6, s7], inputs);
+/// }
+/// let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+/// let parallel_hashes = finalize8([s0, s1, s2, s3, s4, s5, s6, s7]);
+///
+/// for (input, hash) in inputs.iter().zip(parallel_hashes.iter()) {
+///     assert_eq!(&blake2b(input), hash);
+/// }
+/// ```
+///
+/// [`update4`]: fn.update4.html
+pub fn update8(mut states: [&mut State; 8], mut inputs: [&[u8]; 8]) {
+    let implementation = guts::Implementation::detect();
+
+    // First we need to make sure all the buffers are clear.
+    for (state, input) in states.iter_mut().zip(inputs.iter_mut()) {
+        state.compress_buffer_if_possible(input);
+    }
+
+    // As in update4, we can only compress chunks here if we're certain there's more input coming.
+    let min_len = inputs.iter().map(|input| input.len()).min().unwrap();
+    let full_chunks = min_len.saturating_sub(1) / BLOCKBYTES;
+
+    if full_chunks > 0 {
+        let mut transposed_state = implementation.transpose8([
+            &states[0].h,
+            &states[1].h,
+            &states[2].h,
+            &states[3].h,
+            &states[4].h,
+            &states[5].h,
+            &states[6].h,
+            &states[7].h,
+        ]);
+
+        for _ in 0..full_chunks {
+            let mut count_low = guts::u64x8([0; 8]);
+            let mut count_high = guts::u64x8([0; 8]);
+            for (i, state) in states.iter_mut().enumerate() {
+                state.count += BLOCKBYTES as u128;
+                count_low[i] = state.count as u64;
+                count_high[i] = (state.count >> 64) as u64;
+            }
+            implementation.compress8(
+                &mut transposed_state,
+                [
+                    array_ref!(inputs[0], 0, BLOCKBYTES),
+                    array_ref!(inputs[1], 0, BLOCKBYTES),
+                    array_ref!(inputs[2], 0, BLOCKBYTES),
+                    array_ref!(inputs[3], 0, BLOCKBYTES),
+                    array_ref!(inputs[4], 0, BLOCKBYTES),
+                    array_ref!(inputs[5], 0, BLOCKBYTES),
+                    array_ref!(inputs[6], 0, BLOCKBYTES),
+                    array_ref!(inputs[7], 0, BLOCKBYTES),
+                ],
+                &count_low,
+                &count_high,
+                &guts::u64x8([0; 8]),
+                &guts::u64x8([0; 8]),
+            );
+            for input in inputs.iter_mut() {
+                *input = &input[BLOCKBYTES..];
+            }
+        }
+
+        let [state0, state1, state2, state3, state4, state5, state6, state7] = &mut states;
+        implementation.untranspose8(
+            &transposed_state,
+            [
+                &mut state0.h,
+                &mut state1.h,
+                &mut state2.h,
+                &mut state3.h,
+                &mut state4.h,
+                &mut state5.h,
+                &mut state6.h,
+                &mut state7.h,
+            ],
+        );
+    }
+
+    // Finally, buffer/hash any remaining input the usual one-at-a-time way.
+    for (state, input) in states.iter_mut().zip(inputs.iter()) {
+        state.update(input);
+    }
+}
+
+/// Finalize eight `State` objects at the same time.
+///
+/// This is the counterpart to [`update8`]. Like the regular [`finalize`], this is idempotent.
+/// Calling it multiple times on the same states will produce the same output, and it's possible to
+/// add more input in between calls. See [`update8`] for an example.
+///
+/// [`update8`]: fn.update8.html
+/// [`finalize`]: struct.State.html#method.finalize
+pub fn finalize8(mut states: [&mut State; 8]) -> [Hash; 8] {
+    let implementation = guts::Implementation::detect();
+
+    let mut count_low = guts::u64x8([0; 8]);
+    let mut count_high = guts::u64x8([0; 8]);
+    let mut lastnode = guts::u64x8([0; 8]);
+    for (i, state) in states.iter_mut().enumerate() {
+        // Zero out the buffer tails, which might contain bytes from previous blocks.
+        for byte in &mut state.buf[state.buflen as usize..] {
+            *byte = 0;
+        }
+        count_low[i] = state.count as u64;
+        count_high[i] = (state.count >> 64) as u64;
+        lastnode[i] = if state.last_node { !0 } else { 0 };
+    }
+
+    // As in finalize4, we don't write these words back into the State objects, which makes
+    // finalize idempotent.
+    let mut transposed_state = implementation.transpose8([
+        &states[0].h,
+        &states[1].h,
+        &states[2].h,
+        &states[3].h,
+        &states[4].h,
+        &states[5].h,
+        &states[6].h,
+        &states[7].h,
+    ]);
+    implementation.compress8(
+        &mut transposed_state,
+        [
+            &states[0].buf,
+            &states[1].buf,
+            &states[2].buf,
+            &states[3].buf,
+            &states[4].buf,
+            &states[5].buf,
+            &states[6].buf,
+            &states[7].buf,
+        ],
+        &count_low,
+        &count_high,
+        &guts::u64x8([!0; 8]),
+        &lastnode,
+    );
+
+    // Extract the resulting hashes.
+    let mut words = [[0; 8]; 8];
+    {
+        let [words0, words1, words2, words3, words4, words5, words6, words7] = &mut words;
+        implementation.untranspose8(
+            &transposed_state,
+            [
+                words0, words1, words2, words3, words4, words5, words6, words7,
+            ],
+        );
+    }
+    let mut hashes = [Hash {
+        bytes: [0; OUTBYTES],
+        len: 0,
+    }; 8];
+    for ((hash, state_words), state) in hashes.iter_mut().zip(words.iter()).zip(states.iter()) {
+        *hash = Hash {
+            bytes: state_words_to_bytes(state_words),
+            len: state.hash_length,
+        };
+    }
+    hashes
+}
+
+/// Hash many inputs with the same parameters, returning a `Vec` of hashes in the same order.
+///
+/// This is a convenience wrapper around [`hash_many_into`], which works without the standard
+/// library. See that function for details.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::{blake2b, hash_many, Params};
+///
+/// let inputs: &[&[u8]] = &[b"foo", b"bar", b"baz", b"bing", b"bang"];
+/// let hashes = hash_many(&Params::new(), inputs);
+/// for (input, hash) in inputs.iter().zip(hashes.iter()) {
+///     assert_eq!(&blake2b(input), hash);
+/// }
+/// ```
+///
+/// [`hash_many_into`]: fn.hash_many_into.html
+#[cfg(feature = "std")]
+pub fn hash_many(params: &Params, inputs: &[&[u8]]) -> Vec<Hash> {
+    let empty_hash = Hash {
+        bytes: [0; OUTBYTES],
+        len: 0,
+    };
+    let mut outputs = vec![empty_hash; inputs.len()];
+    hash_many_into(params, inputs, &mut outputs);
+    outputs
+}
+
+/// Hash many inputs with the same parameters, writing the hashes to `outputs` in the same order.
+/// This panics if `inputs` and `outputs` have different lengths.
+///
+/// Like [`update4`], this hashes four inputs at a time on a single thread, using AVX2 if it's
+/// available. But unlike `update4`, it doesn't fall back to serial hashing when one input runs
+/// out. Instead, as soon as any input is finished, the next input takes over its lane. That keeps
+/// all four lanes busy until the very end, even when the inputs have very different lengths. Only
+/// the last few inputs, once there are no more to fill the free lanes, are finished serially.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::{hash_many_into, Params};
+///
+/// let inputs: [&[u8]; 3] = [b"foo", b"bar", b"baz"];
+/// let mut params = Params::new();
+/// params.hash_length(16);
+/// let mut outputs = [params.to_state().finalize(); 3];
+/// hash_many_into(&params, &inputs, &mut outputs);
+/// for (input, hash) in inputs.iter().zip(outputs.iter()) {
+///     assert_eq!(&params.to_state().update(input).finalize(), hash);
+/// }
+/// ```
+///
+/// [`update4`]: fn.update4.html
+pub fn hash_many_into(params: &Params, inputs: &[&[u8]], outputs: &mut [Hash]) {
+    assert_eq!(
+        inputs.len(),
+        outputs.len(),
+        "Mismatched inputs and outputs lengths"
+    );
+    let implementation = guts::Implementation::detect();
+    let initial_words = params.to_state_words();
+    let mut key_block = [0; BLOCKBYTES];
+    key_block[..KEYBYTES].copy_from_slice(&params.key);
+    let keyed = params.key_length > 0;
+    let lastnode = if params.last_node { !0 } else { 0 };
+    let output_hash = |words: &StateWords| Hash {
+        bytes: state_words_to_bytes(words),
+        len: params.hash_length,
+    };
+
+    let mut jobs = inputs.iter().enumerate();
+    let mut lanes: [Option<ManyJob>; 4] = [None, None, None, None];
+    let mut transposed_state = [guts::u64x4([0; 4]); 8];
+    loop {
+        // Swap a new job into any lane that's free, by overwriting that lane of the transposed
+        // state with fresh state words. If we run out of jobs, stop running four at a time.
+        for (lane_index, lane) in lanes.iter_mut().enumerate() {
+            if lane.is_none() {
+                if let Some((index, &input)) = jobs.next() {
+                    *lane = Some(ManyJob {
+                        index,
+                        input,
+                        count: 0,
+                        key_pending: keyed,
+                    });
+                    for (vec, &word) in transposed_state.iter_mut().zip(initial_words.iter()) {
+                        vec[lane_index] = word;
+                    }
+                }
+            }
+        }
+        if lanes.iter().any(Option::is_none) {
+            break;
+        }
+
+        // Compress one block in each lane. Some lanes might be compressing their last block.
+        let mut scratch_blocks = [[0; BLOCKBYTES]; 4];
+        let mut msgs = [&key_block; 4];
+        let mut count_low = guts::u64x4([0; 4]);
+        let mut count_high = guts::u64x4([0; 4]);
+        let mut lastblock = guts::u64x4([0; 4]);
+        let mut lastnode_vec = guts::u64x4([0; 4]);
+        for (lane_index, (lane, scratch)) in lanes.iter_mut().zip(&mut scratch_blocks).enumerate() {
+            let job = lane.as_mut().unwrap();
+            let (block, is_last) = job.next_block(&key_block, scratch);
+            msgs[lane_index] = block;
+            count_low[lane_index] = job.count as u64;
+            count_high[lane_index] = (job.count >> 64) as u64;
+            if is_last {
+                lastblock[lane_index] = !0;
+                lastnode_vec[lane_index] = lastnode;
+            }
+        }
+        implementation.compress4(
+            &mut transposed_state,
+            msgs[0],
+            msgs[1],
+            msgs[2],
+            msgs[3],
+            &count_low,
+            &count_high,
+            &lastblock,
+            &lastnode_vec,
+        );
+
+        // Transpose the finished lanes back out and free them up.
+        for (lane_index, lane) in lanes.iter_mut().enumerate() {
+            if lastblock[lane_index] != 0 {
+                let mut words = [0; 8];
+                for (word, vec) in words.iter_mut().zip(transposed_state.iter()) {
+                    *word = vec[lane_index];
+                }
+                outputs[lane.as_ref().unwrap().index] = output_hash(&words);
+                *lane = None;
+            }
+        }
+    }
+
+    // There are no jobs left to fill the free lanes. Finish whatever's left one at a time.
+    for (lane_index, lane) in lanes.iter_mut().enumerate() {
+        if let Some(job) = lane {
+            let mut words = [0; 8];
+            for (word, vec) in words.iter_mut().zip(transposed_state.iter()) {
+                *word = vec[lane_index];
+            }
+            loop {
+                let mut scratch = [0; BLOCKBYTES];
+                let (block, is_last) = job.next_block(&key_block, &mut scratch);
+                if is_last {
+                    implementation.compress(&mut words, block, job.count, !0, lastnode);
+                    break;
+                }
+                implementation.compress(&mut words, block, job.count, 0, 0);
+            }
+            outputs[job.index] = output_hash(&words);
+        }
+    }
+}
+
+// One input in progress in hash_many_into.
+struct ManyJob<'a> {
+    index: usize,
+    input: &'a [u8],
+    count: u128,
+    key_pending: bool,
+}
+
+impl<'a> ManyJob<'a> {
+    // Return the next block to compress and whether it's the last one, and update the count.
+    // Blocks in the middle of the input are used in place. The key block and the final block,
+    // which needs to be padded with zeros, come from the caller, and `scratch` must be zeroed.
+    fn next_block<'b>(&mut self, key_block: &'b Block, scratch: &'b mut Block) -> (&'b Block, bool)
+    where
+        'a: 'b,
+    {
+        if self.key_pending {
+            self.key_pending = false;
+            self.count += BLOCKBYTES as u128;
+            return (key_block, self.input.is_empty());
+        }
+        if self.input.len() > BLOCKBYTES {
+            let block = array_ref!(self.input, 0, BLOCKBYTES);
+            self.input = &self.input[BLOCKBYTES..];
+            self.count += BLOCKBYTES as u128;
+            (block, false)
+        } else {
+            scratch[..self.input.len()].copy_from_slice(self.input);
+            self.count += self.input.len() as u128;
+            self.input = &[];
+            (scratch, true)
+        }
+    }
+}
+
+/// Compute the BLAKE2b hashes of four inputs at once, each of at most one block (`BLOCKBYTES`,
+/// 128 bytes), using the same parameters. This panics if any input is longer than that.
+///
+/// This is the four-lane version of [`blake2b_exact`], using the same machinery as [`update4`].
+/// The inputs don't need to be the same length.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::{blake2b, hash4_exact, Params};
+///
+/// let hashes = hash4_exact(&Params::new(), b"foo", b"bar", b"baz", &[0; 128]);
+/// assert_eq!(blake2b(b"foo"), hashes[0]);
+/// assert_eq!(blake2b(&[0; 128]), hashes[3]);
+/// ```
+///
+/// [`blake2b_exact`]: fn.blake2b_exact.html
+/// [`update4`]: fn.update4.html
+pub fn hash4_exact(
+    params: &Params,
+    input0: &[u8],
+    input1: &[u8],
+    input2: &[u8],
+    input3: &[u8],
+) -> [Hash; 4] {
+    let implementation = guts::Implementation::detect();
+    let exact = ExactParams::new(params, implementation);
+    let mut padded0 = [0; BLOCKBYTES];
+    let mut padded1 = [0; BLOCKBYTES];
+    let mut padded2 = [0; BLOCKBYTES];
+    let mut padded3 = [0; BLOCKBYTES];
+    let (mut words0, block0, count0) = exact.final_block(input0, &mut padded0);
+    let (mut words1, block1, count1) = exact.final_block(input1, &mut padded1);
+    let (mut words2, block2, count2) = exact.final_block(input2, &mut padded2);
+    let (mut words3, block3, count3) = exact.final_block(input3, &mut padded3);
+
+    let mut transposed_state = implementation.transpose4(&words0, &words1, &words2, &words3);
+    implementation.compress4(
+        &mut transposed_state,
+        block0,
+        block1,
+        block2,
+        block3,
+        &guts::u64x4([count0 as u64, count1 as u64, count2 as u64, count3 as u64]),
+        // The count is always less than 2^64 here.
+        &guts::u64x4([0; 4]),
+        &guts::u64x4([!0; 4]),
+        &guts::u64x4([exact.lastnode; 4]),
+    );
+    implementation.untranspose4(
+        &transposed_state,
+        &mut words0,
+        &mut words1,
+        &mut words2,
+        &mut words3,
+    );
+    [
+        Hash {
+            bytes: state_words_to_bytes(&words0),
+            len: params.hash_length,
+        },
+        Hash {
+            bytes: state_words_to_bytes(&words1),
+            len: params.hash_length,
+        },
+        Hash {
+            bytes: state_words_to_bytes(&words2),
+            len: params.hash_length,
+        },
+        Hash {
+            bytes: state_words_to_bytes(&words3),
+            len: params.hash_length,
+        },
+    ]
+}
+
+// The parts of the BLAKE2b state that blake2b_exact and hash4_exact need, computed once for all
+// their inputs.
+struct ExactParams {
+    initial_words: StateWords,
+    // The state after compressing the key block, if there's a key.
+    keyed_words: StateWords,
+    key_block: Block,
+    keyed: bool,
+    lastnode: u64,
+}
+
+impl ExactParams {
+    fn new(params: &Params, implementation: guts::Implementation) -> Self {
+        let initial_words = params.to_state_words();
+        let mut key_block = [0; BLOCKBYTES];
+        key_block[..KEYBYTES].copy_from_slice(&params.key);
+        let keyed = params.key_length > 0;
+        let mut keyed_words = initial_words;
+        if keyed {
+            implementation.compress(&mut keyed_words, &key_block, BLOCKBYTES as u128, 0, 0);
+        }
+        Self {
+            initial_words,
+            keyed_words,
+            key_block,
+            keyed,
+            lastnode: if params.last_node { !0 } else { 0 },
+        }
+    }
+
+    // Return the state words to start from, the final block, and the final count for one input.
+    // Shorter inputs get copied into `padded`, which must be zeroed.
+    fn final_block<'a>(
+        &'a self,
+        input: &'a [u8],
+        padded: &'a mut Block,
+    ) -> (StateWords, &'a Block, u128) {
+        assert!(
+            input.len() <= BLOCKBYTES,
+            "Bad input length: {}",
+            input.len()
+        );
+        if !self.keyed {
+            let block = Self::pad(input, padded);
+            (self.initial_words, block, input.len() as u128)
+        } else if input.is_empty() {
+            // With a key and no input, the key block itself is the final block.
+            (self.initial_words, &self.key_block, BLOCKBYTES as u128)
+        } else {
+            let block = Self::pad(input, padded);
+            (self.keyed_words, block, (BLOCKBYTES + input.len()) as u128)
+        }
+    }
+
+    fn pad<'a>(input: &'a [u8], padded: &'a mut Block) -> &'a Block {
+        if input.len() == BLOCKBYTES {
+            array_ref!(input, 0, BLOCKBYTES)
+        } else {
+            padded[..input.len()].copy_from_slice(input);
+            padded
+        }
+    }
+}
+
+/// Compute the variable-length hash `H'` of the concatenation of `inputs`, and write it to `out`.
+/// The output length is `out.len()`, which must be between 1 and `2^32 - 1`.
+///
+/// This is the construction that Argon2 defines in
+/// [section 3.3 of RFC 9106](https://www.rfc-editor.org/rfc/rfc9106.html#section-3.3), which other
+/// protocols use too. The output length is prepended to the input as 4 little-endian bytes. Outputs
+/// up to `OUTBYTES` (64) are a single BLAKE2b hash of that length. Longer outputs are a chain of
+/// 64-byte hashes, each of the one before, taking the first 32 bytes of each, and ending with a
+/// hash of whatever length is left. Note that this is different from [BLAKE2Xb], and that the
+/// output length is associated data, so a short output is *not* a prefix of a long one. For
+/// incremental input, see [`LongState`].
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::blake2b_long;
+/// let mut output = [0; 100];
+/// blake2b_long(&mut output, &[b"foo", b"bar"]);
+/// ```
+///
+/// [BLAKE2Xb]: blake2xb/index.html
+/// [`LongState`]: struct.LongState.html
+pub fn blake2b_long(out: &mut [u8], inputs: &[&[u8]]) {
+    let mut state = LongState::new(out.len());
+    for input in inputs {
+        state.update(input);
+    }
+    state.finalize(out);
+}
+
+/// An incremental hasher for the variable-length hash `H'`. See [`blake2b_long`].
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::{blake2b_long, LongState};
+/// let mut state = LongState::new(100);
+/// state.update(b"foo");
+/// state.update(b"bar");
+/// let mut output = [0; 100];
+/// state.finalize(&mut output);
+///
+/// let mut expected = [0; 100];
+/// blake2b_long(&mut expected, &[b"foobar"]);
+/// assert_eq!(&expected[..], &output[..]);
+/// ```
+///
+/// [`blake2b_long`]: fn.blake2b_long.html
+#[derive(Clone)]
+pub struct LongState {
+    state: State,
+    output_length: u32,
+}
+
+impl LongState {
+    /// Create a `LongState` for an output of `output_length` bytes, between 1 and `2^32 - 1`.
+    pub fn new(output_length: usize) -> Self {
+        assert!(
+            1 <= output_length && output_length <= u32::MAX as usize,
+            "Bad output length: {}",
+            output_length
+        );
+        let mut state = Params::new()
+            .hash_length(cmp::min(output_length, OUTBYTES))
+            .to_state();
+        let mut length_bytes = [0; 4];
+        LittleEndian::write_u32(&mut length_bytes, output_length as u32);
+        state.update(&length_bytes);
+        Self {
+            state,
+            output_length: output_length as u32,
+        }
+    }
+
+    /// Add input to the hash. You can call `update` any number of times.
+    pub fn update(&mut self, input: &[u8]) -> &mut Self {
+        self.state.update(input);
+        self
+    }
+
+    /// Finalize the state and write the output to `out`, which must be `output_length` bytes. You
+    /// can keep adding input after calling `finalize`.
+    pub fn finalize(&mut self, out: &mut [u8]) {
+        assert_eq!(
+            self.output_length as usize,
+            out.len(),
+            "Bad output length: {}",
+            out.len()
+        );
+        let mut hash = self.state.finalize();
+        if out.len() <= OUTBYTES {
+            out.copy_from_slice(hash.as_bytes());
+            return;
+        }
+        let half = OUTBYTES / 2;
+        let mut position = 0;
+        loop {
+            out[position..][..half].copy_from_slice(&hash.as_bytes()[..half]);
+            position += half;
+            if out.len() - position <= OUTBYTES {
+                break;
+            }
+            hash = blake2b(hash.as_bytes());
+        }
+        let last = Params::new()
+            .hash_length(out.len() - position)
+            .to_state()
+            .update(hash.as_bytes())
+            .finalize();
+        out[position..].copy_from_slice(last.as_bytes());
+    }
+
+    /// The length of the output, as given to `new`.
+    pub fn output_length(&self) -> usize {
+        self.output_length as usize
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for LongState {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for LongState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "LongState {{ output_length: {}, state: {:?} }}",
+            self.output_length, self.state,
+        )
+    }
+}
+
+/// The BLAKE2b compression function `F`, with a variable number of rounds.
+///
+/// This is the interface of the BLAKE2 precompile in Ethereum,
+/// [EIP-152](https://eips.ethereum.org/EIPS/eip-152), which takes the round count, the 8-word
+/// state, a 128-byte message block, the 128-bit offset counter, and the final block flag, and
+/// updates the state in place. The message words are read from `block` in little-endian order. The
+/// message schedule repeats every 10 rounds, and 12 rounds is the compression function of regular
+/// BLAKE2b. Other round counts are useful for research on reduced-round BLAKE2b. Note that the
+/// running time is proportional to `rounds`, which can be as large as `2^32 - 1`.
+///
+/// This is a low-level building block, and most callers want [`State`] instead. It uses AVX2 when
+/// that's available.
+///
+/// # Example
+///
+/// ```
+/// # use blake2b_simd::{blake2b, compress_rounds, BLOCKBYTES};
+/// # use byteorder::{ByteOrder, LittleEndian};
+/// // The initial state for a 64-byte hash, the IV XORed with the parameter block.
+/// let mut state_words = [
+///     0x6a09e667f2bdc948,
+///     0xbb67ae8584caa73b,
+///     0x3c6ef372fe94f82b,
+///     0xa54ff53a5f1d36f1,
+///     0x510e527fade682d1,
+///     0x9b05688c2b3e6c1f,
+///     0x1f83d9abfb41bd6b,
+///     0x5be0cd19137e2179,
+/// ];
+/// let mut block = [0; BLOCKBYTES];
+/// block[..3].copy_from_slice(b"abc");
+/// compress_rounds(&mut state_words, &block, 3, true, 12);
+///
+/// let mut output = [0; 64];
+/// LittleEndian::write_u64_into(&state_words, &mut output);
+/// assert_eq!(blake2b(b"abc").as_bytes(), &output[..]);
+/// ```
+///
+/// [`State`]: struct.State.html
+pub fn compress_rounds(
+    state_words: &mut [u64; 8],
+    block: &[u8; BLOCKBYTES],
+    offset: u128,
+    final_block: bool,
+    rounds: u32,
+) {
+    let lastblock = if final_block { !0 } else { 0 };
+    guts::Implementation::detect().compress_rounds(
+        state_words,
+        block,
+        offset,
+        lastblock,
+        0,
+        rounds,
+    );
+}
+
 // This module is pub for internal benchmarks only. Please don't use it.
 #[doc(hidden)]
 pub mod benchmarks {
@@ -923,4 +2279,12 @@ pub mod benchmarks {
     pub fn force_portable_blake2bp(state: &mut crate::blake2bp::State) {
         crate::blake2bp::force_portable(state);
     }
+
+    pub fn force_portable_blake2s(state: &mut crate::blake2s::State) {
+        crate::blake2s::force_portable(state);
+    }
+
+    pub fn force_portable_blake2sp(state: &mut crate::blake2sp::State) {
+        crate::blake2sp::force_portable(state);
+    }
 }
@@ -0,0 +1,275 @@
+//! A message authentication code (MAC) built on keyed BLAKE2b.
+//!
+//! Keyed BLAKE2b is already a MAC, and [`Params::key`] is all it takes to use it that way. But a
+//! [`Hash`] also converts to a plain byte slice, and comparing slices with `==` isn't constant time,
+//! which leaks how much of a forged tag was correct. The [`Mac`] type here can't be created without
+//! a key, and it checks tags with [`verify`], which is constant time.
+//!
+//! Tags are 64 bytes by default. For shorter tags, set [`Params::hash_length`] and use
+//! [`Mac::with_params`]. Note that the hash length is associated data in BLAKE2b, so a 16-byte tag
+//! is *not* a prefix of the 64-byte tag for the same key and message, and a tag can only be
+//! verified at the length it was created with. Tags shorter than 16 bytes are easier to forge by
+//! guessing, and they aren't recommended.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::mac::Mac;
+//!
+//! let key = b"my secret key";
+//! let tag = Mac::new(key).update(b"foo").finalize();
+//!
+//! // Check the tag bytes received with a message.
+//! let received: &[u8] = tag.as_bytes();
+//! assert!(Mac::new(key).update(b"foo").verify(received).is_ok());
+//! assert!(Mac::new(key).update(b"bar").verify(received).is_err());
+//! ```
+//!
+//! [`Params::key`]: ../struct.Params.html#method.key
+//! [`Params::hash_length`]: ../struct.Params.html#method.hash_length
+//! [`Hash`]: ../struct.Hash.html
+//! [`Mac`]: struct.Mac.html
+//! [`verify`]: struct.Mac.html#method.verify
+//! [`Mac::with_params`]: struct.Mac.html#method.with_params
+
+use crate::Hash;
+use crate::Params;
+use crate::State;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std;
+
+/// An incremental MAC computation. This is a keyed BLAKE2b [`State`].
+///
+/// [`State`]: ../struct.State.html
+#[derive(Clone)]
+pub struct Mac {
+    state: State,
+}
+
+impl Mac {
+    /// Create a MAC with the given key and 64-byte tags. The key must be between 1 and
+    /// `KEYBYTES` (64) bytes long.
+    pub fn new(key: &[u8]) -> Self {
+        assert!(!key.is_empty(), "Bad key length: {}", key.len());
+        Self::with_params(Params::new().key(key))
+    }
+
+    /// Create a MAC from a set of BLAKE2b parameters, which must include a key. Use this for
+    /// shorter tags, or for a salt or personalization.
+    pub fn with_params(params: &Params) -> Self {
+        assert!(params.key_length > 0, "Bad key length: 0");
+        Self {
+            state: params.to_state(),
+        }
+    }
+
+    /// Add input to the MAC. You can call `update` any number of times.
+    pub fn update(&mut self, input: &[u8]) -> &mut Self {
+        self.state.update(input);
+        self
+    }
+
+    /// Finalize the MAC and return the tag. You can keep adding input after calling `finalize`.
+    pub fn finalize(&mut self) -> Tag {
+        Tag {
+            hash: self.state.finalize(),
+        }
+    }
+
+    /// Finalize the MAC and compare the tag to `tag` in constant time. A `tag` of the wrong length
+    /// fails right away, since the length isn't secret.
+    pub fn verify(&mut self, tag: &[u8]) -> Result<(), MacError> {
+        if self.finalize().hash == *tag {
+            Ok(())
+        } else {
+            Err(MacError)
+        }
+    }
+
+    /// The length of the tags this MAC produces.
+    pub fn tag_length(&self) -> usize {
+        self.state.hash_length as usize
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for Mac {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: The State doesn't print its words, so it won't leak the key.
+        write!(f, "Mac {{ state: {:?} }}", self.state)
+    }
+}
+
+/// A finalized MAC tag, with constant-time equality.
+#[derive(Clone, Copy)]
+pub struct Tag {
+    hash: Hash,
+}
+
+impl Tag {
+    /// Convert the tag to a byte slice, for sending it along with a message. To check a tag,
+    /// use [`Mac::verify`] or `==` on the `Tag`, not `==` on the bytes, which isn't constant time.
+    ///
+    /// [`Mac::verify`]: struct.Mac.html#method.verify
+    pub fn as_bytes(&self) -> &[u8] {
+        self.hash.as_bytes()
+    }
+
+    /// Convert the tag to a lowercase hexadecimal
+    /// [`ArrayString`](https://docs.rs/arrayvec/0.4/arrayvec/struct.ArrayString.html).
+    pub fn to_hex(&self) -> crate::HexString {
+        self.hash.to_hex()
+    }
+}
+
+/// This implementation is constant time, if the two tags are the same length.
+impl PartialEq for Tag {
+    fn eq(&self, other: &Tag) -> bool {
+        self.hash == other.hash
+    }
+}
+
+/// This implementation is constant time, if the slice is the same length as the tag.
+impl PartialEq<[u8]> for Tag {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.hash == *other
+    }
+}
+
+impl Eq for Tag {}
+
+impl AsRef<[u8]> for Tag {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl fmt::Debug for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tag(0x{})", self.to_hex())
+    }
+}
+
+/// The error returned when a tag fails to verify. It doesn't say anything else about the tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MacError;
+
+impl fmt::Display for MacError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MAC tag mismatch")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MacError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches_keyed_blake2b() {
+        let key = b"some key";
+        let expected = Params::new().key(key).to_state().update(b"foo").finalize();
+        let tag = Mac::new(key).update(b"f").update(b"oo").finalize();
+        assert_eq!(expected.as_bytes(), tag.as_bytes());
+        assert_eq!(64, tag.as_bytes().len());
+        assert_eq!(expected.to_hex(), tag.to_hex());
+    }
+
+    #[test]
+    fn test_verify() {
+        let key = [0x42; 64];
+        let tag = Mac::new(&key).update(b"foo").finalize();
+        assert!(Mac::new(&key).update(b"foo").verify(tag.as_bytes()).is_ok());
+        assert_eq!(
+            Err(MacError),
+            Mac::new(&key).update(b"fop").verify(tag.as_bytes())
+        );
+        assert!(Mac::new(&key[1..])
+            .update(b"foo")
+            .verify(tag.as_bytes())
+            .is_err());
+
+        // Flipping any bit fails.
+        for i in 0..tag.as_bytes().len() {
+            let mut bad = [0; 64];
+            bad.copy_from_slice(tag.as_bytes());
+            bad[i] ^= 1;
+            assert!(Mac::new(&key).update(b"foo").verify(&bad).is_err());
+            assert!(tag != bad[..]);
+        }
+
+        // A prefix of the tag isn't accepted.
+        assert!(Mac::new(&key)
+            .update(b"foo")
+            .verify(&tag.as_bytes()[..32])
+            .is_err());
+        assert!(Mac::new(&key).update(b"foo").verify(&[]).is_err());
+    }
+
+    #[test]
+    fn test_truncated_tags() {
+        for &length in &[1, 16, 32, 63] {
+            let mut params = Params::new();
+            params.key(b"some key").hash_length(length);
+            let mut mac = Mac::with_params(&params);
+            assert_eq!(length, mac.tag_length());
+            let tag = mac.update(b"foo").finalize();
+            assert_eq!(length, tag.as_bytes().len());
+            assert_eq!(
+                params.to_state().update(b"foo").finalize().as_bytes(),
+                tag.as_bytes()
+            );
+            assert!(Mac::with_params(&params)
+                .update(b"foo")
+                .verify(tag.as_bytes())
+                .is_ok());
+            // The full-length tag is a different value, not an extension.
+            let full = Mac::new(b"some key").update(b"foo").finalize();
+            assert!(Mac::new(b"some key")
+                .update(b"foo")
+                .verify(tag.as_bytes())
+                .is_err());
+            assert!(full.as_bytes()[..length] != *tag.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_finalize_is_idempotent() {
+        let mut mac = Mac::new(b"key");
+        mac.update(b"foo");
+        let tag = mac.finalize();
+        assert_eq!(tag, mac.finalize());
+        mac.update(b"bar");
+        assert_eq!(
+            Mac::new(b"key").update(b"foobar").finalize(),
+            mac.finalize()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_key_panics() {
+        Mac::new(b"");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_params_without_key_panics() {
+        Mac::with_params(Params::new().hash_length(32));
+    }
+}
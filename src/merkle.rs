@@ -0,0 +1,385 @@
+//! Binary Merkle trees over BLAKE2b, with inclusion proofs.
+//!
+//! Leaves and parent nodes are kept apart using the BLAKE2 tree parameters, rather than ad hoc
+//! prefix bytes. Every node is hashed with a fanout of 2, unlimited depth, and an inner hash
+//! length equal to the hash length. Leaves have a `node_depth` of 0, and parents have the depth of
+//! their level above the leaves. Every node's `node_offset` is its index within its level, so a
+//! leaf hash also commits to the leaf's position. The rest of the caller's [`Params`] (hash
+//! length, key, salt, and personalization) apply to every node, and any tree parameters set there
+//! are replaced.
+//!
+//! When a level has an odd number of nodes, the last one has no sibling, and it moves up to the
+//! next level unchanged. It's not paired with a copy of itself, which would let two different
+//! lists of leaves produce the same root.
+//!
+//! Leaves are hashed four at a time with [`update4`] and [`finalize4`].
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::{merkle, Params};
+//!
+//! let mut params = Params::new();
+//! params.hash_length(32).personal(b"my app leaves");
+//! let leaves: &[&[u8]] = &[b"foo", b"bar", b"baz"];
+//! let tree = merkle::Tree::new(&params, leaves);
+//! assert_eq!(merkle::root(&params, leaves), tree.root());
+//!
+//! let proof = tree.proof(2);
+//! assert!(proof.verify(&params, &tree.root(), b"baz"));
+//! assert!(!proof.verify(&params, &tree.root(), b"bar"));
+//! ```
+//!
+//! [`Params`]: ../struct.Params.html
+//! [`update4`]: ../fn.update4.html
+//! [`finalize4`]: ../fn.finalize4.html
+
+use crate::Hash;
+use crate::Params;
+use core::fmt;
+
+use std;
+use std::vec::Vec;
+
+fn node_params(params: &Params, depth: usize, offset: usize) -> Params {
+    let mut node_params = params.clone();
+    node_params
+        .fanout(2)
+        .max_depth(u8::MAX)
+        .inner_hash_length(params.hash_length as usize)
+        .node_depth(depth as u8)
+        .node_offset(offset as u64)
+        .last_node(false);
+    node_params
+}
+
+fn hash_leaf(params: &Params, index: usize, leaf: &[u8]) -> Hash {
+    node_params(params, 0, index)
+        .to_state()
+        .update(leaf)
+        .finalize()
+}
+
+fn hash_parent(params: &Params, depth: usize, offset: usize, left: &Hash, right: &Hash) -> Hash {
+    node_params(params, depth, offset)
+        .to_state()
+        .update(left.as_bytes())
+        .update(right.as_bytes())
+        .finalize()
+}
+
+fn hash_leaves(params: &Params, leaves: &[&[u8]]) -> Vec<Hash> {
+    let mut hashes = Vec::with_capacity(leaves.len());
+    let mut groups = leaves.chunks_exact(4);
+    for (group_index, group) in (&mut groups).enumerate() {
+        let offset = 4 * group_index;
+        let mut state0 = node_params(params, 0, offset).to_state();
+        let mut state1 = node_params(params, 0, offset + 1).to_state();
+        let mut state2 = node_params(params, 0, offset + 2).to_state();
+        let mut state3 = node_params(params, 0, offset + 3).to_state();
+        crate::update4(
+            &mut state0,
+            &mut state1,
+            &mut state2,
+            &mut state3,
+            group[0],
+            group[1],
+            group[2],
+            group[3],
+        );
+        hashes.extend_from_slice(&crate::finalize4(
+            &mut state0,
+            &mut state1,
+            &mut state2,
+            &mut state3,
+        ));
+    }
+    for leaf in groups.remainder() {
+        let index = hashes.len();
+        hashes.push(hash_leaf(params, index, leaf));
+    }
+    hashes
+}
+
+/// Compute the Merkle root of a list of leaves. This is the same as `Tree::new(params,
+/// leaves).root()`, but it doesn't keep the whole tree around. This panics if `leaves` is empty.
+pub fn root(params: &Params, leaves: &[&[u8]]) -> Hash {
+    assert!(!leaves.is_empty(), "Bad leaf count: 0");
+    let mut level = hash_leaves(params, leaves);
+    let mut depth = 0;
+    while level.len() > 1 {
+        depth += 1;
+        level = next_level(params, depth, &level);
+    }
+    level[0]
+}
+
+fn next_level(params: &Params, depth: usize, children: &[Hash]) -> Vec<Hash> {
+    children
+        .chunks(2)
+        .enumerate()
+        .map(|(offset, pair)| {
+            if pair.len() == 2 {
+                hash_parent(params, depth, offset, &pair[0], &pair[1])
+            } else {
+                // An odd node out moves up unchanged.
+                pair[0]
+            }
+        })
+        .collect()
+}
+
+/// A complete Merkle tree, which can produce inclusion proofs for its leaves.
+#[derive(Clone)]
+pub struct Tree {
+    // Level 0 is the leaf hashes, and the last level is the root alone.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl Tree {
+    /// Hash all the leaves and build the tree. This panics if `leaves` is empty.
+    pub fn new(params: &Params, leaves: &[&[u8]]) -> Self {
+        assert!(!leaves.is_empty(), "Bad leaf count: 0");
+        let mut levels = vec![hash_leaves(params, leaves)];
+        while levels[levels.len() - 1].len() > 1 {
+            let depth = levels.len();
+            let level = next_level(params, depth, &levels[depth - 1]);
+            levels.push(level);
+        }
+        Self { levels }
+    }
+
+    /// The root hash of the tree.
+    pub fn root(&self) -> Hash {
+        self.levels[self.levels.len() - 1][0]
+    }
+
+    /// The number of leaves in the tree.
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Build an inclusion proof for the leaf at `index`. This panics if `index` is out of range.
+    pub fn proof(&self, index: usize) -> Proof {
+        assert!(index < self.leaf_count(), "Bad leaf index: {}", index);
+        let mut siblings = Vec::new();
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = position ^ 1;
+            if sibling < level.len() {
+                siblings.push(level[sibling]);
+            }
+            position /= 2;
+        }
+        Proof {
+            index,
+            leaf_count: self.leaf_count(),
+            siblings,
+        }
+    }
+}
+
+impl fmt::Debug for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Tree {{ leaf_count: {}, root: {:?} }}",
+            self.leaf_count(),
+            self.root(),
+        )
+    }
+}
+
+/// An inclusion proof for one leaf of a Merkle tree.
+///
+/// A proof holds the sibling hashes along the path from the leaf to the root, lowest first. Levels
+/// where the path has no sibling, because it's the odd node out, are skipped, so the proof for a
+/// tree of `n` leaves holds at most `ceil(log2(n))` hashes. The shape of the path follows from the
+/// leaf index and the leaf count, which the proof also records. Note that the root commits to the
+/// position of every leaf, but not directly to the leaf count. A proof with a different leaf count
+/// can still verify, if it gives the same path shape, and it proves the same thing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof {
+    index: usize,
+    leaf_count: usize,
+    siblings: Vec<Hash>,
+}
+
+impl Proof {
+    /// Reassemble a proof from its parts, for example after receiving them over the network. An
+    /// inconsistent proof isn't an error here, but it won't verify.
+    pub fn from_parts(index: usize, leaf_count: usize, siblings: Vec<Hash>) -> Self {
+        Self {
+            index,
+            leaf_count,
+            siblings,
+        }
+    }
+
+    /// The index of the leaf this proof is for.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The number of leaves in the tree this proof is for.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// The sibling hashes along the path to the root, lowest first.
+    pub fn siblings(&self) -> &[Hash] {
+        &self.siblings
+    }
+
+    /// Check that `leaf` is in the tree with the given `root`, at this proof's index. The `params`
+    /// must be the same as the ones the tree was built with. The final comparison is constant
+    /// time.
+    pub fn verify(&self, params: &Params, root: &Hash, leaf: &[u8]) -> bool {
+        if self.index >= self.leaf_count {
+            return false;
+        }
+        let mut node = hash_leaf(params, self.index, leaf);
+        let mut position = self.index;
+        let mut level_len = self.leaf_count;
+        let mut siblings = self.siblings.iter();
+        let mut depth = 0;
+        while level_len > 1 {
+            depth += 1;
+            if position % 2 == 1 {
+                match siblings.next() {
+                    Some(left) => node = hash_parent(params, depth, position / 2, left, &node),
+                    None => return false,
+                }
+            } else if position + 1 < level_len {
+                match siblings.next() {
+                    Some(right) => node = hash_parent(params, depth, position / 2, &node, right),
+                    None => return false,
+                }
+            }
+            position /= 2;
+            level_len = level_len / 2 + level_len % 2;
+        }
+        siblings.next().is_none() && node == *root
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blake2bp::test::paint_input;
+
+    fn leaves_from(input: &[u8], count: usize) -> Vec<&[u8]> {
+        // Leaves of varying lengths, including empty ones.
+        (0..count).map(|i| &input[i..][..(i * 37) % 300]).collect()
+    }
+
+    #[test]
+    fn test_three_leaves_by_hand() {
+        let params = Params::new();
+        let leaf = |index, input: &[u8]| {
+            Params::new()
+                .fanout(2)
+                .max_depth(255)
+                .inner_hash_length(64)
+                .node_offset(index)
+                .to_state()
+                .update(input)
+                .finalize()
+        };
+        let parent = |depth, offset, left: &Hash, right: &Hash| {
+            Params::new()
+                .fanout(2)
+                .max_depth(255)
+                .inner_hash_length(64)
+                .node_depth(depth)
+                .node_offset(offset)
+                .to_state()
+                .update(left.as_bytes())
+                .update(right.as_bytes())
+                .finalize()
+        };
+        let left = parent(1, 0, &leaf(0, b"a"), &leaf(1, b"b"));
+        let expected = parent(2, 0, &left, &leaf(2, b"c"));
+        let leaves: &[&[u8]] = &[b"a", b"b", b"c"];
+        assert_eq!(expected, root(&params, leaves));
+        assert_eq!(expected, Tree::new(&params, leaves).root());
+        // A single leaf is its own root.
+        assert_eq!(leaf(0, b"a"), root(&params, &leaves[..1]));
+    }
+
+    #[test]
+    fn test_proofs() {
+        let mut input = [0; 400];
+        paint_input(&mut input);
+        let mut params = Params::new();
+        params.hash_length(24).key(b"key").personal(b"leaves");
+        for count in 1..=20 {
+            let leaves = leaves_from(&input, count);
+            let tree = Tree::new(&params, &leaves);
+            assert_eq!(count, tree.leaf_count());
+            assert_eq!(root(&params, &leaves), tree.root());
+            for (index, &leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(index);
+                assert!(proof.verify(&params, &tree.root(), leaf));
+                let rebuilt = Proof::from_parts(index, count, proof.siblings().to_vec());
+                assert_eq!(proof, rebuilt);
+
+                // The wrong leaf, index, count, root, or params must fail.
+                assert!(!proof.verify(&params, &tree.root(), b"wrong leaf"));
+                let wrong_index = Proof::from_parts(index ^ 1, count, proof.siblings().to_vec());
+                assert!(!wrong_index.verify(&params, &tree.root(), leaf));
+                let out_of_range = Proof::from_parts(index, index, proof.siblings().to_vec());
+                assert!(!out_of_range.verify(&params, &tree.root(), leaf));
+                let wrong_root = root(&params, &[b"wrong root"]);
+                assert!(!proof.verify(&params, &wrong_root, leaf));
+                assert!(!proof.verify(&Params::new(), &tree.root(), leaf));
+
+                // Extra or missing siblings must fail.
+                let mut siblings = proof.siblings().to_vec();
+                siblings.push(tree.root());
+                assert!(!Proof::from_parts(index, count, siblings).verify(
+                    &params,
+                    &tree.root(),
+                    leaf
+                ));
+                if !proof.siblings().is_empty() {
+                    let siblings = proof.siblings()[1..].to_vec();
+                    assert!(!Proof::from_parts(index, count, siblings).verify(
+                        &params,
+                        &tree.root(),
+                        leaf
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaves_and_parents_are_separate() {
+        // A leaf whose content is the concatenation of two child hashes doesn't collide with their
+        // parent.
+        let params = Params::new();
+        let leaves: &[&[u8]] = &[b"a", b"b"];
+        let tree = Tree::new(&params, leaves);
+        let mut fake_leaf = Vec::new();
+        fake_leaf.extend_from_slice(tree.levels[0][0].as_bytes());
+        fake_leaf.extend_from_slice(tree.levels[0][1].as_bytes());
+        assert_ne!(tree.root(), root(&params, &[&fake_leaf[..]]));
+        // And an odd leaf out isn't paired with itself.
+        let three: &[&[u8]] = &[b"a", b"b", b"c"];
+        let four: &[&[u8]] = &[b"a", b"b", b"c", b"c"];
+        assert_ne!(root(&params, three), root(&params, four));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_no_leaves_panics() {
+        root(&Params::new(), &[]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_proof_index_out_of_range_panics() {
+        Tree::new(&Params::new(), &[b"foo"]).proof(1);
+    }
+}
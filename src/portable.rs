@@ -40,13 +40,9 @@ fn round(r: usize, m: &[u64; 16], v: &mut [u64; 16]) {
     g(v, 3, 4, 9, 14, m[s[14] as usize], m[s[15] as usize]);
 }
 
-// H is the 8-word state vector. `msg` is BLOCKBYTES of input, possibly padded
-// with zero bytes in the final block. `count` is the number of bytes fed so
-// far, including in this call, though not including padding in the final call.
-// `finalize` is set to true only in the final call.
-pub fn compress(h: &mut StateWords, msg: &Block, count: u128, lastblock: u64, lastnode: u64) {
-    // Initialize the compression state.
-    let mut v = [
+#[inline(always)]
+fn initial_state(h: &StateWords, count: u128, lastblock: u64, lastnode: u64) -> [u64; 16] {
+    [
         h[0],
         h[1],
         h[2],
@@ -63,11 +59,14 @@ pub fn compress(h: &mut StateWords, msg: &Block, count: u128, lastblock: u64, la
         IV[5] ^ (count >> 64) as u64,
         IV[6] ^ lastblock,
         IV[7] ^ lastnode,
-    ];
+    ]
+}
 
-    // Parse the message bytes as ints in little endian order.
+// Parse the message bytes as ints in little endian order.
+#[inline(always)]
+fn message_words(msg: &Block) -> [u64; 16] {
     let msg_refs = array_refs!(msg, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8);
-    let m = [
+    [
         LittleEndian::read_u64(msg_refs.0),
         LittleEndian::read_u64(msg_refs.1),
         LittleEndian::read_u64(msg_refs.2),
@@ -84,7 +83,28 @@ pub fn compress(h: &mut StateWords, msg: &Block, count: u128, lastblock: u64, la
         LittleEndian::read_u64(msg_refs.13),
         LittleEndian::read_u64(msg_refs.14),
         LittleEndian::read_u64(msg_refs.15),
-    ];
+    ]
+}
+
+#[inline(always)]
+fn finish(h: &mut StateWords, v: &[u64; 16]) {
+    h[0] ^= v[0] ^ v[8];
+    h[1] ^= v[1] ^ v[9];
+    h[2] ^= v[2] ^ v[10];
+    h[3] ^= v[3] ^ v[11];
+    h[4] ^= v[4] ^ v[12];
+    h[5] ^= v[5] ^ v[13];
+    h[6] ^= v[6] ^ v[14];
+    h[7] ^= v[7] ^ v[15];
+}
+
+// H is the 8-word state vector. `msg` is BLOCKBYTES of input, possibly padded
+// with zero bytes in the final block. `count` is the number of bytes fed so
+// far, including in this call, though not including padding in the final call.
+// `finalize` is set to true only in the final call.
+pub fn compress(h: &mut StateWords, msg: &Block, count: u128, lastblock: u64, lastnode: u64) {
+    let mut v = initial_state(h, count, lastblock, lastnode);
+    let m = message_words(msg);
 
     round(0, &m, &mut v);
     round(1, &m, &mut v);
@@ -99,14 +119,59 @@ pub fn compress(h: &mut StateWords, msg: &Block, count: u128, lastblock: u64, la
     round(10, &m, &mut v);
     round(11, &m, &mut v);
 
-    h[0] ^= v[0] ^ v[8];
-    h[1] ^= v[1] ^ v[9];
-    h[2] ^= v[2] ^ v[10];
-    h[3] ^= v[3] ^ v[11];
-    h[4] ^= v[4] ^ v[12];
-    h[5] ^= v[5] ^ v[13];
-    h[6] ^= v[6] ^ v[14];
-    h[7] ^= v[7] ^ v[15];
+    finish(h, &v);
+}
+
+// The same as compress, but with any number of rounds, like the F function in
+// EIP-152. The message schedule repeats every 10 rounds, so 12 rounds is the
+// same as compress.
+pub fn compress_rounds(
+    h: &mut StateWords,
+    msg: &Block,
+    count: u128,
+    lastblock: u64,
+    lastnode: u64,
+    rounds: u32,
+) {
+    let mut v = initial_state(h, count, lastblock, lastnode);
+    let m = message_words(msg);
+    for r in 0..rounds {
+        round((r % 10) as usize, &m, &mut v);
+    }
+    finish(h, &v);
+}
+
+// The BlaMka variant of G from Argon2. It takes no message words, and each
+// addition also adds twice the product of the low 32 bits of its operands.
+#[inline(always)]
+fn blamka_add(x: u64, y: u64) -> u64 {
+    let product = (x & 0xffff_ffff) * (y & 0xffff_ffff);
+    x.wrapping_add(y).wrapping_add(product.wrapping_mul(2))
+}
+
+#[inline(always)]
+fn blamka_g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize) {
+    v[a] = blamka_add(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = blamka_add(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = blamka_add(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = blamka_add(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+// One round of the Argon2 permutation P, with the same column and diagonal
+// order as the BLAKE2b round.
+pub fn blamka_round(v: &mut [u64; 16]) {
+    blamka_g(v, 0, 4, 8, 12);
+    blamka_g(v, 1, 5, 9, 13);
+    blamka_g(v, 2, 6, 10, 14);
+    blamka_g(v, 3, 7, 11, 15);
+    blamka_g(v, 0, 5, 10, 15);
+    blamka_g(v, 1, 6, 11, 12);
+    blamka_g(v, 2, 7, 8, 13);
+    blamka_g(v, 3, 4, 9, 14);
 }
 
 pub fn transpose2(words0: &[u64; 8], words1: &[u64; 8]) -> [u64x2; 8] {
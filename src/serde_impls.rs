@@ -0,0 +1,174 @@
+// Serde support, with the optional `serde` Cargo feature. A State serializes as the bytes from its
+// `save` method, so the serialized form is just as sensitive as those bytes. A Hash serializes as
+// hex in human-readable formats like JSON, and as raw bytes in binary formats.
+
+use crate::blake2bp;
+use crate::Hash;
+use crate::ParseHashError;
+use crate::RestoreError;
+use crate::State;
+use crate::OUTBYTES;
+use core::fmt;
+use core::marker::PhantomData;
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+// The longest saved state, which bounds the buffer that visit_seq collects into.
+const MAX_SAVED_STATE_BYTES: usize = blake2bp::SAVED_STATE_BYTES;
+
+trait Restore: Sized {
+    fn restore(saved: &[u8]) -> Result<Self, RestoreError>;
+}
+
+impl Restore for State {
+    fn restore(saved: &[u8]) -> Result<Self, RestoreError> {
+        State::restore(saved)
+    }
+}
+
+impl Restore for blake2bp::State {
+    fn restore(saved: &[u8]) -> Result<Self, RestoreError> {
+        blake2bp::State::restore(saved)
+    }
+}
+
+struct SavedStateVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Restore> Visitor<'de> for SavedStateVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a saved BLAKE2 state")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<T, E> {
+        T::restore(v).map_err(E::custom)
+    }
+
+    // Formats without a native bytes type, like JSON, serialize bytes as a sequence.
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let mut saved = [0; MAX_SAVED_STATE_BYTES];
+        let mut len = 0;
+        while let Some(byte) = seq.next_element()? {
+            if len == saved.len() {
+                return Err(de::Error::custom(RestoreError::WrongLength));
+            }
+            saved[len] = byte;
+            len += 1;
+        }
+        T::restore(&saved[..len]).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for State {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.save()[..])
+    }
+}
+
+impl<'de> Deserialize<'de> for State {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(SavedStateVisitor(PhantomData))
+    }
+}
+
+impl Serialize for blake2bp::State {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.save()[..])
+    }
+}
+
+impl<'de> Deserialize<'de> for blake2bp::State {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(SavedStateVisitor(PhantomData))
+    }
+}
+
+impl Serialize for Hash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_hex())
+        } else {
+            serializer.serialize_bytes(self.as_bytes())
+        }
+    }
+}
+
+struct HashVisitor;
+
+impl<'de> Visitor<'de> for HashVisitor {
+    type Value = Hash;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a BLAKE2 hash, as a hex string or as bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Hash, E> {
+        Hash::from_hex(v).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Hash, E> {
+        Hash::from_bytes(v).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Hash, A::Error> {
+        let mut bytes = [0; OUTBYTES];
+        let mut len = 0;
+        while let Some(byte) = seq.next_element()? {
+            if len == bytes.len() {
+                return Err(de::Error::custom(ParseHashError::InvalidLength));
+            }
+            bytes[len] = byte;
+            len += 1;
+        }
+        Hash::from_bytes(&bytes[..len]).map_err(de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(HashVisitor)
+        } else {
+            deserializer.deserialize_bytes(HashVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::de::value::{BytesDeserializer, Error, SeqDeserializer};
+
+    // JSON only exercises visit_str for a Hash and visit_seq for a State. These deserializers call
+    // the other visitor methods, like a binary format would.
+
+    #[test]
+    fn test_hash_from_bytes_and_seq() {
+        let hash = crate::blake2b(b"abc");
+        let deserializer = BytesDeserializer::<Error>::new(hash.as_bytes());
+        assert_eq!(hash, Hash::deserialize(deserializer).unwrap());
+        let deserializer = SeqDeserializer::<_, Error>::new(hash.as_bytes().iter().cloned());
+        assert_eq!(hash, Hash::deserialize(deserializer).unwrap());
+
+        let deserializer = BytesDeserializer::<Error>::new(&[]);
+        assert!(Hash::deserialize(deserializer).is_err());
+        let too_long = [0; OUTBYTES + 1];
+        let deserializer = SeqDeserializer::<_, Error>::new(too_long.iter().cloned());
+        assert!(Hash::deserialize(deserializer).is_err());
+    }
+
+    #[test]
+    fn test_state_from_bytes() {
+        let mut state = State::new();
+        state.update(b"abc");
+        let saved = state.save();
+        let deserializer = BytesDeserializer::<Error>::new(&saved);
+        assert_eq!(
+            state.finalize(),
+            State::deserialize(deserializer).unwrap().finalize()
+        );
+        let deserializer = BytesDeserializer::<Error>::new(&saved[1..]);
+        assert!(State::deserialize(deserializer).is_err());
+    }
+}
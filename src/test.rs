@@ -123,6 +123,148 @@ fn test_all_parameters_blake2bp() {
     assert_eq!("8c54e888a8a01c63da6585c058fe54ea81df", &hash.to_hex());
 }
 
+#[test]
+fn test_param_block() {
+    let mut params = Params::new();
+    params
+        .hash_length(18)
+        .key(b"bar")
+        .salt(b"bazbazbazbazbazb")
+        .personal(b"bing bing bing b")
+        .fanout(2)
+        .max_depth(3)
+        .max_leaf_length(0x04050607)
+        .node_offset(0x08090a0b0c0d0e0f)
+        .node_depth(16)
+        .inner_hash_length(17);
+    // The blake2b_param struct from the reference implementation, packed with Python's struct
+    // module.
+    let expected = "12030203070605040f0e0d0c0b0a09081011000000000000000000000000000062617a62617a62\
+                    617a62617a62617a6262696e672062696e672062696e672062";
+    let block = params.to_param_block();
+    assert_eq!(expected, &hex::encode(&block[..]));
+    let mut default_block = [0; 64];
+    default_block[..4].copy_from_slice(&[64, 0, 1, 1]);
+    assert_eq!(&default_block[..], &Params::new().to_param_block()[..]);
+
+    // The key isn't in the block, so keyed blocks need it passed separately, and its length has
+    // to match.
+    assert_eq!(
+        ParamBlockError::KeyLengthMismatch,
+        Params::from_param_block(&block).unwrap_err()
+    );
+    let parsed = Params::from_param_block_with_key(&block, b"bar").unwrap();
+    assert_eq!(&block[..], &parsed.to_param_block()[..]);
+    let hash = parsed
+        .to_state()
+        .set_last_node(true)
+        .update(b"foo")
+        .finalize();
+    assert_eq!("ec0f59cb65f92e7fcca1280ba859a6925ded", &hash.to_hex());
+    for &key in &[&b""[..], b"ba", b"barr"] {
+        assert_eq!(
+            ParamBlockError::KeyLengthMismatch,
+            Params::from_param_block_with_key(&block, key).unwrap_err()
+        );
+    }
+    let default_params = Params::from_param_block(&default_block).unwrap();
+    assert_eq!(
+        blake2b(b"foo"),
+        default_params.to_state().update(b"foo").finalize()
+    );
+    assert_eq!(
+        ParamBlockError::KeyLengthMismatch,
+        Params::from_param_block_with_key(&default_block, b"bar").unwrap_err()
+    );
+
+    let invalid = [(0, 0), (0, 65), (1, 65), (3, 0), (17, 65)];
+    for &(index, value) in &invalid {
+        let mut bad_block = default_block;
+        bad_block[index] = value;
+        assert_eq!(
+            ParamBlockError::InvalidParameter,
+            Params::from_param_block(&bad_block).unwrap_err()
+        );
+    }
+    for index in 18..32 {
+        let mut bad_block = default_block;
+        bad_block[index] = 1;
+        assert_eq!(
+            ParamBlockError::NonzeroReserved,
+            Params::from_param_block(&bad_block).unwrap_err()
+        );
+    }
+}
+
+#[test]
+fn test_param_block_blake2bp() {
+    let mut params = blake2bp::Params::new();
+    params.hash_length(18).key(b"bar");
+    // The blake2b_param structs from the reference BLAKE2bp, packed with Python's struct module.
+    let leaf_prefixes = [
+        "120304020000000000000000000000000040",
+        "120304020000000001000000000000000040",
+        "120304020000000002000000000000000040",
+        "120304020000000003000000000000000040",
+    ];
+    for (i, prefix) in leaf_prefixes.iter().enumerate() {
+        let block = params.to_leaf_param_block(i as u64);
+        assert_eq!(*prefix, &hex::encode(&block[..18]));
+        assert!(block[18..].iter().all(|&b| b == 0));
+    }
+    let block = params.to_root_param_block();
+    assert_eq!(
+        "120304020000000000000000000000000140",
+        &hex::encode(&block[..18])
+    );
+    assert!(block[18..].iter().all(|&b| b == 0));
+
+    assert_eq!(
+        ParamBlockError::KeyLengthMismatch,
+        blake2bp::Params::from_param_block(&block).unwrap_err()
+    );
+    let blocks = [
+        params.to_leaf_param_block(0),
+        params.to_leaf_param_block(3),
+        params.to_root_param_block(),
+    ];
+    for block in &blocks {
+        let parsed = blake2bp::Params::from_param_block_with_key(block, b"bar").unwrap();
+        for i in 0..4 {
+            assert_eq!(
+                &params.to_leaf_param_block(i)[..],
+                &parsed.to_leaf_param_block(i)[..]
+            );
+        }
+        assert_eq!(
+            &params.to_root_param_block()[..],
+            &parsed.to_root_param_block()[..]
+        );
+        let hash = parsed.to_state().update(b"foo").finalize();
+        assert_eq!("8c54e888a8a01c63da6585c058fe54ea81df", &hash.to_hex());
+    }
+
+    // Ordinary BLAKE2b parameters aren't BLAKE2bp, and neither is a fifth leaf.
+    let mut fifth_leaf = blocks[0];
+    fifth_leaf[8] = 4;
+    let not_blake2bp = [
+        (Params::new().to_param_block(), &b""[..]),
+        (fifth_leaf, b"bar"),
+    ];
+    for &(ref block, key) in &not_blake2bp {
+        assert_eq!(
+            ParamBlockError::InvalidParameter,
+            blake2bp::Params::from_param_block_with_key(block, key).unwrap_err()
+        );
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_blake2bp_leaf_param_block_panics() {
+    blake2bp::Params::new().to_leaf_param_block(4);
+}
+
 #[test]
 #[should_panic]
 fn test_short_hash_length_panics() {
@@ -183,6 +325,63 @@ fn test_blake2bp_long_key_panics() {
     blake2bp::Params::new().key(&[0; KEYBYTES + 1]);
 }
 
+#[test]
+fn test_update2() {
+    // This is the same as test_update4 below, with two lanes.
+    const INPUT_PREFIX: &[u8] = b"foobarbaz";
+
+    fn test_run(state0: &mut State, state1: &mut State, input0: &[u8], input1: &[u8]) {
+        // Compute the expected hashes the normal way, using cloned copies.
+        let expected0 = state0.clone().update(input0).finalize();
+        let expected1 = state1.clone().update(input1).finalize();
+
+        // Now do the same thing using the parallel interface.
+        update2(state0, state1, input0, input1);
+        let output = finalize2(state0, state1);
+
+        assert_eq!(expected0, output[0]);
+        assert_eq!(expected1, output[1]);
+    }
+
+    // State A is default.
+    let mut state_a = State::new();
+    // State B sets last node on the state.
+    let mut state_b = State::new();
+    state_b.set_last_node(true);
+    // State C gets a "foobarbaz" prefix.
+    let mut state_c = State::new();
+    state_c.update(INPUT_PREFIX);
+    // State D gets wacky parameters.
+    let mut state_d = Params::new()
+        .hash_length(18)
+        .key(b"bar")
+        .salt(b"bazbazbazbazbazb")
+        .personal(b"bing bing bing b")
+        .fanout(2)
+        .max_depth(3)
+        .max_leaf_length(0x04050607)
+        .node_offset(0x08090a0b0c0d0e0f)
+        .node_depth(16)
+        .inner_hash_length(17)
+        .last_node(true)
+        .to_state();
+
+    let mut input = [0; 15 * BLOCKBYTES];
+    blake2bp::test::paint_input(&mut input);
+    let input_e = &input[..10 * BLOCKBYTES];
+    // Input F is short.
+    let input_f = &input[10 * BLOCKBYTES..15 * BLOCKBYTES];
+
+    // Loop over both orders of the input.
+    for (input0, input1) in &[(input_e, input_f), (input_f, input_e)] {
+        // For each input order, run different pairs of states.
+        test_run(&mut state_a, &mut state_b, input0, input1);
+        test_run(&mut state_b, &mut state_c, input0, input1);
+        test_run(&mut state_c, &mut state_d, input0, input1);
+        test_run(&mut state_d, &mut state_a, input0, input1);
+    }
+}
+
 #[test]
 fn test_update4() {
     const INPUT_PREFIX: &[u8] = b"foobarbaz";
@@ -298,3 +497,711 @@ fn test_update4() {
         );
     }
 }
+
+#[test]
+fn test_update8() {
+    // Eight different kinds of state. Some are default, some set last node, some have a prefix
+    // buffered already, and some have wacky parameters.
+    let mut wacky_params = Params::new();
+    wacky_params
+        .hash_length(18)
+        .key(b"bar")
+        .salt(b"bazbazbazbazbazb")
+        .personal(b"bing bing bing b")
+        .fanout(2)
+        .max_depth(3)
+        .max_leaf_length(0x04050607)
+        .node_offset(0x08090a0b0c0d0e0f)
+        .node_depth(16)
+        .inner_hash_length(17)
+        .last_node(true);
+    let mut original_states = [
+        State::new(),
+        State::new(),
+        State::new(),
+        State::new(),
+        wacky_params.to_state(),
+        wacky_params.to_state(),
+        wacky_params.to_state(),
+        wacky_params.to_state(),
+    ];
+    for (i, state) in original_states.iter_mut().enumerate() {
+        if i % 2 == 1 {
+            state.set_last_node(true);
+        }
+        if i % 4 >= 2 {
+            state.update(b"foobarbaz");
+        }
+    }
+
+    // Eight inputs of different lengths, including one that's much shorter than the rest.
+    let mut input = [0; 80 * BLOCKBYTES];
+    blake2bp::test::paint_input(&mut input);
+    let mut inputs: [&[u8]; 8] = [&[]; 8];
+    for (i, input_slice) in inputs.iter_mut().enumerate() {
+        let start = i * 10 * BLOCKBYTES;
+        let len = if i == 5 {
+            3 * BLOCKBYTES
+        } else {
+            9 * BLOCKBYTES + i
+        };
+        *input_slice = &input[start..][..len];
+    }
+
+    // Rotate the inputs through the states, so that each state gets each input once.
+    for rotation in 0..8 {
+        let mut states = original_states.clone();
+        let mut rotated_inputs = inputs;
+        rotated_inputs.rotate_left(rotation);
+        let mut expected = [blake2b(b""); 8];
+        for ((hash, state), input) in expected.iter_mut().zip(&states).zip(&rotated_inputs) {
+            *hash = state.clone().update(input).finalize();
+        }
+        let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+        update8([s0, s1, s2, s3, s4, s5, s6, s7], rotated_inputs);
+        let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+        assert_eq!(expected, finalize8([s0, s1, s2, s3, s4, s5, s6, s7]));
+        // Finalize is idempotent.
+        let [s0, s1, s2, s3, s4, s5, s6, s7] = &mut states;
+        assert_eq!(expected, finalize8([s0, s1, s2, s3, s4, s5, s6, s7]));
+    }
+}
+
+const BLAKE2S_EMPTY_HASH: &str = "69217a3079908094e11121d042354a7c1f55b6482ca1a51e1b250dfd1ed0eef9";
+const BLAKE2S_ABC_HASH: &str = "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982";
+const BLAKE2S_ONE_BLOCK_HASH: &str =
+    "ae09db7cd54f42b490ef09b6bc541af688e4959bb8c53f359a6f56e38ab454a3";
+const BLAKE2S_THOUSAND_HASH: &str =
+    "37e9dd47498579c5343fd282c13c62ea824cdfc9b0f4f747a41347414640f62c";
+
+#[test]
+fn test_blake2s_vectors() {
+    let io = &[
+        (&b""[..], BLAKE2S_EMPTY_HASH),
+        (&b"abc"[..], BLAKE2S_ABC_HASH),
+        (&[0; blake2s::BLOCKBYTES], BLAKE2S_ONE_BLOCK_HASH),
+        (&[0; 1000], BLAKE2S_THOUSAND_HASH),
+    ];
+    // Test each input all at once.
+    for &(input, output) in io {
+        let hash = blake2s::blake2s(input);
+        assert_eq!(&hash.to_hex(), output, "hash mismatch");
+    }
+    // Now in two chunks.
+    for &(input, output) in io {
+        let mut state = blake2s::State::new();
+        let split = input.len() / 2;
+        state.update(&input[..split]);
+        assert_eq!(split as u64, state.count());
+        state.update(&input[split..]);
+        assert_eq!(input.len() as u64, state.count());
+        let hash = state.finalize();
+        assert_eq!(&hash.to_hex(), output, "hash mismatch");
+    }
+    // Now one byte at a time.
+    for &(input, output) in io {
+        let mut state = blake2s::State::new();
+        let mut count = 0;
+        for &b in input {
+            state.update(&[b]);
+            count += 1;
+            assert_eq!(count, state.count());
+        }
+        let hash = state.finalize();
+        assert_eq!(&hash.to_hex(), output, "hash mismatch");
+    }
+}
+
+#[test]
+fn test_blake2s_multiple_finalizes() {
+    let mut state = blake2s::State::new();
+    assert_eq!(&state.finalize().to_hex(), BLAKE2S_EMPTY_HASH);
+    assert_eq!(&state.finalize().to_hex(), BLAKE2S_EMPTY_HASH);
+    state.update(b"abc");
+    assert_eq!(&state.finalize().to_hex(), BLAKE2S_ABC_HASH);
+    assert_eq!(&state.finalize().to_hex(), BLAKE2S_ABC_HASH);
+}
+
+// You can check this case against the equivalent Python:
+//
+// import hashlib
+// hashlib.blake2s(
+//     b'foo',
+//     digest_size=18,
+//     key=b"bar",
+//     salt=b"bazbazba",
+//     person=b"bing bin",
+//     fanout=2,
+//     depth=3,
+//     leaf_size=0x04050607,
+//     node_offset=0x08090a0b0c0d,
+//     node_depth=16,
+//     inner_size=17,
+//     last_node=True,
+// ).hexdigest()
+#[test]
+fn test_blake2s_all_parameters() {
+    let hash = blake2s::Params::new()
+        .hash_length(18)
+        // Make sure a shorter key properly overwrites a longer one.
+        .key(b"not the real key")
+        .key(b"bar")
+        .salt(b"bazbazba")
+        .personal(b"bing bin")
+        .fanout(2)
+        .max_depth(3)
+        .max_leaf_length(0x04050607)
+        .node_offset(0x08090a0b0c0d)
+        .node_depth(16)
+        .inner_hash_length(17)
+        .to_state()
+        .set_last_node(true)
+        .update(b"foo")
+        .finalize();
+    assert_eq!("62361e5392ab0eb7dd27e48a6809ee82dc57", &hash.to_hex());
+}
+
+#[test]
+fn test_blake2s_portable_agrees() {
+    let mut input = [0; 1000];
+    blake2bp::test::paint_input(&mut input);
+    let mut state = blake2s::State::new();
+    benchmarks::force_portable_blake2s(&mut state);
+    state.update(&input);
+    assert_eq!(blake2s::blake2s(&input), state.finalize());
+
+    let mut state = blake2sp::State::new();
+    benchmarks::force_portable_blake2sp(&mut state);
+    state.update(&input);
+    assert_eq!(blake2sp::blake2sp(&input), state.finalize());
+}
+
+#[test]
+#[should_panic]
+fn test_blake2s_long_hash_length_panics() {
+    blake2s::Params::new().hash_length(blake2s::OUTBYTES + 1);
+}
+
+#[test]
+#[should_panic]
+fn test_blake2s_long_key_panics() {
+    blake2s::Params::new().key(&[0; blake2s::KEYBYTES + 1]);
+}
+
+#[test]
+#[should_panic]
+fn test_blake2s_long_salt_panics() {
+    blake2s::Params::new().salt(&[0; blake2s::SALTBYTES + 1]);
+}
+
+#[test]
+#[should_panic]
+fn test_blake2s_long_personal_panics() {
+    blake2s::Params::new().personal(&[0; blake2s::PERSONALBYTES + 1]);
+}
+
+#[test]
+#[should_panic]
+fn test_blake2s_long_node_offset_panics() {
+    blake2s::Params::new().node_offset(1 << 48);
+}
+
+#[test]
+#[should_panic]
+fn test_blake2sp_long_key_panics() {
+    blake2sp::Params::new().key(&[0; blake2s::KEYBYTES + 1]);
+}
+
+#[test]
+fn test_hash_many() {
+    let mut input = [0; 40 * BLOCKBYTES];
+    blake2bp::test::paint_input(&mut input);
+    // A mix of lengths, so that lanes finish at different times and get refilled, including empty
+    // inputs and inputs right at block boundaries.
+    let lengths = [
+        0,
+        1,
+        BLOCKBYTES - 1,
+        BLOCKBYTES,
+        BLOCKBYTES + 1,
+        10 * BLOCKBYTES,
+        0,
+        3 * BLOCKBYTES + 7,
+        40 * BLOCKBYTES,
+        2 * BLOCKBYTES,
+        5,
+        BLOCKBYTES,
+        17 * BLOCKBYTES - 1,
+        0,
+        1,
+        4 * BLOCKBYTES,
+        9 * BLOCKBYTES + 100,
+    ];
+    let mut inputs: [&[u8]; 17] = [&[]; 17];
+    for (i, (input_slice, &len)) in inputs.iter_mut().zip(lengths.iter()).enumerate() {
+        // Offset each input a little, so they're all different.
+        *input_slice = &input[i..][..len - cmp::min(len, i)];
+    }
+
+    let mut keyed_params = Params::new();
+    keyed_params
+        .hash_length(20)
+        .key(b"foo")
+        .personal(b"bar")
+        .last_node(true);
+    for params in &[Params::new(), keyed_params] {
+        // Try every number of inputs, to cover different numbers of leftover lanes.
+        for num_inputs in 0..=inputs.len() {
+            let inputs = &inputs[..num_inputs];
+            let mut outputs = [blake2b(b""); 17];
+            hash_many_into(params, inputs, &mut outputs[..num_inputs]);
+            for (input, output) in inputs.iter().zip(outputs.iter()) {
+                assert_eq!(&params.to_state().update(input).finalize(), output);
+            }
+            #[cfg(feature = "std")]
+            assert_eq!(&outputs[..num_inputs], &hash_many(params, inputs)[..]);
+        }
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_hash_many_mismatched_outputs_panics() {
+    let mut outputs = [blake2b(b""); 1];
+    hash_many_into(&Params::new(), &[b"foo", b"bar"], &mut outputs);
+}
+
+#[test]
+fn test_exact() {
+    let mut input = [0; BLOCKBYTES + 3];
+    blake2bp::test::paint_input(&mut input);
+    let mut keyed_params = Params::new();
+    keyed_params
+        .hash_length(20)
+        .key(b"foo")
+        .salt(b"bar")
+        .last_node(true);
+    for params in &[Params::new(), keyed_params] {
+        for len in 0..=BLOCKBYTES {
+            let expected = params.to_state().update(&input[..len]).finalize();
+            assert_eq!(expected, blake2b_exact(params, &input[..len]));
+
+            // Vary the lengths of the other lanes too.
+            let hashes = hash4_exact(
+                params,
+                &input[..len],
+                &input[1..][..len],
+                &input[2..][..BLOCKBYTES - len],
+                &input[3..][..len / 2],
+            );
+            assert_eq!(expected, hashes[0]);
+            assert_eq!(
+                params.to_state().update(&input[1..][..len]).finalize(),
+                hashes[1]
+            );
+            assert_eq!(
+                params
+                    .to_state()
+                    .update(&input[2..][..BLOCKBYTES - len])
+                    .finalize(),
+                hashes[2]
+            );
+            assert_eq!(
+                params.to_state().update(&input[3..][..len / 2]).finalize(),
+                hashes[3]
+            );
+        }
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_exact_long_input_panics() {
+    blake2b_exact(&Params::new(), &[0; BLOCKBYTES + 1]);
+}
+
+#[test]
+#[should_panic]
+fn test_hash4_exact_long_input_panics() {
+    hash4_exact(&Params::new(), b"", b"", b"", &[0; BLOCKBYTES + 1]);
+}
+
+#[test]
+fn test_blake2b_long() {
+    // These come from a Python implementation of H' built on hashlib.
+    let io: &[(usize, &str)] = &[
+        (1, "79"),
+        (
+            32,
+            "6cfcbf5d43e547674bfbc009070570bcb84e272d359c1e9277e416d74cbbe1c6",
+        ),
+        (
+            64,
+            "f32577a3172f56657d531faaa43077bb8c9726ada7bb04dd337ec5a65454abff\
+              241ad6b87a72440e5127c6f9caa70327f2a699096e52d163eb52d9cd99620593",
+        ),
+        (
+            65,
+            "77baa447fe6f777c9bcb519545ce80badaf08ecb973fbb4ff45af7d8b569ec7c\
+              aad65f72669d05153cdeaa563a162bb5ae4c42214551593816008dc560c5a65391",
+        ),
+        (
+            100,
+            "4c9ba23bcafae5e571a5d41673bb8084a4a1de2688416ed390f669d33d364f3d\
+              4d9bfa7fe762680c6b2362711c4ce5b2c60ddcd14c1277ec1369c79f44c28966\
+              98a2b0773a3ce2e410532fa7c72f0bb61ccca0c24c362f337555cbf2998f2d36\
+              01be70d1",
+        ),
+    ];
+    for &(len, expected) in io {
+        let mut output = [0; 100];
+        blake2b_long(&mut output[..len], &[b"a", b"", b"bc"]);
+        assert_eq!(expected, hex::encode(&output[..len]));
+
+        let mut state = LongState::new(len);
+        state.update(b"ab").update(b"c");
+        assert_eq!(len, state.output_length());
+        let mut output = [0; 100];
+        state.finalize(&mut output[..len]);
+        assert_eq!(expected, hex::encode(&output[..len]));
+    }
+
+    let mut output = [0; 1024];
+    blake2b_long(&mut output, &[b"abc"]);
+    assert_eq!(
+        "74e1e21a31d8062c02e7ff282659d9361f74761d6b97a3f1b549795df7b4d21c\
+         60596bacec333f4916ac60262d09b5f691d81e2f701449bb63522b67740f025c",
+        &*blake2b(&output).to_hex()
+    );
+}
+
+#[test]
+fn test_blake2b_long_rfc_9106() {
+    // The Argon2d test vector in section 5.1 of RFC 9106 prints the pre-hashing digest H0, and the
+    // first words of block 0 in lane 0 after the first pass. That block is H'^1024(H0 || 0 || 0).
+    let h0 = "b8819791a0359660bb7709c85fa48f04d5d82c05c5f215ccdb885491717cf757\
+              082c28b951be381410b5fc2eb7274033b9fdc7ae672bcaac5d179097a4af3109";
+    let h0_bytes = hex::decode(h0).unwrap();
+    let mut block = [0; 1024];
+    blake2b_long(&mut block, &[&h0_bytes, &[0; 4], &[0; 4]]);
+    let expected_words: [u64; 4] = [
+        0xdb2fea6b2c6f5c8a,
+        0x719413be00f82634,
+        0xa1e3f6dd42aa25cc,
+        0x3ea8efd4d55ac0d1,
+    ];
+    for (i, &expected) in expected_words.iter().enumerate() {
+        assert_eq!(expected, LittleEndian::read_u64(&block[8 * i..]));
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_blake2b_long_empty_output_panics() {
+    blake2b_long(&mut [], &[b"foo"]);
+}
+
+#[test]
+#[should_panic]
+fn test_long_state_wrong_output_length_panics() {
+    LongState::new(100).finalize(&mut [0; 99]);
+}
+
+// The input format of the EIP-152 precompile: a 4-byte big-endian round count, the 8 state words,
+// the 16 message words, and the 2 offset words, all little-endian, and a 1-byte final block flag.
+fn check_eip_152(input: &str, expected: &str) {
+    let input = hex::decode(input).unwrap();
+    assert_eq!(213, input.len());
+    let rounds = byteorder::BigEndian::read_u32(&input[..4]);
+    let mut state_words = [0; 8];
+    LittleEndian::read_u64_into(&input[4..68], &mut state_words);
+    let block = array_ref!(input, 68, BLOCKBYTES);
+    let offset_low = LittleEndian::read_u64(&input[196..204]);
+    let offset_high = LittleEndian::read_u64(&input[204..212]);
+    let offset = offset_low as u128 + ((offset_high as u128) << 64);
+    let final_block = match input[212] {
+        0 => false,
+        1 => true,
+        _ => panic!("bad final block flag"),
+    };
+    compress_rounds(&mut state_words, block, offset, final_block, rounds);
+    let mut output = [0; 64];
+    LittleEndian::write_u64_into(&state_words, &mut output);
+    assert_eq!(expected, hex::encode(&output[..]));
+}
+
+// The valid inputs from the EIP-152 test vectors, which hash "abc". The 12-round final block gives
+// the regular BLAKE2b hash.
+#[test]
+fn test_compress_rounds_eip_152() {
+    let vectors = [
+        (
+            "0000000048c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000001",
+            "08c9bcf367e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d282e6ad7f520e511f6c3e2b8c68059b9442be0454267ce079217e1319cde05b",
+        ),
+        (
+            "0000000c48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000001",
+            ABC_HASH,
+        ),
+        (
+            "0000000c48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000000",
+            "75ab69d3190a562c51aef8d88f1c2775876944407270c42c9844252c26d2875298743e7f6d5ea2f2d3e8d226039cd31b4e426ac4f2d3d666a610c2116fde4735",
+        ),
+        (
+            "0000000148c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000001",
+            "b63a380cb2897d521994a85234ee2c181b5f844d2c624c002677e9703449d2fba551b3a8333bcdf5f2f7e08993d53923de3d64fcc68c034e717b9293fed7a421",
+        ),
+    ];
+    for &(input, expected) in &vectors {
+        check_eip_152(input, expected);
+    }
+}
+
+// The last EIP-152 test vector has 2^32 - 1 rounds, which takes about a minute even in release
+// mode, so it only runs with `cargo test --release -- --ignored`.
+#[test]
+#[ignore]
+fn test_compress_rounds_eip_152_max_rounds() {
+    let input = "ffffffff48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b61626300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000001";
+    let expected = "fc59093aafa9ab43daae0e914c57635c5402d8e3d2130eb9b3cc181de7f0ecf9b22bf99a7815ce16419e200e01846e6b5df8cc7703041bbceb571de6631d2615";
+    check_eip_152(input, expected);
+}
+
+#[test]
+fn test_save_restore() {
+    let mut input = [0; 1000];
+    for (i, byte) in input.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let mut keyed_params = Params::new();
+    keyed_params.hash_length(32).key(b"foo").last_node(true);
+    // The key block counts as input.
+    for &(params, key_bytes) in &[(&Params::new(), 0), (&keyed_params, BLOCKBYTES)] {
+        let expected = params.to_state().update(&input).finalize();
+        for &split in &[0, 1, 127, 128, 129, 256, 999, 1000] {
+            let mut state = params.to_state();
+            state.update(&input[..split]);
+            let saved = state.save();
+            let mut restored = State::restore(&saved).unwrap();
+            assert_eq!((key_bytes + split) as u128, restored.count());
+            assert_eq!(state.finalize(), restored.finalize());
+            // The format doesn't depend on the implementation.
+            restored.implementation = guts::Implementation::portable();
+            restored.update(&input[split..]);
+            assert_eq!(expected, restored.finalize());
+        }
+    }
+}
+
+#[test]
+fn test_saved_state_format() {
+    let mut expected = [0; SAVED_STATE_BYTES];
+    expected[0] = 1; // version
+    expected[1] = b'b'; // BLAKE2b
+    LittleEndian::write_u64_into(&Params::new().to_state_words(), &mut expected[2..66]);
+    expected[66] = 3; // count, 16 bytes
+    expected[82] = 3; // buflen
+    expected[83] = 0; // last_node
+    expected[84] = 64; // hash_length
+    expected[85..88].copy_from_slice(b"abc"); // buf, 128 bytes
+    assert_eq!(&expected[..], &State::new().update(b"abc").save()[..]);
+}
+
+#[test]
+fn test_restore_errors() {
+    let saved = State::new().update(b"abc").save();
+    assert_eq!(
+        Err(RestoreError::WrongLength),
+        State::restore(&saved[1..]).map(|_| ())
+    );
+    let blake2bp_saved = blake2bp::State::new().save();
+    assert_eq!(
+        Err(RestoreError::WrongLength),
+        State::restore(&blake2bp_saved).map(|_| ())
+    );
+    let mut bad = saved;
+    bad[0] = 2;
+    assert_eq!(
+        Err(RestoreError::UnknownFormat),
+        State::restore(&bad).map(|_| ())
+    );
+    bad = saved;
+    bad[1] = b'p';
+    assert_eq!(
+        Err(RestoreError::UnknownFormat),
+        State::restore(&bad).map(|_| ())
+    );
+    // buflen, last_node, hash_length, and a count that doesn't match buflen
+    for &(index, value) in &[
+        (82, 129),
+        (82, 0),
+        (82, 4),
+        (83, 2),
+        (84, 0),
+        (84, 65),
+        (66, 130),
+    ] {
+        bad = saved;
+        bad[index] = value;
+        assert_eq!(
+            Err(RestoreError::InvalidState),
+            State::restore(&bad).map(|_| ()),
+            "index {} value {}",
+            index,
+            value,
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let mut state = Params::new().key(b"foo").to_state();
+    state.update(b"bar");
+    let json = serde_json::to_string(&state).unwrap();
+    let mut restored: State = serde_json::from_str(&json).unwrap();
+    assert_eq!(state.finalize(), restored.finalize());
+
+    let mut state = blake2bp::Params::new().key(b"foo").to_state();
+    state.update(b"bar");
+    let json = serde_json::to_string(&state).unwrap();
+    let mut restored: blake2bp::State = serde_json::from_str(&json).unwrap();
+    assert_eq!(state.finalize(), restored.finalize());
+
+    // The wrong length fails.
+    assert!(serde_json::from_str::<State>("[1, 98, 0]").is_err());
+    assert!(serde_json::from_str::<State>(&serde_json::to_string(&state).unwrap()).is_err());
+}
+
+#[test]
+fn test_hash_from_bytes_and_hex() {
+    let input = [0xab; OUTBYTES + 1];
+    for len in 1..=OUTBYTES {
+        let hash = Hash::from_bytes(&input[..len]).unwrap();
+        assert_eq!(&input[..len], hash.as_bytes());
+        assert_eq!(hash, Hash::from_hex(&hash.to_hex()).unwrap());
+        assert_eq!(hash, hash.to_hex().parse::<Hash>().unwrap());
+    }
+    assert_eq!(Err(ParseHashError::InvalidLength), Hash::from_bytes(&[]));
+    assert_eq!(Err(ParseHashError::InvalidLength), Hash::from_bytes(&input));
+
+    let hash = blake2b(b"abc");
+    assert_eq!(hash, Hash::from_hex(ABC_HASH).unwrap());
+    let mut upper = hash.to_hex();
+    upper.make_ascii_uppercase();
+    assert_eq!(hash, Hash::from_hex(&upper).unwrap());
+    // Lengths are checked before characters.
+    for &(hex, error) in &[
+        ("", ParseHashError::InvalidLength),
+        ("abc", ParseHashError::InvalidLength),
+        (&ABC_HASH[1..], ParseHashError::InvalidLength),
+        ("0g", ParseHashError::InvalidEncoding),
+        (" 0", ParseHashError::InvalidEncoding),
+        ("+1", ParseHashError::InvalidEncoding),
+    ] {
+        assert_eq!(Err(error), Hash::from_hex(hex), "{:?}", hex);
+    }
+    let too_long = [b'0'; 2 * OUTBYTES + 2];
+    assert_eq!(
+        Err(ParseHashError::InvalidLength),
+        Hash::from_hex(core::str::from_utf8(&too_long).unwrap())
+    );
+}
+
+#[test]
+fn test_hash_display() {
+    use core::fmt::Write;
+    let hash = blake2b(b"abc");
+    let mut s = arrayvec::ArrayString::<[u8; 2 * OUTBYTES]>::new();
+    write!(s, "{}", hash).unwrap();
+    assert_eq!(ABC_HASH, &*s);
+}
+
+#[test]
+fn test_hash_base_encodings() {
+    // Computed with Python's base64 module, stripping the padding where it isn't used.
+    let vectors = &[
+        (
+            blake2b(b"abc"),
+            "uoClP5gcTQ1qJ5e2nxL26UwhLxRoWsS3SxK7b9v/otF9h8U5Kqt5LcJS1d5FM8yVGNOKqNvxklq5I4bt1ACZIw==",
+            "uoClP5gcTQ1qJ5e2nxL26UwhLxRoWsS3SxK7b9v_otF9h8U5Kqt5LcJS1d5FM8yVGNOKqNvxklq5I4bt1ACZIw",
+            "xkakkp4ydrgq22rhs63j6exw5fgcclyunbnmjn2lck5w7w77ulix3b6fhevkw6jnyjjnlxsfgpgjkggtrkunx4mslk4shbxn2qajsiy",
+        ),
+        (
+            Params::new().hash_length(32).to_state().update(b"abc").finalize(),
+            "vd2BPGNCOXIxce8/7phXm5SWTjuxyz5CcmLIwGjVIxk=",
+            "vd2BPGNCOXIxce8_7phXm5SWTjuxyz5CcmLIwGjVIxk",
+            "xxoycpddii4xemlr54765gcxtokjmtr3whft4qtsmlema2gvemmq",
+        ),
+        (Params::new().hash_length(2).to_state().update(b"abc").finalize(), "rh4=", "rh4", "vypa"),
+        (Params::new().hash_length(1).to_state().update(b"abc").finalize(), "aw==", "aw", "nm"),
+    ];
+    for &(hash, base64, base64url, base32) in vectors {
+        assert_eq!(base64, &*hash.to_base64());
+        assert_eq!(base64url, &*hash.to_base64url());
+        assert_eq!(base32, &*hash.to_base32());
+        assert_eq!(hash, Hash::from_base64(base64).unwrap());
+        assert_eq!(hash, Hash::from_base64url(base64url).unwrap());
+        assert_eq!(hash, Hash::from_base32(base32).unwrap());
+        let mut upper = hash.to_base32();
+        upper.make_ascii_uppercase();
+        assert_eq!(hash, Hash::from_base32(&upper).unwrap());
+    }
+
+    // Every length round trips.
+    let input = [0xff; OUTBYTES];
+    for len in 1..=OUTBYTES {
+        let hash = Hash::from_bytes(&input[..len]).unwrap();
+        assert_eq!(hash, Hash::from_base64(&hash.to_base64()).unwrap());
+        assert_eq!(hash, Hash::from_base64url(&hash.to_base64url()).unwrap());
+        assert_eq!(hash, Hash::from_base32(&hash.to_base32()).unwrap());
+    }
+
+    // Empty and too long.
+    assert_eq!(Err(ParseHashError::InvalidLength), Hash::from_base64(""));
+    assert_eq!(Err(ParseHashError::InvalidLength), Hash::from_base32("a"));
+    let long = Hash::from_bytes(&input).unwrap().to_base64url();
+    let mut too_long = arrayvec::ArrayString::<[u8; 2 * OUTBYTES]>::new();
+    too_long.push_str(&long);
+    too_long.push_str("AA");
+    assert_eq!(
+        Err(ParseHashError::InvalidLength),
+        Hash::from_base64url(&too_long)
+    );
+    // Bad characters, missing or unexpected padding, and nonzero trailing bits.
+    for &bad in &["rh4", "rh4==", "rh5=", "r h4=", "rh_="] {
+        assert_eq!(
+            Err(ParseHashError::InvalidEncoding),
+            Hash::from_base64(bad),
+            "{:?}",
+            bad
+        );
+    }
+    for &bad in &["rh4=", "rh5", "rh+"] {
+        assert_eq!(
+            Err(ParseHashError::InvalidEncoding),
+            Hash::from_base64url(bad),
+            "{:?}",
+            bad
+        );
+    }
+    for &bad in &["vypa====", "vypb", "vyp1"] {
+        assert_eq!(
+            Err(ParseHashError::InvalidEncoding),
+            Hash::from_base32(bad),
+            "{:?}",
+            bad
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_hash_serde() {
+    let hash = blake2b(b"abc");
+    let json = serde_json::to_string(&hash).unwrap();
+    assert_eq!(serde_json::to_string(ABC_HASH).unwrap(), json);
+    assert_eq!(hash, serde_json::from_str::<Hash>(&json).unwrap());
+    assert!(serde_json::from_str::<Hash>("\"abc\"").is_err());
+    assert!(serde_json::from_str::<Hash>("[1, 2, 3]").is_err());
+}
@@ -0,0 +1,478 @@
+//! Incremental tree hashing, as described in section 2.10 of [the BLAKE2 spec].
+//!
+//! [`TreeState`] takes the tree shape from the `fanout`, `max_depth`, `max_leaf_length`, and
+//! `inner_hash_length` fields of a BLAKE2b [`Params`], and takes care of all the per-node
+//! bookkeeping: splitting the input into leaves, assigning `node_offset` and `node_depth` to every
+//! node, setting `last_node` on the rightmost node of each level, and deciding which node is the
+//! root. The `hash_length`, key, salt, and personalization apply to every node, except that
+//! non-root nodes use `inner_hash_length` as their hash length. Any `node_offset`, `node_depth`,
+//! or `last_node` set on the `Params` is ignored.
+//!
+//! The shape of the tree follows these rules:
+//!
+//! - Each leaf covers `max_leaf_length` bytes of input, except the last one, which may be
+//!   shorter. A leaf length of 0 means unlimited, so all the input goes into a single leaf.
+//! - Each parent node covers up to `fanout` children. A fanout of 0 means unlimited.
+//! - Nodes at depth `max_depth - 1` have unlimited fanout, so the tree never grows taller than
+//!   `max_depth`. That means the default parameters (`fanout` 1 and `max_depth` 1) describe a
+//!   single node, which is the same as regular sequential BLAKE2b. In that case the node doesn't
+//!   get the `last_node` flag, because it isn't part of a tree.
+//! - The root is the only node in the topmost level. If all the input fits in one leaf, the root
+//!   is that leaf.
+//!
+//! # Example
+//!
+//! This is the tree hashing example from the [Python `hashlib` docs], which builds the same tree
+//! by hand: two leaves and a root.
+//!
+//! ```
+//! use blake2b_simd::{tree::TreeState, Params};
+//!
+//! let mut params = Params::new();
+//! params
+//!     .hash_length(32)
+//!     .fanout(2)
+//!     .max_depth(2)
+//!     .max_leaf_length(4096)
+//!     .inner_hash_length(64);
+//! let hash = TreeState::new(&params).update(&[0; 6000]).finalize();
+//! assert_eq!(
+//!     "3ad2a9b37c6070e374c7a8c508fe20ca86b6ed54e286e93a0318e95e881db5aa",
+//!     &hash.to_hex(),
+//! );
+//! ```
+//!
+//! [the BLAKE2 spec]: https://blake2.net/blake2.pdf
+//! [`TreeState`]: struct.TreeState.html
+//! [`Params`]: ../struct.Params.html
+//! [Python `hashlib` docs]: https://docs.python.org/3/library/hashlib.html#tree-mode
+
+use crate::Hash;
+use crate::Params;
+use crate::State;
+use core::cmp;
+use core::fmt;
+
+use std;
+use std::vec::Vec;
+
+// One level of the tree, holding the rightmost node seen so far at that depth. Nodes to the left
+// of it have already been finalized and fed to the level above.
+#[derive(Clone)]
+struct Level {
+    // The non-root state of the rightmost node. This is None for levels with unlimited children,
+    // which never split. Their only node is always the root, so it only needs the root candidate,
+    // and the input doesn't get compressed twice.
+    node: Option<State>,
+    // While this level has only one node, that node might turn out to be the root. The root uses
+    // the final hash length rather than inner_hash_length, which changes its parameter block, so
+    // we feed the same input to a second state with the root parameters. This is dropped as soon
+    // as the level gets a second node.
+    root_candidate: Option<State>,
+    node_offset: u64,
+    // Input bytes for leaves, or child hashes for parent nodes.
+    children: u64,
+}
+
+/// An incremental tree hasher, driven by the tree parameters of a [`Params`] object. See the
+/// [module level docs](index.html) for the rules that determine the tree shape.
+///
+/// Like [`State`], `TreeState` buffers only what it needs. It holds at most one unfinished node
+/// per level of the tree.
+///
+/// [`Params`]: ../struct.Params.html
+/// [`State`]: ../struct.State.html
+#[derive(Clone)]
+pub struct TreeState {
+    params: Params,
+    levels: Vec<Level>,
+    count: u128,
+}
+
+impl TreeState {
+    /// Construct a new `TreeState` from the tree parameters in `params`. This panics if
+    /// `max_depth` is greater than 1 and `inner_hash_length` is 0, because non-root nodes can't
+    /// have an empty hash.
+    pub fn new(params: &Params) -> Self {
+        assert!(
+            params.max_depth == 1 || params.inner_hash_length > 0,
+            "Bad inner hash length for a tree: {}",
+            params.inner_hash_length
+        );
+        let mut state = Self {
+            params: params.clone(),
+            levels: Vec::new(),
+            count: 0,
+        };
+        let first_leaf = state.new_level(0);
+        state.levels.push(first_leaf);
+        state
+    }
+
+    fn node_state(&self, depth: usize, node_offset: u64, is_root: bool) -> State {
+        let hash_length = if is_root {
+            self.params.hash_length
+        } else {
+            self.params.inner_hash_length
+        };
+        self.params
+            .clone()
+            .hash_length(hash_length as usize)
+            .node_offset(node_offset)
+            .node_depth(depth as u8)
+            .last_node(false)
+            .to_state()
+    }
+
+    fn new_level(&self, depth: usize) -> Level {
+        // With a max depth of 1 the only level is unlimited, so this never builds a non-root
+        // state, and an inner_hash_length of 0 is fine.
+        let node = self
+            .max_children(depth)
+            .map(|_| self.node_state(depth, 0, false));
+        Level {
+            node,
+            root_candidate: Some(self.node_state(depth, 0, true)),
+            node_offset: 0,
+            children: 0,
+        }
+    }
+
+    // Nodes at the top level allowed by max_depth have unlimited fanout. Below that, leaves are
+    // limited by max_leaf_length and parents by fanout, where 0 means unlimited in both cases.
+    fn max_children(&self, depth: usize) -> Option<u64> {
+        let limit = if depth + 1 >= self.params.max_depth as usize {
+            0
+        } else if depth == 0 {
+            self.params.max_leaf_length as u64
+        } else {
+            self.params.fanout as u64
+        };
+        if limit == 0 {
+            None
+        } else {
+            Some(limit)
+        }
+    }
+
+    // Finalize the current node at `depth` as a non-root node and feed its hash to the level
+    // above. This is only called once we know the node isn't the root.
+    fn finish_node(&mut self, depth: usize, last_node: bool) {
+        let hash = {
+            let node = self.levels[depth]
+                .node
+                .as_mut()
+                .expect("only limited levels have non-root nodes");
+            node.set_last_node(last_node);
+            node.finalize()
+        };
+        if self.levels.len() == depth + 1 {
+            let parent = self.new_level(depth + 1);
+            self.levels.push(parent);
+        }
+        self.push_child(depth + 1, hash.as_bytes());
+    }
+
+    // Start the next node at `depth`, after the current one has been finished.
+    fn next_node(&mut self, depth: usize) {
+        let node_offset = self.levels[depth].node_offset + 1;
+        let node = self.node_state(depth, node_offset, false);
+        let level = &mut self.levels[depth];
+        level.node = Some(node);
+        level.root_candidate = None;
+        level.node_offset = node_offset;
+        level.children = 0;
+    }
+
+    // Feed one child hash to the current node at `depth`, first finishing that node if it's full.
+    fn push_child(&mut self, depth: usize, child_hash: &[u8]) {
+        if Some(self.levels[depth].children) == self.max_children(depth) {
+            self.finish_node(depth, false);
+            self.next_node(depth);
+        }
+        let level = &mut self.levels[depth];
+        if let Some(ref mut node) = level.node {
+            node.update(child_hash);
+        }
+        if let Some(ref mut root_candidate) = level.root_candidate {
+            root_candidate.update(child_hash);
+        }
+        level.children += 1;
+    }
+
+    /// Add input to the hash. You can call `update` any number of times.
+    pub fn update(&mut self, mut input: &[u8]) -> &mut Self {
+        self.count += input.len() as u128;
+        while !input.is_empty() {
+            let max_leaf_length = self.max_children(0);
+            // A full leaf only gets finished once more input arrives, since until then it might
+            // be the last leaf, or even the root.
+            if Some(self.levels[0].children) == max_leaf_length {
+                self.finish_node(0, false);
+                self.next_node(0);
+            }
+            let leaf = &mut self.levels[0];
+            let take = match max_leaf_length {
+                Some(max) => cmp::min((max - leaf.children) as usize, input.len()),
+                None => input.len(),
+            };
+            if let Some(ref mut node) = leaf.node {
+                node.update(&input[..take]);
+            }
+            if let Some(ref mut root_candidate) = leaf.root_candidate {
+                root_candidate.update(&input[..take]);
+            }
+            leaf.children += take as u64;
+            input = &input[take..];
+        }
+        self
+    }
+
+    /// Finalize the tree and return the root `Hash`. This method is idempotent, and calling it
+    /// multiple times will give the same result. It's also possible to `update` with more input in
+    /// between.
+    pub fn finalize(&mut self) -> Hash {
+        // Finishing the rightmost nodes can add levels to the tree, so work on a copy.
+        let mut tree = self.clone();
+        let mut depth = 0;
+        loop {
+            // The root is the first node in the topmost level. Every level below it has more than
+            // one node, so only the topmost level still has a root candidate.
+            if depth + 1 == tree.levels.len() {
+                let level = &mut tree.levels[depth];
+                if let Some(ref mut root) = level.root_candidate {
+                    root.set_last_node(tree.params.max_depth > 1);
+                    return root.finalize();
+                }
+            }
+            tree.finish_node(depth, true);
+            depth += 1;
+        }
+    }
+
+    /// Return the total number of bytes input so far.
+    pub fn count(&self) -> u128 {
+        self.count
+    }
+}
+
+impl std::io::Write for TreeState {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TreeState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB: Don't print the node states. Their words would allow length extension.
+        write!(
+            f,
+            "TreeState {{ count: {}, levels: {}, params: {:?} }}",
+            self.count,
+            self.levels.len(),
+            self.params,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blake2bp::test::paint_input;
+
+    // A simple non-incremental implementation of the same rules, which builds the whole tree one
+    // level at a time.
+    fn tree_reference(params: &Params, input: &[u8]) -> Hash {
+        let node = |depth: usize, offset: usize, hash_length: u8, last: bool, input: &[u8]| {
+            params
+                .clone()
+                .hash_length(hash_length as usize)
+                .node_offset(offset as u64)
+                .node_depth(depth as u8)
+                .last_node(last)
+                .to_state()
+                .update(input)
+                .finalize()
+        };
+        let leaf_length = params.max_leaf_length as usize;
+        let mut level: Vec<Vec<u8>> = if params.max_depth == 1 || leaf_length == 0 {
+            vec![input.to_vec()]
+        } else if input.is_empty() {
+            vec![Vec::new()]
+        } else {
+            input.chunks(leaf_length).map(|c| c.to_vec()).collect()
+        };
+        let mut depth = 0;
+        loop {
+            if level.len() == 1 {
+                let last = params.max_depth > 1;
+                return node(depth, 0, params.hash_length, last, &level[0]);
+            }
+            let hashes: Vec<Hash> = level
+                .iter()
+                .enumerate()
+                .map(|(i, n)| node(depth, i, params.inner_hash_length, i == level.len() - 1, n))
+                .collect();
+            depth += 1;
+            let fanout = if params.fanout == 0 || depth + 1 >= params.max_depth as usize {
+                hashes.len()
+            } else {
+                params.fanout as usize
+            };
+            level = hashes
+                .chunks(fanout)
+                .map(|group| group.iter().flat_map(|h| h.as_bytes().to_vec()).collect())
+                .collect();
+        }
+    }
+
+    #[test]
+    fn test_python_example() {
+        let mut params = Params::new();
+        params
+            .hash_length(32)
+            .fanout(2)
+            .max_depth(2)
+            .max_leaf_length(4096)
+            .inner_hash_length(64);
+        let expected = "3ad2a9b37c6070e374c7a8c508fe20ca86b6ed54e286e93a0318e95e881db5aa";
+        let input = [0; 6000];
+        assert_eq!(expected, &tree_reference(&params, &input).to_hex());
+        assert_eq!(
+            expected,
+            &TreeState::new(&params).update(&input).finalize().to_hex()
+        );
+    }
+
+    // You can check this case against the equivalent Python, building the tree by hand as in the
+    // hashlib docs. The 100-byte input has three leaves and two parents at depth 1, under a root
+    // at depth 2:
+    //
+    // import hashlib
+    // p = dict(fanout=2, depth=3, leaf_size=40, inner_size=20, key=b"key")
+    // data = bytes(range(100))
+    // leaf = lambda i, last: hashlib.blake2b(data[40*i:40*i+40], digest_size=20,
+    //     node_offset=i, node_depth=0, last_node=last, **p).digest()
+    // p1 = hashlib.blake2b(leaf(0, False) + leaf(1, False), digest_size=20, node_offset=0,
+    //     node_depth=1, **p).digest()
+    // p2 = hashlib.blake2b(leaf(2, True), digest_size=20, node_offset=1, node_depth=1,
+    //     last_node=True, **p).digest()
+    // hashlib.blake2b(p1 + p2, digest_size=32, node_depth=2, last_node=True, **p).hexdigest()
+    #[test]
+    fn test_three_levels() {
+        let mut params = Params::new();
+        params
+            .hash_length(32)
+            .key(b"key")
+            .fanout(2)
+            .max_depth(3)
+            .max_leaf_length(40)
+            .inner_hash_length(20);
+        let mut input = [0; 100];
+        for (i, b) in input.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let expected = "b466456064534cdfb25bf65ea86f4ba5f9cdaef967892fae9bce99fd4eefe2aa";
+        assert_eq!(expected, &tree_reference(&params, &input).to_hex());
+        assert_eq!(
+            expected,
+            &TreeState::new(&params).update(&input).finalize().to_hex()
+        );
+    }
+
+    #[test]
+    fn test_sequential_mode() {
+        // The default parameters describe a single node, which is just BLAKE2b.
+        let input = b"foo";
+        let params = Params::new();
+        assert_eq!(
+            crate::blake2b(input),
+            TreeState::new(&params).update(input).finalize()
+        );
+    }
+
+    #[test]
+    fn test_against_reference() {
+        let mut input = [0; 2000];
+        paint_input(&mut input);
+        // (fanout, max_depth, max_leaf_length) triples, covering unlimited fanout, sequential
+        // fanout, a depth limit that gets hit, and an unlimited leaf length.
+        let shapes = [
+            (2, 2, 64),
+            (2, 3, 64),
+            (3, 4, 16),
+            (0, 5, 100),
+            (1, 3, 128),
+            (1, 255, 256),
+            (4, 255, 7),
+            (2, 1, 64),
+            (2, 4, 0),
+        ];
+        for &(fanout, max_depth, max_leaf_length) in &shapes {
+            let mut params = Params::new();
+            params
+                .hash_length(40)
+                .fanout(fanout)
+                .max_depth(max_depth)
+                .max_leaf_length(max_leaf_length)
+                .inner_hash_length(24);
+            for &len in &[0, 1, 7, 16, 63, 64, 65, 128, 129, 1000, 2000] {
+                let expected = tree_reference(&params, &input[..len]);
+
+                // All at once.
+                let mut tree = TreeState::new(&params);
+                assert_eq!(expected, tree.update(&input[..len]).finalize());
+
+                // In uneven pieces, with a throwaway finalize in the middle.
+                let mut tree = TreeState::new(&params);
+                for (i, chunk) in input[..len].chunks(13).enumerate() {
+                    tree.update(chunk);
+                    if i == 3 {
+                        tree.finalize();
+                    }
+                }
+                assert_eq!(len as u128, tree.count());
+                assert_eq!(expected, tree.finalize());
+                assert_eq!(expected, tree.finalize());
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_leaf_one_pass() {
+        // When the leaf level can never split, the input only goes into the root candidate.
+        let mut input = [0; 1000];
+        paint_input(&mut input);
+        let mut unlimited_leaf = Params::new();
+        unlimited_leaf
+            .fanout(2)
+            .max_depth(3)
+            .max_leaf_length(0)
+            .inner_hash_length(32);
+        for params in &[Params::new(), unlimited_leaf] {
+            let mut tree = TreeState::new(params);
+            tree.update(&input);
+            assert_eq!(1, tree.levels.len());
+            let leaf = &tree.levels[0];
+            assert!(leaf.node.is_none());
+            assert_eq!(
+                input.len() as u128,
+                leaf.root_candidate.as_ref().unwrap().count()
+            );
+            assert_eq!(tree_reference(params, &input), tree.finalize());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_inner_hash_length_panics() {
+        let mut params = Params::new();
+        params.max_depth(2);
+        TreeState::new(&params);
+    }
+}
@@ -0,0 +1,875 @@
+//! Verified streaming, in the style of [Bao](https://github.com/oconnor663/bao).
+//!
+//! The [`encode`] function splits the input into 4096-byte chunks, hashes them into a binary tree,
+//! and lays out the parent nodes of that tree in front of the chunk data they cover. It takes the
+//! whole input as a slice, and [`encode_to`] produces the same encoding from a file or another
+//! seekable reader, holding only one chunk in memory at a time. The
+//! [`Decoder`] reads that encoding and checks each parent node and each chunk against the trusted
+//! root hash before it returns any of the bytes, so a reader can consume a large file as it
+//! downloads without trusting the source, and without buffering more than one chunk. Corrupt input
+//! is reported as an `io::Error` of kind `InvalidData` at the first chunk that fails to verify.
+//! With a seekable encoding, the decoder can also seek, and it only reads the parent nodes on the
+//! path to the new position. Finally, [`extract_slice`] pulls out just the parts of an encoding
+//! needed to verify one range of the content, which [`SliceDecoder`] then decodes.
+//!
+//! Note that this format is not compatible with the `bao` crate.
+//!
+//! # Format
+//!
+//! The tree has a chunk at each leaf. The left subtree of every parent holds the largest power of
+//! 2 number of chunks that's less than the total for that parent, and the right subtree holds the
+//! rest. An empty input is a single empty chunk. Each node is hashed with BLAKE2b, with a hash
+//! length of 32, a fanout of 2, unlimited depth, a leaf length of 4096, and an inner hash length of
+//! 32. Chunks have a `node_depth` of 0, and parents have the height of their subtree. The
+//! `node_offset` of every node is the index of its first chunk. The root node has `last_node` set,
+//! and it appends the content length as 8 little-endian bytes to its input, so the root hash
+//! commits to the length.
+//!
+//! A parent node is the concatenation of its left and right child hashes. The encoding is an 8-byte
+//! little-endian content length, followed by the tree in pre-order: each parent node, then its left
+//! subtree, then its right subtree. Chunks appear in order.
+//!
+//! # Example
+//!
+//! ```
+//! use blake2b_simd::verified;
+//! use std::io::prelude::*;
+//!
+//! let input = vec![0xab; 10_000];
+//! let (hash, encoded) = verified::encode(&input);
+//! assert_eq!(verified::hash(&input), hash);
+//!
+//! let mut decoded = Vec::new();
+//! let mut decoder = verified::Decoder::new(&encoded[..], &hash);
+//! decoder.read_to_end(&mut decoded)?;
+//! assert_eq!(input, decoded);
+//!
+//! // Corrupting any byte of the encoding makes decoding fail.
+//! let mut corrupt = encoded.clone();
+//! corrupt[9000] ^= 1;
+//! let mut decoder = verified::Decoder::new(&corrupt[..], &hash);
+//! assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! [`encode`]: fn.encode.html
+//! [`encode_to`]: fn.encode_to.html
+//! [`Decoder`]: struct.Decoder.html
+//! [`extract_slice`]: fn.extract_slice.html
+//! [`SliceDecoder`]: struct.SliceDecoder.html
+
+use crate::Hash;
+use crate::Params;
+use crate::OUTBYTES;
+use byteorder::{ByteOrder, LittleEndian};
+use core::cmp;
+use core::fmt;
+
+use std;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::vec::Vec;
+
+/// The size of the chunks at the leaves of the tree.
+pub const CHUNK_SIZE: usize = 4096;
+
+/// The length of the root hash, and of each child hash in a parent node.
+pub const HASH_SIZE: usize = 32;
+
+/// The size of the content length header at the start of an encoding.
+pub const HEADER_SIZE: usize = 8;
+
+const PARENT_SIZE: usize = 2 * HASH_SIZE;
+
+fn node_params(depth: u8, first_chunk: u64, is_root: bool) -> Params {
+    let mut params = Params::new();
+    params
+        .hash_length(HASH_SIZE)
+        .fanout(2)
+        .max_depth(u8::MAX)
+        .max_leaf_length(CHUNK_SIZE as u32)
+        .inner_hash_length(HASH_SIZE)
+        .node_depth(depth)
+        .node_offset(first_chunk)
+        .last_node(is_root);
+    params
+}
+
+// Hash a chunk or a parent node. The root passes the content length, which gets appended.
+fn hash_node(input: &[u8], depth: u8, first_chunk: u64, root_len: Option<u64>) -> Hash {
+    let mut state = node_params(depth, first_chunk, root_len.is_some()).to_state();
+    state.update(input);
+    if let Some(len) = root_len {
+        let mut len_bytes = [0; 8];
+        LittleEndian::write_u64(&mut len_bytes, len);
+        state.update(&len_bytes);
+    }
+    state.finalize()
+}
+
+fn count_chunks(content_len: u64) -> u64 {
+    // An empty input is still one chunk.
+    if content_len == 0 {
+        1
+    } else {
+        (content_len - 1) / CHUNK_SIZE as u64 + 1
+    }
+}
+
+// The number of chunks in the left subtree of a parent with `chunks` chunks, at least 2.
+fn left_chunks(chunks: u64) -> u64 {
+    debug_assert!(chunks >= 2);
+    1 << (63 - (chunks - 1).leading_zeros())
+}
+
+// The number of levels of parents above the chunks of a subtree.
+fn height(chunks: u64) -> u8 {
+    (64 - (chunks - 1).leading_zeros()) as u8
+}
+
+/// The size of the encoding of `content_len` bytes of content.
+pub fn encoded_size(content_len: u64) -> u64 {
+    HEADER_SIZE as u64 + PARENT_SIZE as u64 * (count_chunks(content_len) - 1) + content_len
+}
+
+fn subtree_hash(input: &[u8], first_chunk: u64, chunks: u64, root_len: Option<u64>) -> Hash {
+    if chunks == 1 {
+        return hash_node(input, 0, first_chunk, root_len);
+    }
+    let left = left_chunks(chunks);
+    let (left_input, right_input) = input.split_at(left as usize * CHUNK_SIZE);
+    let mut parent = [0; PARENT_SIZE];
+    parent[..HASH_SIZE]
+        .copy_from_slice(subtree_hash(left_input, first_chunk, left, None).as_bytes());
+    parent[HASH_SIZE..].copy_from_slice(
+        subtree_hash(right_input, first_chunk + left, chunks - left, None).as_bytes(),
+    );
+    hash_node(&parent, height(chunks), first_chunk, root_len)
+}
+
+// Write the pre-order encoding of a subtree to `out` and return its hash.
+fn encode_subtree(
+    input: &[u8],
+    first_chunk: u64,
+    chunks: u64,
+    root_len: Option<u64>,
+    out: &mut Vec<u8>,
+) -> Hash {
+    if chunks == 1 {
+        out.extend_from_slice(input);
+        return hash_node(input, 0, first_chunk, root_len);
+    }
+    let left = left_chunks(chunks);
+    let (left_input, right_input) = input.split_at(left as usize * CHUNK_SIZE);
+    // Leave room for the parent node, and fill it in once we know the child hashes.
+    let parent_start = out.len();
+    out.extend_from_slice(&[0; PARENT_SIZE]);
+    let left_hash = encode_subtree(left_input, first_chunk, left, None, out);
+    let right_hash = encode_subtree(right_input, first_chunk + left, chunks - left, None, out);
+    let parent = &mut out[parent_start..][..PARENT_SIZE];
+    parent[..HASH_SIZE].copy_from_slice(left_hash.as_bytes());
+    parent[HASH_SIZE..].copy_from_slice(right_hash.as_bytes());
+    hash_node(parent, height(chunks), first_chunk, root_len)
+}
+
+/// Compute the root hash of `input`, without building the encoding.
+pub fn hash(input: &[u8]) -> Hash {
+    let len = input.len() as u64;
+    subtree_hash(input, 0, count_chunks(len), Some(len))
+}
+
+/// Encode `input`, returning the root hash and the encoding.
+pub fn encode(input: &[u8]) -> (Hash, Vec<u8>) {
+    let len = input.len() as u64;
+    let mut encoded = Vec::with_capacity(encoded_size(len) as usize);
+    let mut header = [0; HEADER_SIZE];
+    LittleEndian::write_u64(&mut header, len);
+    encoded.extend_from_slice(&header);
+    let hash = encode_subtree(input, 0, count_chunks(len), Some(len), &mut encoded);
+    (hash, encoded)
+}
+
+// The same as `encode_subtree`, but reading the input a chunk at a time and seeking back to fill
+// in each parent node.
+fn encode_subtree_to<R: Read, W: Write + Seek>(
+    input: &mut R,
+    output: &mut W,
+    buf: &mut [u8; CHUNK_SIZE],
+    content_len: u64,
+    first_chunk: u64,
+    chunks: u64,
+    root_len: Option<u64>,
+) -> io::Result<Hash> {
+    if chunks == 1 {
+        let start = first_chunk * CHUNK_SIZE as u64;
+        let len = cmp::min(CHUNK_SIZE as u64, content_len - start) as usize;
+        input.read_exact(&mut buf[..len])?;
+        output.write_all(&buf[..len])?;
+        return Ok(hash_node(&buf[..len], 0, first_chunk, root_len));
+    }
+    let left = left_chunks(chunks);
+    let parent_start = output.stream_position()?;
+    output.write_all(&[0; PARENT_SIZE])?;
+    let left_hash = encode_subtree_to(input, output, buf, content_len, first_chunk, left, None)?;
+    let right_hash = encode_subtree_to(
+        input,
+        output,
+        buf,
+        content_len,
+        first_chunk + left,
+        chunks - left,
+        None,
+    )?;
+    let mut parent = [0; PARENT_SIZE];
+    parent[..HASH_SIZE].copy_from_slice(left_hash.as_bytes());
+    parent[HASH_SIZE..].copy_from_slice(right_hash.as_bytes());
+    let subtree_end = output.stream_position()?;
+    output.seek(SeekFrom::Start(parent_start))?;
+    output.write_all(&parent)?;
+    output.seek(SeekFrom::Start(subtree_end))?;
+    Ok(hash_node(&parent, height(chunks), first_chunk, root_len))
+}
+
+/// Encode everything in `input` from its start, writing the encoding to `output` at its current
+/// position and returning the root hash.
+///
+/// This produces the same bytes as [`encode`], but it only holds one chunk of the input in memory
+/// at a time, so it works for files too large to buffer. The tree shape depends on the content
+/// length, which is why `input` has to be seekable. `output` has to be seekable because each
+/// parent node is written in front of its subtree, after the subtree has been hashed.
+///
+/// [`encode`]: fn.encode.html
+pub fn encode_to<R: Read + Seek, W: Write + Seek>(mut input: R, mut output: W) -> io::Result<Hash> {
+    let len = input.seek(SeekFrom::End(0))?;
+    input.seek(SeekFrom::Start(0))?;
+    let mut header = [0; HEADER_SIZE];
+    LittleEndian::write_u64(&mut header, len);
+    output.write_all(&header)?;
+    let mut buf = [0; CHUNK_SIZE];
+    encode_subtree_to(
+        &mut input,
+        &mut output,
+        &mut buf,
+        len,
+        0,
+        count_chunks(len),
+        Some(len),
+    )
+}
+
+// The first and last chunks needed to cover a slice of the content. A slice always includes at
+// least one chunk, even if it's empty or it starts past the end, because the decoder needs to
+// verify a chunk to trust the content length.
+fn slice_chunks(content_len: u64, slice_start: u64, slice_len: u64) -> (u64, u64) {
+    let last_chunk = count_chunks(content_len) - 1;
+    let first = cmp::min(slice_start / CHUNK_SIZE as u64, last_chunk);
+    let end = slice_start.saturating_add(slice_len);
+    let last = if slice_len == 0 {
+        first
+    } else {
+        cmp::max(first, cmp::min((end - 1) / CHUNK_SIZE as u64, last_chunk))
+    };
+    (first, last)
+}
+
+// A subtree that the decoder hasn't read yet, along with its expected hash.
+#[derive(Clone, Copy)]
+struct Subtree {
+    hash: Hash,
+    first_chunk: u64,
+    chunks: u64,
+    is_root: bool,
+}
+
+impl Subtree {
+    fn content_len(&self, total_len: u64) -> u64 {
+        let start = self.first_chunk * CHUNK_SIZE as u64;
+        cmp::min(self.chunks * CHUNK_SIZE as u64, total_len - start)
+    }
+
+    fn encoded_len(&self, total_len: u64) -> u64 {
+        PARENT_SIZE as u64 * (self.chunks - 1) + self.content_len(total_len)
+    }
+}
+
+fn child_hash(bytes: &[u8]) -> Hash {
+    let mut hash = Hash {
+        bytes: [0; OUTBYTES],
+        len: HASH_SIZE as u8,
+    };
+    hash.bytes[..HASH_SIZE].copy_from_slice(bytes);
+    hash
+}
+
+fn hash_mismatch() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "hash mismatch")
+}
+
+/// A reader that decodes and verifies an encoding produced by [`encode`], returning the original
+/// content.
+///
+/// Every chunk is verified before any of its bytes are returned. If a chunk or a parent node
+/// doesn't match, `read` returns an error of kind `InvalidData`. If the inner reader also
+/// implements `Seek`, so does the decoder, and the content position can be moved anywhere.
+///
+/// [`encode`]: fn.encode.html
+pub struct Decoder<R> {
+    inner: R,
+    root_hash: Hash,
+    content_len: Option<u64>,
+    // Subtrees still to be read, in reverse order, so that the next one is on top.
+    stack: Vec<Subtree>,
+    buf: [u8; CHUNK_SIZE],
+    buf_len: usize,
+    buf_chunk: Option<u64>,
+    position: u64,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Create a decoder for an encoding with the given root hash.
+    pub fn new(inner: R, root_hash: &Hash) -> Self {
+        Self {
+            inner,
+            root_hash: *root_hash,
+            content_len: None,
+            stack: Vec::new(),
+            buf: [0; CHUNK_SIZE],
+            buf_len: 0,
+            buf_chunk: None,
+            position: 0,
+        }
+    }
+
+    /// Return the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_header(&mut self) -> io::Result<u64> {
+        if let Some(len) = self.content_len {
+            return Ok(len);
+        }
+        let mut header = [0; HEADER_SIZE];
+        self.inner.read_exact(&mut header)?;
+        let len = LittleEndian::read_u64(&header);
+        // The length isn't trusted until the root node is verified, but that happens before any
+        // content is returned.
+        self.content_len = Some(len);
+        self.reset_to_root();
+        Ok(len)
+    }
+
+    fn reset_to_root(&mut self) {
+        self.stack.clear();
+        self.stack.push(Subtree {
+            hash: self.root_hash,
+            first_chunk: 0,
+            chunks: count_chunks(self.content_len.unwrap()),
+            is_root: true,
+        });
+        self.buf_chunk = None;
+    }
+
+    // Walk down the tree until the chunk at `target_chunk` is verified and in the buffer.
+    // Subtrees that end before the target are passed to `skip`, with their encoded length.
+    fn load_chunk<F>(&mut self, target_chunk: u64, mut skip: F) -> io::Result<()>
+    where
+        F: FnMut(&mut R, u64) -> io::Result<()>,
+    {
+        let content_len = self.content_len.unwrap();
+        let root_len = |subtree: &Subtree| {
+            if subtree.is_root {
+                Some(content_len)
+            } else {
+                None
+            }
+        };
+        while let Some(subtree) = self.stack.pop() {
+            if subtree.first_chunk + subtree.chunks <= target_chunk {
+                skip(&mut self.inner, subtree.encoded_len(content_len))?;
+                continue;
+            }
+            if subtree.chunks == 1 {
+                let len = subtree.content_len(content_len) as usize;
+                self.inner.read_exact(&mut self.buf[..len])?;
+                let found = hash_node(&self.buf[..len], 0, subtree.first_chunk, root_len(&subtree));
+                if found != subtree.hash {
+                    return Err(hash_mismatch());
+                }
+                self.buf_len = len;
+                self.buf_chunk = Some(subtree.first_chunk);
+                return Ok(());
+            }
+            let mut parent = [0; PARENT_SIZE];
+            self.inner.read_exact(&mut parent)?;
+            let found = hash_node(
+                &parent,
+                height(subtree.chunks),
+                subtree.first_chunk,
+                root_len(&subtree),
+            );
+            if found != subtree.hash {
+                return Err(hash_mismatch());
+            }
+            let left = left_chunks(subtree.chunks);
+            self.stack.push(Subtree {
+                hash: child_hash(&parent[HASH_SIZE..]),
+                first_chunk: subtree.first_chunk + left,
+                chunks: subtree.chunks - left,
+                is_root: false,
+            });
+            self.stack.push(Subtree {
+                hash: child_hash(&parent[..HASH_SIZE]),
+                first_chunk: subtree.first_chunk,
+                chunks: left,
+                is_root: false,
+            });
+        }
+        // The stack only runs out if we're asked for a chunk we've already passed.
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "chunk is behind the decoder",
+        ))
+    }
+
+    // Copy verified content from the buffer, loading the next chunk first if needed.
+    fn read_inner<F>(&mut self, buf: &mut [u8], end: u64, skip: F) -> io::Result<usize>
+    where
+        F: FnMut(&mut R, u64) -> io::Result<()>,
+    {
+        let content_len = self.read_header()?;
+        if self.position >= end && self.buf_chunk.is_some() {
+            return Ok(0);
+        }
+        let target_chunk = cmp::min(
+            self.position / CHUNK_SIZE as u64,
+            count_chunks(content_len) - 1,
+        );
+        if self.buf_chunk != Some(target_chunk) {
+            self.load_chunk(target_chunk, skip)?;
+        }
+        let chunk_start = target_chunk * CHUNK_SIZE as u64;
+        let chunk_end = cmp::min(chunk_start + self.buf_len as u64, end);
+        if self.position >= chunk_end {
+            return Ok(0);
+        }
+        let offset = (self.position - chunk_start) as usize;
+        let take = cmp::min(buf.len(), (chunk_end - self.position) as usize);
+        buf[..take].copy_from_slice(&self.buf[offset..][..take]);
+        self.position += take as u64;
+        Ok(take)
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Reading is sequential, so the next subtree on the stack always holds the next chunk, and
+        // nothing gets skipped. But discarding is correct in any case.
+        self.read_inner(buf, u64::MAX, |inner, len| {
+            let copied = io::copy(&mut inner.take(len), &mut io::sink())?;
+            if copied < len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<R: Read + Seek> Seek for Decoder<R> {
+    /// Seek to a position in the content. Seeking reads and verifies the parent nodes on the path
+    /// to the chunk at the new position, and that chunk itself, so it can fail with a hash
+    /// mismatch. Seeking past the end is allowed, and verifies the last chunk.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let content_len = self.read_header()?;
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => (n, 0),
+            SeekFrom::Current(n) => (self.position, n),
+            SeekFrom::End(n) => (content_len, n),
+        };
+        let new_position = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        let new_position = new_position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        let target_chunk = cmp::min(
+            new_position / CHUNK_SIZE as u64,
+            count_chunks(content_len) - 1,
+        );
+        if self.buf_chunk != Some(target_chunk) {
+            // Restart from the root, skipping over every subtree before the target.
+            self.inner.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+            self.reset_to_root();
+            self.load_chunk(target_chunk, |inner, len| {
+                inner.seek(SeekFrom::Current(len as i64)).map(|_| ())
+            })?;
+        }
+        self.position = new_position;
+        Ok(new_position)
+    }
+}
+
+impl<R> fmt::Debug for Decoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Decoder {{ content_len: {:?}, position: {} }}",
+            self.content_len, self.position,
+        )
+    }
+}
+
+/// Extract the parts of an encoding needed to verify `slice_len` bytes of content starting at
+/// `slice_start`, and return them as a slice encoding for [`SliceDecoder`].
+///
+/// The slice holds the header, the parent nodes on the paths to the chunks that overlap the range,
+/// and those chunks, in the same pre-order layout as the full encoding. This doesn't verify
+/// anything. That's up to the recipient. If the range goes past the end of the content, it's cut
+/// short. The slice always includes at least one chunk, so that the recipient can verify the
+/// content length.
+///
+/// [`SliceDecoder`]: struct.SliceDecoder.html
+pub fn extract_slice<R: Read + Seek>(
+    mut encoded: R,
+    slice_start: u64,
+    slice_len: u64,
+) -> io::Result<Vec<u8>> {
+    let mut header = [0; HEADER_SIZE];
+    encoded.seek(SeekFrom::Start(0))?;
+    encoded.read_exact(&mut header)?;
+    let content_len = LittleEndian::read_u64(&header);
+    let (first, last) = slice_chunks(content_len, slice_start, slice_len);
+    let mut slice = header.to_vec();
+    // The same traversal as the decoder, without the hashes.
+    let mut stack = vec![(0, count_chunks(content_len))];
+    while let Some((first_chunk, chunks)) = stack.pop() {
+        let subtree = Subtree {
+            hash: child_hash(&[0; HASH_SIZE]),
+            first_chunk,
+            chunks,
+            is_root: false,
+        };
+        if first_chunk + chunks <= first || first_chunk > last {
+            encoded.seek(SeekFrom::Current(subtree.encoded_len(content_len) as i64))?;
+            continue;
+        }
+        let node_len = if chunks == 1 {
+            subtree.content_len(content_len) as usize
+        } else {
+            PARENT_SIZE
+        };
+        let start = slice.len();
+        slice.resize(start + node_len, 0);
+        encoded.read_exact(&mut slice[start..])?;
+        if chunks > 1 {
+            let left = left_chunks(chunks);
+            stack.push((first_chunk + left, chunks - left));
+            stack.push((first_chunk, left));
+        }
+    }
+    Ok(slice)
+}
+
+/// A reader that decodes and verifies a slice produced by [`extract_slice`], returning the
+/// content in the slice's range.
+///
+/// The `slice_start` and `slice_len` must be the same as the ones given to `extract_slice`. Like
+/// [`Decoder`], this returns an `InvalidData` error for any chunk or parent node that fails to
+/// verify.
+///
+/// # Example
+///
+/// ```
+/// use blake2b_simd::verified;
+/// use std::io::{prelude::*, Cursor};
+///
+/// let input = vec![0xab; 100_000];
+/// let (hash, encoded) = verified::encode(&input);
+/// let slice = verified::extract_slice(Cursor::new(&encoded), 50_000, 1000)?;
+/// assert!(slice.len() < encoded.len() / 10);
+///
+/// let mut decoded = Vec::new();
+/// let mut decoder = verified::SliceDecoder::new(&slice[..], &hash, 50_000, 1000);
+/// decoder.read_to_end(&mut decoded)?;
+/// assert_eq!(&input[50_000..51_000], &decoded[..]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// [`extract_slice`]: fn.extract_slice.html
+/// [`Decoder`]: struct.Decoder.html
+pub struct SliceDecoder<R> {
+    decoder: Decoder<R>,
+    slice_start: u64,
+    slice_len: u64,
+    started: bool,
+}
+
+impl<R: Read> SliceDecoder<R> {
+    /// Create a decoder for a slice of an encoding with the given root hash.
+    pub fn new(inner: R, root_hash: &Hash, slice_start: u64, slice_len: u64) -> Self {
+        Self {
+            decoder: Decoder::new(inner, root_hash),
+            slice_start,
+            slice_len,
+            started: false,
+        }
+    }
+
+    /// Return the inner reader.
+    pub fn into_inner(self) -> R {
+        self.decoder.into_inner()
+    }
+}
+
+impl<R: Read> Read for SliceDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let content_len = self.decoder.read_header()?;
+        if !self.started {
+            // Subtrees before the slice aren't in the encoding at all.
+            let (first, _) = slice_chunks(content_len, self.slice_start, self.slice_len);
+            self.decoder.load_chunk(first, |_, _| Ok(()))?;
+            self.decoder.position = cmp::min(self.slice_start, content_len);
+            self.started = true;
+        }
+        let end = cmp::min(self.slice_start.saturating_add(self.slice_len), content_len);
+        self.decoder.read_inner(buf, end, |_, _| Ok(()))
+    }
+}
+
+impl<R> fmt::Debug for SliceDecoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SliceDecoder {{ slice_start: {}, slice_len: {}, decoder: {:?} }}",
+            self.slice_start, self.slice_len, self.decoder,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blake2bp::test::paint_input;
+    use std::io::Cursor;
+
+    const LENGTHS: &[usize] = &[
+        0,
+        1,
+        CHUNK_SIZE - 1,
+        CHUNK_SIZE,
+        CHUNK_SIZE + 1,
+        2 * CHUNK_SIZE,
+        3 * CHUNK_SIZE + 5,
+        8 * CHUNK_SIZE,
+        9 * CHUNK_SIZE + 1,
+    ];
+
+    fn make_input(len: usize) -> Vec<u8> {
+        let mut input = vec![0; len];
+        paint_input(&mut input);
+        input
+    }
+
+    #[test]
+    fn test_single_chunk_format() {
+        let input = b"foo";
+        let expected = Params::new()
+            .hash_length(32)
+            .fanout(2)
+            .max_depth(255)
+            .max_leaf_length(4096)
+            .inner_hash_length(32)
+            .last_node(true)
+            .to_state()
+            .update(input)
+            .update(&[3, 0, 0, 0, 0, 0, 0, 0])
+            .finalize();
+        let (hash, encoded) = encode(input);
+        assert_eq!(expected, hash);
+        assert_eq!(b"\x03\0\0\0\0\0\0\0foo", &encoded[..]);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        for &len in LENGTHS {
+            let input = make_input(len);
+            let (hash, encoded) = encode(&input);
+            assert_eq!(super::hash(&input), hash);
+            assert_eq!(encoded_size(len as u64), encoded.len() as u64);
+
+            // Decode with small, uneven reads.
+            let mut decoder = Decoder::new(&encoded[..], &hash);
+            let mut output = Vec::new();
+            let mut buf = [0; 1000];
+            loop {
+                let n = decoder.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                output.extend_from_slice(&buf[..n]);
+            }
+            assert_eq!(input, output);
+
+            // The wrong hash fails, even for empty input.
+            let wrong_hash = super::hash(b"wrong");
+            let mut decoder = Decoder::new(&encoded[..], &wrong_hash);
+            let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        }
+    }
+
+    #[test]
+    fn test_encode_to() {
+        for &len in LENGTHS {
+            let input = make_input(len);
+            let (hash, encoded) = encode(&input);
+            // Start the output at a nonzero position, to check that parents are placed relative
+            // to it.
+            let mut output = Cursor::new(b"prefix".to_vec());
+            output.seek(SeekFrom::End(0)).unwrap();
+            let mut reader = Cursor::new(&input);
+            reader.seek(SeekFrom::End(0)).unwrap();
+            assert_eq!(hash, encode_to(reader, &mut output).unwrap());
+            let output = output.into_inner();
+            assert_eq!(b"prefix", &output[..6]);
+            assert_eq!(encoded, &output[6..]);
+        }
+    }
+
+    #[test]
+    fn test_corruption() {
+        for &len in LENGTHS {
+            let input = make_input(len);
+            let (hash, encoded) = encode(&input);
+            // Flip a bit in the header, in parent nodes, and in the chunks.
+            for &offset in &[0, 7, 8, 9, 72, 100, encoded.len() / 2, encoded.len() - 1] {
+                if offset >= encoded.len() {
+                    continue;
+                }
+                let mut corrupt = encoded.clone();
+                corrupt[offset] ^= 1;
+                let mut decoder = Decoder::new(&corrupt[..], &hash);
+                assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_at_the_bad_chunk() {
+        let input = make_input(5 * CHUNK_SIZE);
+        let (hash, mut encoded) = encode(&input);
+        // Corrupt the last byte, in the last chunk. The first four chunks still come out.
+        let last = encoded.len() - 1;
+        encoded[last] ^= 1;
+        let mut decoder = Decoder::new(&encoded[..], &hash);
+        let mut output = vec![0; 4 * CHUNK_SIZE];
+        decoder.read_exact(&mut output).unwrap();
+        assert_eq!(&input[..4 * CHUNK_SIZE], &output[..]);
+        let err = decoder.read(&mut [0; 1]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_seek() {
+        for &len in LENGTHS {
+            let input = make_input(len);
+            let (hash, encoded) = encode(&input);
+            let mut decoder = Decoder::new(Cursor::new(&encoded), &hash);
+            for &position in &[
+                len / 2,
+                0,
+                len,
+                len + 10,
+                len / 3,
+                CHUNK_SIZE,
+                1,
+                len / 2 + 1,
+            ] {
+                assert_eq!(
+                    position as u64,
+                    decoder.seek(SeekFrom::Start(position as u64)).unwrap()
+                );
+                let mut output = Vec::new();
+                decoder.read_to_end(&mut output).unwrap();
+                let expected = &input[cmp::min(position, len)..];
+                assert_eq!(expected, &output[..]);
+            }
+            assert_eq!(len as u64, decoder.seek(SeekFrom::End(0)).unwrap());
+            assert!(decoder.seek(SeekFrom::Current(-(len as i64) - 1)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_seek_verifies() {
+        let input = make_input(4 * CHUNK_SIZE);
+        let (hash, mut encoded) = encode(&input);
+        // Corrupt the third chunk.
+        let third_chunk = HEADER_SIZE + 3 * PARENT_SIZE + 2 * CHUNK_SIZE;
+        encoded[third_chunk] ^= 1;
+        let mut decoder = Decoder::new(Cursor::new(&encoded), &hash);
+        decoder
+            .seek(SeekFrom::Start(3 * CHUNK_SIZE as u64))
+            .unwrap();
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(&input[3 * CHUNK_SIZE..], &output[..]);
+        assert!(decoder
+            .seek(SeekFrom::Start(2 * CHUNK_SIZE as u64))
+            .is_err());
+    }
+
+    #[test]
+    fn test_slices() {
+        for &len in LENGTHS {
+            let input = make_input(len);
+            let (hash, encoded) = encode(&input);
+            for &(start, slice_len) in &[
+                (0, 0),
+                (0, len),
+                (1, 1),
+                (len / 2, CHUNK_SIZE),
+                (CHUNK_SIZE - 1, 2),
+                (len, 10),
+                (len + 100, 10),
+                (3 * CHUNK_SIZE, 100 * CHUNK_SIZE),
+            ] {
+                let slice =
+                    extract_slice(Cursor::new(&encoded), start as u64, slice_len as u64).unwrap();
+                let mut decoder =
+                    SliceDecoder::new(&slice[..], &hash, start as u64, slice_len as u64);
+                let mut output = Vec::new();
+                decoder.read_to_end(&mut output).unwrap();
+                let expected_start = cmp::min(start, len);
+                let expected_end = cmp::min(start + slice_len, len);
+                assert_eq!(&input[expected_start..expected_end], &output[..]);
+
+                // Corrupting the last byte of the slice fails.
+                let mut corrupt = slice.clone();
+                let last = corrupt.len() - 1;
+                corrupt[last] ^= 1;
+                let mut decoder =
+                    SliceDecoder::new(&corrupt[..], &hash, start as u64, slice_len as u64);
+                assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_slice_is_small() {
+        let input = make_input(100 * CHUNK_SIZE);
+        let (hash, encoded) = encode(&input);
+        let slice = extract_slice(Cursor::new(&encoded), 50 * CHUNK_SIZE as u64, 10).unwrap();
+        // One chunk and seven parents, for a tree of 100 chunks.
+        assert_eq!(HEADER_SIZE + 7 * PARENT_SIZE + CHUNK_SIZE, slice.len());
+        let mut decoder = SliceDecoder::new(&slice[..], &hash, 50 * CHUNK_SIZE as u64, 10);
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(&input[50 * CHUNK_SIZE..][..10], &output[..]);
+    }
+}
@@ -0,0 +1,95 @@
+//! The tests in this file run the conformance checks from the `dev` modules of the `digest` and
+//! `crypto-mac` crates, with the BLAKE2b vectors from the RustCrypto `blake2` crate:
+//! https://github.com/RustCrypto/hashes/tree/blake2-v0.8.1/blake2/tests/data/blake2b
+//!
+//! `fixed.blb` covers the 64-byte `Digest`, `variable.blb` covers `VariableOutput`, and `mac.blb`
+//! covers keyed BLAKE2b through `Mac`.
+
+#![cfg(feature = "digest")]
+
+extern crate blake2b_simd;
+extern crate crypto_mac;
+extern crate digest_crate as digest;
+
+use blake2b_simd::digest::{Blake2b256, Blake2b512, Blake2bMac};
+use blake2b_simd::{blake2bp, Params, State};
+use digest::dev::{digest_test, variable_test};
+use digest::{Input, Reset, VariableOutput};
+use std::fmt;
+
+// `variable_test` needs `Reset`, which `State` and `blake2bp::State` don't implement, because they
+// don't keep a copy of their parameters. `VariableOutput::new` is always unkeyed though, so this
+// wrapper resets by building a new state with the same output size.
+#[derive(Clone)]
+struct Resettable<S>(S);
+
+impl<S: Input> Input for Resettable<S> {
+    fn input<B: AsRef<[u8]>>(&mut self, data: B) {
+        self.0.input(data);
+    }
+}
+
+impl<S: VariableOutput> VariableOutput for Resettable<S> {
+    fn new(output_size: usize) -> Result<Self, digest::InvalidOutputSize> {
+        S::new(output_size).map(Resettable)
+    }
+
+    fn output_size(&self) -> usize {
+        self.0.output_size()
+    }
+
+    fn variable_result<F: FnOnce(&[u8])>(self, f: F) {
+        self.0.variable_result(f);
+    }
+}
+
+impl<S: VariableOutput> Reset for Resettable<S> {
+    fn reset(&mut self) {
+        self.0 = S::new(self.0.output_size()).unwrap();
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Resettable<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+digest::new_test!(blake2b_fixed, "blake2b/fixed", Blake2b512, digest_test);
+digest::new_test!(
+    blake2b_variable,
+    "blake2b/variable",
+    Resettable<State>,
+    variable_test
+);
+crypto_mac::new_test!(blake2b_mac, "blake2b/mac", Blake2bMac);
+
+// The vectors above don't cover the 32-byte digest or BLAKE2bp, so run the same checks on those,
+// against the inherent API.
+#[test]
+fn test_other_outputs() {
+    let mut input = [0; 1000];
+    for (i, byte) in input.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    for &length in &[0, 1, 127, 128, 129, 255, 256, 257, 1000] {
+        let input = &input[..length];
+        let expected = Params::new()
+            .hash_length(32)
+            .to_state()
+            .update(input)
+            .finalize();
+        assert_eq!(None, digest_test::<Blake2b256>(input, expected.as_bytes()));
+        for &hash_length in &[1, 20, 32, 63, 64] {
+            let expected = blake2bp::Params::new()
+                .hash_length(hash_length)
+                .to_state()
+                .update(input)
+                .finalize();
+            assert_eq!(
+                None,
+                variable_test::<Resettable<blake2bp::State>>(input, expected.as_bytes())
+            );
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! The tests in this file run the Equihash vectors from `test_equihash.cpp` in zcashd, as ported
+//! to the `equihash` crate in librustzcash:
+//! https://github.com/zcash/librustzcash/tree/master/components/equihash/src/test_vectors
+//!
+//! The vectors give solutions as lists of indices, and the nonce as 32 little-endian bytes. They
+//! cover `n = 96, k = 5`, Zcash's `n = 200, k = 9`, and `n = 144, k = 5`. The invalid solutions
+//! are all modifications of the same valid `n = 96, k = 5` solution.
+
+#![cfg(feature = "std")]
+
+extern crate blake2b_simd;
+extern crate hex;
+#[macro_use]
+extern crate lazy_static;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use blake2b_simd::equihash;
+
+lazy_static! {
+    static ref TEST_CASES: TestCases =
+        serde_json::from_str(include_str!("equihash-vectors.json")).unwrap();
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TestCases {
+    valid: Vec<ValidCase>,
+    invalid: Vec<InvalidCase>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ValidCase {
+    n: u32,
+    k: u32,
+    header: String,
+    nonce: String,
+    solutions: Vec<Vec<u32>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InvalidCase {
+    n: u32,
+    k: u32,
+    header: String,
+    nonce: String,
+    solution: Vec<u32>,
+    error: String,
+}
+
+// Pack the indices into the minimal encoding, n / (k + 1) + 1 bits each, big-endian.
+fn minimal_from_indices(n: u32, k: u32, indices: &[u32]) -> Vec<u8> {
+    let index_bits = n / (k + 1) + 1;
+    let mut solution = Vec::new();
+    let mut accumulator = 0u64;
+    let mut accumulator_bits = 0;
+    for &index in indices {
+        accumulator = (accumulator << index_bits) | index as u64;
+        accumulator_bits += index_bits;
+        while accumulator_bits >= 8 {
+            accumulator_bits -= 8;
+            solution.push((accumulator >> accumulator_bits) as u8);
+        }
+    }
+    solution
+}
+
+#[test]
+fn valid_vectors() {
+    let mut count = 0;
+    for case in &TEST_CASES.valid {
+        println!(
+            "n {}, k {}, header {:?}, nonce {}",
+            case.n, case.k, case.header, case.nonce
+        );
+        let nonce = hex::decode(&case.nonce).unwrap();
+        for indices in &case.solutions {
+            let solution = minimal_from_indices(case.n, case.k, indices);
+            assert_eq!(
+                Ok(()),
+                equihash::verify_solution(
+                    case.n,
+                    case.k,
+                    case.header.as_bytes(),
+                    &nonce,
+                    &solution
+                )
+            );
+            count += 1;
+        }
+    }
+    assert_eq!(46, count);
+}
+
+#[test]
+fn invalid_vectors() {
+    for case in &TEST_CASES.invalid {
+        println!("solution {:?}", case.solution);
+        let nonce = hex::decode(&case.nonce).unwrap();
+        let solution = minimal_from_indices(case.n, case.k, &case.solution);
+        let error =
+            equihash::verify_solution(case.n, case.k, case.header.as_bytes(), &nonce, &solution)
+                .unwrap_err();
+        assert_eq!(case.error, format!("{:?}", error));
+    }
+    assert_eq!(9, TEST_CASES.invalid.len());
+}
@@ -0,0 +1,109 @@
+//! The tests in this file run HMAC-BLAKE2b, HKDF-BLAKE2b, and Noise HKDF vectors from
+//! `hmac-vectors.json`. These supplement the published Noise vectors in `noise_tests.rs`, which
+//! only check the chaining key and hash outputs of `hkdf2` and `hkdf3`. The outputs here were
+//! generated with Python's `hmac` and `hashlib` modules. The HMAC inputs are the ones from RFC
+//! 4231, plus a few block-size edge cases. The HKDF inputs are the ones from RFC 5869, plus a long
+//! output and an empty one. The Noise outputs follow the `HKDF` function in section 4.3 of the
+//! Noise spec, with `HASHLEN` 64, and they cover input lengths that the handshakes don't.
+
+extern crate blake2b_simd;
+extern crate hex;
+#[macro_use]
+extern crate lazy_static;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use blake2b_simd::hmac;
+
+lazy_static! {
+    static ref TEST_CASES: TestCases =
+        serde_json::from_str(include_str!("hmac-vectors.json")).unwrap();
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TestCases {
+    hmac: Vec<HmacCase>,
+    hkdf: Vec<HkdfCase>,
+    noise_hkdf: Vec<NoiseHkdfCase>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HmacCase {
+    key: String,
+    data: String,
+    out: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HkdfCase {
+    salt: String,
+    ikm: String,
+    info: String,
+    prk: String,
+    okm: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NoiseHkdfCase {
+    chaining_key: String,
+    input_key_material: String,
+    output1: String,
+    output2: String,
+    output3: String,
+}
+
+#[test]
+fn hmac_vectors() {
+    for case in &TEST_CASES.hmac {
+        println!("key {:?}, data {:?}", case.key, case.data);
+        let key = hex::decode(&case.key).unwrap();
+        let data = hex::decode(&case.data).unwrap();
+        assert_eq!(case.out, &*hmac::hmac(&key, &data).to_hex());
+    }
+    assert_eq!(9, TEST_CASES.hmac.len());
+}
+
+#[test]
+fn hkdf_vectors() {
+    for case in &TEST_CASES.hkdf {
+        println!(
+            "salt {:?}, ikm {:?}, info {:?}",
+            case.salt, case.ikm, case.info
+        );
+        let salt = hex::decode(&case.salt).unwrap();
+        let ikm = hex::decode(&case.ikm).unwrap();
+        let info = hex::decode(&case.info).unwrap();
+        let expected = hex::decode(&case.okm).unwrap();
+        let prk = hmac::hkdf_extract(&salt, &ikm);
+        assert_eq!(case.prk, &*prk.to_hex());
+        let mut okm = vec![0; expected.len()];
+        hmac::hkdf_expand(prk.as_bytes(), &info, &mut okm);
+        assert_eq!(expected, okm);
+        let mut okm = vec![0; expected.len()];
+        hmac::hkdf(&salt, &ikm, &info, &mut okm);
+        assert_eq!(expected, okm);
+    }
+    assert_eq!(5, TEST_CASES.hkdf.len());
+}
+
+#[test]
+fn noise_hkdf_vectors() {
+    for case in &TEST_CASES.noise_hkdf {
+        println!(
+            "chaining key {:?}, ikm {:?}",
+            case.chaining_key, case.input_key_material
+        );
+        let chaining_key = hex::decode(&case.chaining_key).unwrap();
+        let ikm = hex::decode(&case.input_key_material).unwrap();
+        let [out1, out2] = hmac::hkdf2(&chaining_key, &ikm);
+        assert_eq!(case.output1, &*out1.to_hex());
+        assert_eq!(case.output2, &*out2.to_hex());
+        let [out1, out2, out3] = hmac::hkdf3(&chaining_key, &ikm);
+        assert_eq!(case.output1, &*out1.to_hex());
+        assert_eq!(case.output2, &*out2.to_hex());
+        assert_eq!(case.output3, &*out3.to_hex());
+    }
+    assert_eq!(3, TEST_CASES.noise_hkdf.len());
+}
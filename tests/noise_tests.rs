@@ -0,0 +1,239 @@
+//! The tests in this file run the BLAKE2b handshakes from the Noise test vectors published by
+//! cacophony, as distributed in `snow/tests/vectors`:
+//! https://github.com/mcginty/snow/tree/master/tests/vectors
+//!
+//! `noise-vectors.json` holds the vectors that use Curve25519, ChaChaPoly, and BLAKE2b, with the
+//! one-way and interactive fundamental patterns and their `psk` modifiers. Each handshake is
+//! replayed over the published message bytes, with `MixKey` and `MixKeyAndHash` feeding
+//! `hmac::hkdf2` and `hmac::hkdf3`, and the result is checked against the published
+//! `handshake_hash`. The ciphertexts are taken from the transcript rather than recomputed, so the
+//! chaining key only reaches the hash through `MixKeyAndHash`. That makes the `psk` vectors the
+//! ones that check the key schedule: each `psk` token mixes in a hash derived from every `MixKey`
+//! before it.
+//!
+//! The vectors give private keys, so to avoid pulling in X25519 here, each vector also has a
+//! `dh_outputs` field that isn't part of the published files. It holds the shared secret for each
+//! `ee`, `es`, `se`, and `ss` token in order, computed from the vector's keys with the X25519 in
+//! Python's `cryptography` package.
+
+extern crate blake2b_simd;
+extern crate hex;
+#[macro_use]
+extern crate lazy_static;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use blake2b_simd::{blake2b, hmac, State};
+
+lazy_static! {
+    static ref TEST_CASES: TestCases =
+        serde_json::from_str(include_str!("noise-vectors.json")).unwrap();
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TestCases {
+    cacophony: Vec<NoiseCase>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NoiseCase {
+    protocol_name: String,
+    init_prologue: String,
+    #[serde(default)]
+    init_psks: Vec<String>,
+    // The responder's static public key, known to the initiator ahead of time.
+    init_remote_static: Option<String>,
+    // The initiator's static public key, known to the responder ahead of time.
+    resp_remote_static: Option<String>,
+    handshake_hash: String,
+    messages: Vec<Message>,
+    dh_outputs: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Message {
+    payload: String,
+    ciphertext: String,
+}
+
+struct Pattern {
+    initiator_premessage: bool,
+    responder_premessage: bool,
+    messages: Vec<Vec<&'static str>>,
+}
+
+// The fundamental patterns from section 7 of the Noise spec. Messages alternate between the
+// initiator and the responder, starting with the initiator.
+fn fundamental_pattern(name: &str) -> Pattern {
+    let (initiator_premessage, responder_premessage, messages): (_, _, &[&[_]]) = match name {
+        "N" => (false, true, &[&["e", "es"]]),
+        "K" => (true, true, &[&["e", "es", "ss"]]),
+        "X" => (false, true, &[&["e", "es", "s", "ss"]]),
+        "NN" => (false, false, &[&["e"], &["e", "ee"]]),
+        "NK" => (false, true, &[&["e", "es"], &["e", "ee"]]),
+        "NX" => (false, false, &[&["e"], &["e", "ee", "s", "es"]]),
+        "KN" => (true, false, &[&["e"], &["e", "ee", "se"]]),
+        "KK" => (true, true, &[&["e", "es", "ss"], &["e", "ee", "se"]]),
+        "KX" => (true, false, &[&["e"], &["e", "ee", "se", "s", "es"]]),
+        "XN" => (false, false, &[&["e"], &["e", "ee"], &["s", "se"]]),
+        "XK" => (false, true, &[&["e", "es"], &["e", "ee"], &["s", "se"]]),
+        "XX" => (
+            false,
+            false,
+            &[&["e"], &["e", "ee", "s", "es"], &["s", "se"]],
+        ),
+        "IN" => (false, false, &[&["e", "s"], &["e", "ee", "se"]]),
+        "IK" => (false, true, &[&["e", "es", "s", "ss"], &["e", "ee", "se"]]),
+        "IX" => (false, false, &[&["e", "s"], &["e", "ee", "se", "s", "es"]]),
+        _ => panic!("unknown pattern {}", name),
+    };
+    Pattern {
+        initiator_premessage,
+        responder_premessage,
+        messages: messages.iter().map(|m| m.to_vec()).collect(),
+    }
+}
+
+// Parse a name like "XXpsk0+psk2". A psk0 modifier goes at the start of the first message, and
+// pskN goes at the end of message N.
+fn pattern(name: &str) -> Pattern {
+    let (base, modifiers) = match name.find("psk") {
+        Some(i) => (&name[..i], &name[i..]),
+        None => (name, ""),
+    };
+    let mut pattern = fundamental_pattern(base);
+    for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+        let position: usize = modifier["psk".len()..].parse().unwrap();
+        if position == 0 {
+            pattern.messages[0].insert(0, "psk");
+        } else {
+            pattern.messages[position - 1].push("psk");
+        }
+    }
+    pattern
+}
+
+// The parts of the SymmetricState object from section 5.2 of the Noise spec, with HASHLEN 64,
+// that affect the handshake hash. Encryption only matters for how long a ciphertext is, so the
+// key itself isn't kept.
+struct SymmetricState {
+    chaining_key: Vec<u8>,
+    hash: Vec<u8>,
+    has_key: bool,
+}
+
+impl SymmetricState {
+    fn new(protocol_name: &str) -> Self {
+        let hash = if protocol_name.len() <= 64 {
+            let mut padded = protocol_name.as_bytes().to_vec();
+            padded.resize(64, 0);
+            padded
+        } else {
+            blake2b(protocol_name.as_bytes()).as_bytes().to_vec()
+        };
+        Self {
+            chaining_key: hash.clone(),
+            hash,
+            has_key: false,
+        }
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let hash = State::new().update(&self.hash).update(data).finalize();
+        self.hash = hash.as_bytes().to_vec();
+    }
+
+    fn mix_key(&mut self, input_key_material: &[u8]) {
+        let [chaining_key, _key] = hmac::hkdf2(&self.chaining_key, input_key_material);
+        self.chaining_key = chaining_key.as_bytes().to_vec();
+        self.has_key = true;
+    }
+
+    fn mix_key_and_hash(&mut self, input_key_material: &[u8]) {
+        let [chaining_key, hash, _key] = hmac::hkdf3(&self.chaining_key, input_key_material);
+        self.chaining_key = chaining_key.as_bytes().to_vec();
+        self.mix_hash(hash.as_bytes());
+        self.has_key = true;
+    }
+
+    // The length of the ciphertext for a plaintext of the given length, with the 16-byte
+    // ChaChaPoly tag once a key is set.
+    fn ciphertext_len(&self, plaintext_len: usize) -> usize {
+        if self.has_key {
+            plaintext_len + 16
+        } else {
+            plaintext_len
+        }
+    }
+}
+
+// Split each handshake message into its tokens and mix them in the way both parties do. Only
+// the handshake messages are needed, so any transport messages after them are ignored.
+fn check_case(case: &NoiseCase) {
+    println!("{}", case.protocol_name);
+    let name = &case.protocol_name["Noise_".len()..];
+    let pattern = pattern(&name[..name.find('_').unwrap()]);
+    let decode = |hex: &Option<String>| hex::decode(hex.as_ref().unwrap()).unwrap();
+    let mut psks = case.init_psks.iter().map(|psk| hex::decode(psk).unwrap());
+    let mut dh_outputs = case.dh_outputs.iter().map(|dh| hex::decode(dh).unwrap());
+    let has_psk = !case.init_psks.is_empty();
+
+    let mut state = SymmetricState::new(&case.protocol_name);
+    state.mix_hash(&hex::decode(&case.init_prologue).unwrap());
+    if pattern.initiator_premessage {
+        state.mix_hash(&decode(&case.resp_remote_static));
+    }
+    if pattern.responder_premessage {
+        state.mix_hash(&decode(&case.init_remote_static));
+    }
+
+    for (i, tokens) in pattern.messages.iter().enumerate() {
+        let vector = &case.messages[i];
+        let message = hex::decode(&vector.ciphertext).unwrap();
+        let mut rest = &message[..];
+        for &token in tokens {
+            match token {
+                "e" => {
+                    let (public, after) = rest.split_at(32);
+                    state.mix_hash(public);
+                    if has_psk {
+                        state.mix_key(public);
+                    }
+                    rest = after;
+                }
+                "s" => {
+                    let (public, after) = rest.split_at(state.ciphertext_len(32));
+                    state.mix_hash(public);
+                    rest = after;
+                }
+                "ee" | "es" | "se" | "ss" => state.mix_key(&dh_outputs.next().unwrap()),
+                "psk" => state.mix_key_and_hash(&psks.next().unwrap()),
+                _ => unreachable!(),
+            }
+        }
+        let payload = hex::decode(&vector.payload).unwrap();
+        assert_eq!(
+            state.ciphertext_len(payload.len()),
+            rest.len(),
+            "message {}",
+            i
+        );
+        if !state.has_key {
+            assert_eq!(payload, rest, "message {}", i);
+        }
+        state.mix_hash(rest);
+    }
+    assert!(dh_outputs.next().is_none());
+    assert!(psks.next().is_none());
+    assert_eq!(case.handshake_hash, hex::encode(&state.hash));
+}
+
+#[test]
+fn cacophony_vectors() {
+    for case in &TEST_CASES.cacophony {
+        check_case(case);
+    }
+    assert_eq!(36, TEST_CASES.cacophony.len());
+}
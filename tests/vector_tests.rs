@@ -1,9 +1,9 @@
 //! The tests in this file run the standard set of test vectors from upstream:
 //! https://github.com/BLAKE2/BLAKE2/blob/320c325437539ae91091ce62efec1913cd8093c2/testvectors/blake2-kat.json
 //!
-//! Currently those cover default hashing and keyed hashing in BLAKE2b and BLAKE2bp. But they don't
-//! test the other associated data features, and they don't test any inputs longer than a couple
-//! blocks.
+//! Currently those cover default hashing and keyed hashing in BLAKE2b, BLAKE2bp, BLAKE2s,
+//! BLAKE2sp, and BLAKE2Xb. But they don't test the other associated data features, and they don't
+//! test any inputs longer than a couple blocks.
 
 extern crate blake2b_simd;
 extern crate hex;
@@ -81,3 +81,89 @@ fn blake2bp_vectors() {
     // changes in the future, we'll need to update this count.
     assert_eq!(512, count);
 }
+
+#[test]
+fn blake2s_vectors() {
+    let mut count = 0u64;
+    for case in TEST_CASES.iter() {
+        if &case.hash == "blake2s" {
+            println!("case {}, input {:?}, key {:?}", count, case.in_, case.key);
+            let input_bytes = hex::decode(&case.in_).unwrap();
+            let output = if case.key.is_empty() {
+                blake2b_simd::blake2s::blake2s(&input_bytes)
+            } else {
+                let key_bytes = hex::decode(&case.key).unwrap();
+                blake2b_simd::blake2s::Params::new()
+                    .key(&key_bytes)
+                    .to_state()
+                    .update(&input_bytes)
+                    .finalize()
+            };
+            assert_eq!(case.out, &*output.to_hex());
+            count += 1;
+        }
+    }
+
+    // Make sure we don't accidentally skip all the tests somehow. If the number of test vectors
+    // changes in the future, we'll need to update this count.
+    assert_eq!(512, count);
+}
+
+#[test]
+fn blake2sp_vectors() {
+    let mut count = 0u64;
+    for case in TEST_CASES.iter() {
+        if &case.hash == "blake2sp" {
+            println!("case {}, input {:?}, key {:?}", count, case.in_, case.key);
+            let input_bytes = hex::decode(&case.in_).unwrap();
+            let output = if case.key.is_empty() {
+                blake2b_simd::blake2sp::blake2sp(&input_bytes)
+            } else {
+                let key_bytes = hex::decode(&case.key).unwrap();
+                blake2b_simd::blake2sp::Params::new()
+                    .key(&key_bytes)
+                    .to_state()
+                    .update(&input_bytes)
+                    .finalize()
+            };
+            assert_eq!(case.out, &*output.to_hex());
+            count += 1;
+        }
+    }
+
+    // Make sure we don't accidentally skip all the tests somehow. If the number of test vectors
+    // changes in the future, we'll need to update this count.
+    assert_eq!(512, count);
+}
+
+#[test]
+fn blake2xb_vectors() {
+    let mut count = 0u64;
+    for case in TEST_CASES.iter() {
+        if &case.hash == "blake2xb" {
+            println!("case {}, input {:?}, key {:?}", count, case.in_, case.key);
+            let input_bytes = hex::decode(&case.in_).unwrap();
+            let expected = hex::decode(&case.out).unwrap();
+            let mut output = vec![0; expected.len()];
+            let key_bytes = hex::decode(&case.key).unwrap();
+            blake2b_simd::blake2xb::Params::new()
+                .key(&key_bytes)
+                .output_length(output.len() as u32)
+                .to_state()
+                .update(&input_bytes)
+                .finalize()
+                .fill(&mut output);
+            assert_eq!(expected, output);
+            if key_bytes.is_empty() {
+                let mut output = vec![0; expected.len()];
+                blake2b_simd::blake2xb::blake2xb(&input_bytes, &mut output);
+                assert_eq!(expected, output);
+            }
+            count += 1;
+        }
+    }
+
+    // Make sure we don't accidentally skip all the tests somehow. If the number of test vectors
+    // changes in the future, we'll need to update this count.
+    assert_eq!(512, count);
+}